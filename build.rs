@@ -0,0 +1,11 @@
+extern crate mozaic_build;
+
+use std::env;
+
+fn main() {
+    let manifest = "schema/mozaic/messages.toml";
+    println!("cargo:rerun-if-changed={}", manifest);
+
+    let out = format!("{}/messages.rs", env::var("OUT_DIR").unwrap());
+    mozaic_build::compile_messages(manifest, &out).expect("Could not generate messages");
+}
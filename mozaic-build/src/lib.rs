@@ -1,39 +1,170 @@
-extern crate toml;
-extern crate prost_build;
 extern crate serde;
+extern crate toml;
 #[macro_use]
 extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+
+mod rust;
+mod schema;
+mod types;
 
-use std::fs::File;
-use std::collections::HashMap;
-use std::io::{Result, Read};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
 
+use types::Type;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// One message in the manifest, either a struct (fields) or an enum (variants)
+///
+/// [messages.PlayerMsg]
+/// fields = ["id: u64", "data: Option<Data>"]
+///
+/// [messages.HostMsg]
+/// variants = ["Data(Data, Option<u64>)", "Kick(u64)"]
+#[derive(Deserialize, Debug)]
+struct MessageDef {
+    doc: Option<String>,
+    key: Option<String>,
+    version: Option<u32>,
+    fields: Option<Vec<String>>,
+    variants: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
 struct MozaicProtocol {
-    events: HashMap<String, u32>
+    messages: BTreeMap<String, MessageDef>,
+}
+
+/// A parsed message, ready to be generated
+pub struct Message {
+    name: String,
+    doc: Option<String>,
+    key: Option<String>,
+    version: Option<u32>,
+    body: Body,
+}
+
+enum Body {
+    Struct(Vec<(String, Type)>),
+    Enum(Vec<(String, Vec<Type>)>),
+}
+
+fn invalid<E: ToString>(e: E) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+fn parse_field(field: &str) -> Result<(String, Type)> {
+    let colon = field
+        .find(':')
+        .ok_or_else(|| invalid(format!("expected 'name: type', found '{}'", field)))?;
+    let ty = types::parse(&field[colon + 1..]).map_err(invalid)?;
+    Ok((field[..colon].trim().to_string(), ty))
 }
 
-pub fn compile_events(manifest_path: &str, proto_path: &str, events_proto: &str) -> Result<()> {
-    let mut f = File::open(manifest_path).expect("file not found");
+fn parse_variant(variant: &str) -> Result<(String, Vec<Type>)> {
+    let variant = variant.trim();
+    match variant.find('(') {
+        Some(open) if variant.ends_with(')') => {
+            let ty = types::parse(&variant[open..]).map_err(invalid)?;
+            let items = match ty {
+                Type::Tuple(items) => items,
+                ty => vec![ty],
+            };
+            Ok((variant[..open].trim().to_string(), items))
+        }
+        Some(_) => Err(invalid(format!("unclosed variant '{}'", variant))),
+        None => Ok((variant.to_string(), Vec::new())),
+    }
+}
+
+/// Reads and checks the manifest, every named type has to be a message in it
+pub fn read_manifest(manifest_path: &str) -> Result<Vec<Message>> {
+    let mut f = File::open(manifest_path)?;
     let mut contents = String::new();
-    f.read_to_string(&mut contents).unwrap();
-    let protocol: MozaicProtocol = toml::from_str(&contents).unwrap();
+    f.read_to_string(&mut contents)?;
+    let protocol: MozaicProtocol = toml::from_str(&contents).map_err(invalid)?;
+
+    let mut messages = Vec::new();
+    for (name, def) in protocol.messages {
+        let body = match (def.fields, def.variants) {
+            (Some(fields), None) => Body::Struct(
+                fields
+                    .iter()
+                    .map(|f| parse_field(f))
+                    .collect::<Result<_>>()?,
+            ),
+            (None, Some(variants)) => Body::Enum(
+                variants
+                    .iter()
+                    .map(|v| parse_variant(v))
+                    .collect::<Result<_>>()?,
+            ),
+            _ => {
+                return Err(invalid(format!(
+                    "message {} needs either fields or variants",
+                    name
+                )))
+            }
+        };
+
+        messages.push(Message {
+            name,
+            doc: def.doc,
+            key: def.key,
+            version: def.version,
+            body,
+        });
+    }
+
+    for message in &messages {
+        for ty in message.types() {
+            for name in ty.names() {
+                if !messages.iter().any(|m| &m.name == name) {
+                    return Err(invalid(format!(
+                        "message {} uses unknown type {}",
+                        message.name, name
+                    )));
+                }
+            }
+        }
+    }
 
-    let mut config = prost_build::Config::new();
+    Ok(messages)
+}
 
-    for (event_name, event_id) in protocol.events.iter() {
-        config.type_attribute(event_name, "#[derive(MozaicEvent)]");
-        config.type_attribute(
-            event_name,
-            format!("#[mozaic_event(type_id=\"{}\")]", event_id)
-        );
+impl Message {
+    fn types(&self) -> Vec<&Type> {
+        match &self.body {
+            Body::Struct(fields) => fields.iter().map(|(_, ty)| ty).collect(),
+            Body::Enum(variants) => variants.iter().flat_map(|(_, tys)| tys).collect(),
+        }
     }
+}
 
+/// Generates Rust message types, with serde and Key derives, for include!
+///
+/// In a build script:
+/// mozaic_build::compile_messages("messages.toml", &format!("{}/messages.rs", out_dir))
+pub fn compile_messages(manifest_path: &str, out_path: &str) -> Result<()> {
+    let messages = read_manifest(manifest_path)?;
+    let mut f = File::create(out_path)?;
+    f.write_all(rust::generate(&messages).as_bytes())
+}
+
+/// Writes a JSON schema per message to schema_dir, for bot authors
+pub fn compile_schemas(manifest_path: &str, schema_dir: &str) -> Result<()> {
+    let messages = read_manifest(manifest_path)?;
+    fs::create_dir_all(schema_dir)?;
+
+    for message in &messages {
+        let path = Path::new(schema_dir).join(format!("{}.schema.json", message.name));
+        let schema = schema::generate(message, &messages);
+        let mut f = File::create(path)?;
+        f.write_all(serde_json::to_string_pretty(&schema)?.as_bytes())?;
+        f.write_all(b"\n")?;
+    }
 
-    config.compile_protos(
-        &[events_proto],
-        &[proto_path]
-    )?;
-    return Ok(());
+    Ok(())
 }
@@ -0,0 +1,19 @@
+extern crate mozaic_build;
+
+use std::env;
+use std::process;
+
+/// mozaic-build <manifest> <schema dir>
+/// Writes the JSON schemas for all messages in the manifest
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <manifest> <schema dir>", args[0]);
+        process::exit(1);
+    }
+
+    if let Err(e) = mozaic_build::compile_schemas(&args[1], &args[2]) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,54 @@
+use super::{Body, Message};
+
+fn attributes(message: &Message, out: &mut String) {
+    if let Some(doc) = &message.doc {
+        for line in doc.lines() {
+            out.push_str(&format!("/// {}\n", line));
+        }
+    }
+
+    out.push_str(
+        "#[derive(::serde::Serialize, ::serde::Deserialize, ::mozaic::Key, Clone, Debug)]\n",
+    );
+
+    if let Some(key) = &message.key {
+        out.push_str(&format!("#[key(name = {:?})]\n", key));
+    }
+    if let Some(version) = message.version {
+        out.push_str(&format!("#[key(version = {})]\n", version));
+    }
+}
+
+/// Generates the Rust source for all messages
+pub fn generate(messages: &[Message]) -> String {
+    let mut out = String::from("// Generated by mozaic-build, do not edit\n");
+
+    for message in messages {
+        out.push('\n');
+        attributes(message, &mut out);
+
+        match &message.body {
+            Body::Struct(fields) => {
+                out.push_str(&format!("pub struct {} {{\n", message.name));
+                for (name, ty) in fields {
+                    out.push_str(&format!("    pub {}: {},\n", name, ty));
+                }
+                out.push_str("}\n");
+            }
+            Body::Enum(variants) => {
+                out.push_str(&format!("pub enum {} {{\n", message.name));
+                for (name, tys) in variants {
+                    if tys.is_empty() {
+                        out.push_str(&format!("    {},\n", name));
+                    } else {
+                        let tys: Vec<String> = tys.iter().map(|t| t.to_string()).collect();
+                        out.push_str(&format!("    {}({}),\n", name, tys.join(", ")));
+                    }
+                }
+                out.push_str("}\n");
+            }
+        }
+    }
+
+    out
+}
@@ -0,0 +1,100 @@
+use super::{Body, Message};
+use types::Type;
+
+use serde_json::{Map, Value};
+
+fn ty(t: &Type) -> Value {
+    match t {
+        Type::Bool => json!({ "type": "boolean" }),
+        Type::Int { signed: true, .. } => json!({ "type": "integer" }),
+        Type::Int { signed: false, .. } => json!({ "type": "integer", "minimum": 0 }),
+        Type::Float => json!({ "type": "number" }),
+        Type::String => json!({ "type": "string" }),
+        Type::Value => json!({}),
+        Type::Option(t) => json!({ "anyOf": [ty(t), { "type": "null" }] }),
+        Type::Vec(t) => json!({ "type": "array", "items": ty(t) }),
+        Type::Map(t) => json!({ "type": "object", "additionalProperties": ty(t) }),
+        Type::Tuple(ts) => json!({
+            "type": "array",
+            "items": ts.iter().map(ty).collect::<Vec<_>>(),
+            "minItems": ts.len(),
+            "maxItems": ts.len(),
+        }),
+        Type::Named(name) => json!({ "$ref": format!("#/definitions/{}", name) }),
+    }
+}
+
+/// Serde's default, externally tagged, representation
+fn variant(name: &str, tys: &[Type]) -> Value {
+    let inner = match tys {
+        [] => return json!({ "const": name }),
+        [t] => ty(t),
+        ts => ty(&Type::Tuple(ts.to_vec())),
+    };
+
+    json!({
+        "type": "object",
+        "properties": { name: inner },
+        "required": [name],
+        "additionalProperties": false,
+    })
+}
+
+fn message_schema(message: &Message) -> Value {
+    let mut schema = match &message.body {
+        Body::Struct(fields) => {
+            let properties: Map<String, Value> = fields
+                .iter()
+                .map(|(name, t)| (name.clone(), ty(t)))
+                .collect();
+            let required: Vec<&String> = fields
+                .iter()
+                .filter(|(_, t)| !matches!(t, Type::Option(_)))
+                .map(|(name, _)| name)
+                .collect();
+
+            json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            })
+        }
+        Body::Enum(variants) => json!({
+            "oneOf": variants.iter().map(|(name, tys)| variant(name, tys)).collect::<Vec<_>>(),
+        }),
+    };
+
+    let object = schema.as_object_mut().unwrap();
+    object.insert("title".to_string(), json!(message.name));
+    if let Some(doc) = &message.doc {
+        object.insert("description".to_string(), json!(doc));
+    }
+    schema
+}
+
+/// The schema for one message, with the definitions of all messages it uses
+pub fn generate(message: &Message, messages: &[Message]) -> Value {
+    let mut definitions = Map::new();
+    let mut todo: Vec<&String> = message.types().into_iter().flat_map(|t| t.names()).collect();
+
+    while let Some(name) = todo.pop() {
+        if definitions.contains_key(name) {
+            continue;
+        }
+
+        let used = messages.iter().find(|m| &m.name == name).unwrap();
+        definitions.insert(name.clone(), message_schema(used));
+        todo.extend(used.types().into_iter().flat_map(|t| t.names()));
+    }
+
+    let mut schema = message_schema(message);
+    let object = schema.as_object_mut().unwrap();
+    object.insert(
+        "$schema".to_string(),
+        json!("http://json-schema.org/draft-07/schema#"),
+    );
+    if !definitions.is_empty() {
+        object.insert("definitions".to_string(), Value::Object(definitions));
+    }
+    schema
+}
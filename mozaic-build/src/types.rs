@@ -0,0 +1,165 @@
+use std::fmt;
+
+/// The types a message field can have
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Bool,
+    Int { signed: bool, bits: u8 },
+    Float,
+    String,
+    /// Any JSON value
+    Value,
+    Option(Box<Type>),
+    Vec(Box<Type>),
+    Map(Box<Type>),
+    Tuple(Vec<Type>),
+    /// Another message in the manifest
+    Named(String),
+}
+
+impl Type {
+    /// The messages this type refers to
+    pub fn names(&self) -> Vec<&String> {
+        match self {
+            Type::Option(t) | Type::Vec(t) | Type::Map(t) => t.names(),
+            Type::Tuple(ts) => ts.iter().flat_map(|t| t.names()).collect(),
+            Type::Named(name) => vec![name],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Bool => write!(f, "bool"),
+            Type::Int { signed, bits } => write!(f, "{}{}", if *signed { "i" } else { "u" }, bits),
+            Type::Float => write!(f, "f64"),
+            Type::String => write!(f, "String"),
+            Type::Value => write!(f, "::serde_json::Value"),
+            Type::Option(t) => write!(f, "Option<{}>", t),
+            Type::Vec(t) => write!(f, "Vec<{}>", t),
+            Type::Map(t) => write!(f, "::std::collections::HashMap<String, {}>", t),
+            Type::Tuple(ts) => {
+                write!(f, "(")?;
+                for (i, t) in ts.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", t)?;
+                }
+                write!(f, ")")
+            }
+            Type::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn eat(&mut self, token: char) -> bool {
+        self.input = self.input.trim_start();
+        if self.input.starts_with(token) {
+            self.input = &self.input[token.len_utf8()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: char) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at '{}'", token, self.input))
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'a str, String> {
+        self.input = self.input.trim_start();
+        let end = self
+            .input
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(self.input.len());
+        if end == 0 {
+            return Err(format!("expected a type at '{}'", self.input));
+        }
+        let (ident, rest) = self.input.split_at(end);
+        self.input = rest;
+        Ok(ident)
+    }
+
+    fn generic(&mut self) -> Result<Box<Type>, String> {
+        self.expect('<')?;
+        let inner = self.ty()?;
+        self.expect('>')?;
+        Ok(Box::new(inner))
+    }
+
+    fn ty(&mut self) -> Result<Type, String> {
+        if self.eat('(') {
+            let mut items = Vec::new();
+            while !self.eat(')') {
+                items.push(self.ty()?);
+                if !self.eat(',') {
+                    self.expect(')')?;
+                    break;
+                }
+            }
+            return Ok(Type::Tuple(items));
+        }
+
+        let ty = match self.ident()? {
+            "bool" => Type::Bool,
+            "u8" => Type::Int { signed: false, bits: 8 },
+            "u16" => Type::Int { signed: false, bits: 16 },
+            "u32" => Type::Int { signed: false, bits: 32 },
+            "u64" => Type::Int { signed: false, bits: 64 },
+            "i8" => Type::Int { signed: true, bits: 8 },
+            "i16" => Type::Int { signed: true, bits: 16 },
+            "i32" => Type::Int { signed: true, bits: 32 },
+            "i64" => Type::Int { signed: true, bits: 64 },
+            "f32" | "f64" => Type::Float,
+            "String" => Type::String,
+            "Value" => Type::Value,
+            "Option" => Type::Option(self.generic()?),
+            "Vec" => Type::Vec(self.generic()?),
+            "Map" => Type::Map(self.generic()?),
+            name => Type::Named(name.to_string()),
+        };
+        Ok(ty)
+    }
+}
+
+/// Parses a type like Option<(u64, Vec<Data>)>
+pub fn parse(input: &str) -> Result<Type, String> {
+    let mut parser = Parser { input };
+    let ty = parser.ty()?;
+    if !parser.input.trim().is_empty() {
+        return Err(format!("unexpected '{}'", parser.input.trim()));
+    }
+    Ok(ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_types() {
+        let ty = parse(" Option<(u64, Vec<Data>)>").unwrap();
+        assert_eq!(
+            ty,
+            Type::Option(Box::new(Type::Tuple(vec![
+                Type::Int { signed: false, bits: 64 },
+                Type::Vec(Box::new(Type::Named(String::from("Data")))),
+            ])))
+        );
+        assert_eq!(ty.to_string(), "Option<(u64, Vec<Data>)>");
+        assert!(parse("Vec<u8").is_err());
+        assert!(parse("u8 u8").is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use proc_macro2::TokenStream;
+use syn;
+
+/// Arguments given to #[handlers(...)]
+struct Args {
+    name: Option<String>,
+    key: syn::Type,
+    message: syn::Type,
+    params: syn::Ident,
+}
+
+impl Args {
+    fn parse(args: syn::AttributeArgs) -> syn::Result<Self> {
+        let mut out = Args {
+            name: None,
+            key: syn::parse_quote!(::std::any::TypeId),
+            message: syn::parse_quote!(::mozaic::generic::Message),
+            params: syn::Ident::new("params", proc_macro2::Span::call_site()),
+        };
+
+        for arg in args {
+            let name_value = match arg {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) => nv,
+                other => {
+                    return Err(syn::Error::new_spanned(
+                        other,
+                        "expected name = \"value\" arguments",
+                    ))
+                }
+            };
+
+            let lit_str = match &name_value.lit {
+                syn::Lit::Str(lit_str) => lit_str,
+                lit => return Err(syn::Error::new_spanned(lit, "expected a string literal")),
+            };
+
+            match name_value.path.get_ident().map(|i| i.to_string()).as_deref() {
+                Some("name") => out.name = Some(lit_str.value()),
+                Some("key") => out.key = lit_str.parse()?,
+                Some("message") => out.message = lit_str.parse()?,
+                Some("params") => out.params = lit_str.parse()?,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        &name_value.path,
+                        "unknown argument, expected name, key, message or params",
+                    ))
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Removes the attribute with this name, returns whether it was present
+fn take_attr(attrs: &mut Vec<syn::Attribute>, name: &str) -> bool {
+    let len = attrs.len();
+    attrs.retain(|attr| !super::path_equals(&attr.path, name));
+    len != attrs.len()
+}
+
+/// Handlers taking their message by value get it moved out of the message
+fn handler_type(method: &syn::ImplItemMethod) -> syn::Result<TokenStream> {
+    match method.sig.inputs.iter().nth(2) {
+        Some(syn::FnArg::Typed(arg)) if method.sig.inputs.len() == 3 => match *arg.ty {
+            syn::Type::Reference(_) => Ok(quote!(::mozaic::generic::FunctionHandler)),
+            _ => Ok(quote!(::mozaic::generic::OwnedHandler)),
+        },
+        _ => Err(syn::Error::new_spanned(
+            &method.sig,
+            "a #[handler] takes self, the reactor handle and the message",
+        )),
+    }
+}
+
+fn type_name(ty: &syn::Type) -> syn::Result<String> {
+    let segment = match ty {
+        syn::Type::Path(path) => path.path.segments.last(),
+        _ => None,
+    };
+
+    segment.map(|segment| segment.ident.to_string()).ok_or_else(|| {
+        syn::Error::new_spanned(ty, "#[handlers] only works on impl blocks of named types")
+    })
+}
+
+pub fn impl_handlers(args: syn::AttributeArgs, item: syn::ItemImpl) -> TokenStream {
+    expand(args, item).unwrap_or_else(|error| error.to_compile_error())
+}
+
+fn expand(args: syn::AttributeArgs, mut item: syn::ItemImpl) -> syn::Result<TokenStream> {
+    let args = Args::parse(args)?;
+
+    let mut handlers = Vec::new();
+    let mut init = None;
+
+    for impl_item in item.items.iter_mut() {
+        if let syn::ImplItem::Method(method) = impl_item {
+            if take_attr(&mut method.attrs, "handler") {
+                handlers.push((handler_type(method)?, method.sig.ident.clone()));
+            }
+
+            if take_attr(&mut method.attrs, "init") {
+                if init.is_some() {
+                    return Err(syn::Error::new_spanned(
+                        &method.sig,
+                        "only one #[init] method is allowed",
+                    ));
+                }
+                init = Some(method.sig.ident.clone());
+            }
+        }
+    }
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    let name = match args.name {
+        Some(name) => name,
+        None => type_name(self_ty)?,
+    };
+    let key = &args.key;
+    let message = &args.message;
+    let params = &args.params;
+    let (handler_types, handlers): (Vec<_>, Vec<_>) = handlers.into_iter().unzip();
+
+    let init = init.map(|init| {
+        quote! {
+            fn init<'a>(&mut self, handle: &mut ::mozaic::generic::ReactorHandle<'a, #key, #message>) {
+                <#self_ty>::#init(self, handle)
+            }
+        }
+    });
+
+    Ok(quote! {
+        #item
+
+        impl #impl_generics #self_ty #where_clause {
+            /// Creates the reactor params with all #[handler] methods registered
+            pub fn #params(self) -> ::mozaic::generic::CoreParams<Self, #key, #message> {
+                ::mozaic::generic::CoreParams::new(self)
+                    #(.handler(#handler_types::from(Self::#handlers)))*
+            }
+        }
+
+        impl #impl_generics ::mozaic::generic::ReactorState<#key, #message> for #self_ty #where_clause {
+            const NAME: &'static str = #name;
+
+            #init
+        }
+    })
+}
@@ -1,20 +1,46 @@
 extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
 extern crate syn;
 #[macro_use]
 extern crate quote;
 
-#[proc_macro_derive(Key)]
+mod handlers;
+mod protocol;
+
+/// Implements Key<String> for a message type
+///
+/// The key defaults to the lowercase type name, #[key(name = "...")] overrides it.
+/// #[key(version = N)] sets the version, so message types can evolve.
+#[proc_macro_derive(Key, attributes(key))]
 pub fn derive_key(input: TokenStream) -> TokenStream {
     // Parse the string representation
     let ast: syn::DeriveInput = syn::parse(input).unwrap();
 
     let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+
+    let meta_items = get_meta_items(&ast, "key");
+    let key = match meta_items.get("name") {
+        Some(&syn::Lit::Str(ref lit_str)) => lit_str.value(),
+        Some(_) => panic!("expected name to be a string"),
+        None => name.to_string().to_lowercase(),
+    };
+    let version: u32 = match meta_items.get("version") {
+        Some(&syn::Lit::Int(ref lit_int)) => lit_int.base10_parse().unwrap(),
+        Some(_) => panic!("expected version to be an integer"),
+        None => 0,
+    };
 
     // Build the impl
     let gen = quote! {
-        impl ::generic::Key<String> for #name {
+        impl #impl_generics ::mozaic::generic::Key<String> for #name #ty_generics #where_clause {
             fn key() -> String {
-                stringify!(#name).to_lowercase()
+                String::from(#key)
+            }
+
+            fn version() -> u32 {
+                #version
             }
         }
     };
@@ -23,10 +49,36 @@ pub fn derive_key(input: TokenStream) -> TokenStream {
     gen.into()
 }
 
-
 use proc_macro::TokenStream;
 use std::collections::HashMap;
 
+/// Generates the params constructor and ReactorState impl for a reactor
+///
+/// Methods annotated with #[handler] are registered as message handlers,
+/// the method annotated with #[init] is called when the reactor is initialized.
+///
+/// Optional arguments:
+/// name: NAME of the reactor, defaults to the type name
+/// key, message: the K and M of the reactor, defaults to TypeId and Message
+/// params: name of the generated constructor, defaults to params
+#[proc_macro_attribute]
+pub fn handlers(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as syn::AttributeArgs);
+    let item = parse_macro_input!(input as syn::ItemImpl);
+    handlers::impl_handlers(args, item).into()
+}
+
+/// Declares both sides of a link at once
+///
+/// #[side(name: T1, T2)] lists the types that side sends, the other side receives them.
+/// Each side gets a constructor `Protocol::name(state)` returning matching ProtocolParams.
+/// Since both sides are generated from the same lists, they always agree.
+#[proc_macro_derive(LinkProtocol, attributes(side))]
+pub fn derive_link_protocol(input: TokenStream) -> TokenStream {
+    let input: syn::DeriveInput = syn::parse(input).unwrap();
+    protocol::impl_link_protocol(&input).into()
+}
+
 #[proc_macro_derive(MozaicEvent, attributes(mozaic_event))]
 pub fn derive_mozaic_event(input: TokenStream) -> TokenStream {
     let input: syn::DeriveInput = syn::parse(input).unwrap();
@@ -36,7 +88,7 @@ pub fn derive_mozaic_event(input: TokenStream) -> TokenStream {
 fn impl_mozaic_event(ast: &syn::DeriveInput) -> TokenStream {
     let event_ident = &ast.ident;
 
-    let meta_items = get_meta_items(ast);
+    let meta_items = get_meta_items(ast, "mozaic_event");
     let type_id: u32 = match meta_items.get("type_id").unwrap() {
         &syn::Lit::Str(ref lit_str) => {
             lit_str.value().parse().unwrap()
@@ -52,11 +104,11 @@ fn impl_mozaic_event(ast: &syn::DeriveInput) -> TokenStream {
     return tokens.into();
 }
 
-fn get_meta_items(ast: &syn::DeriveInput) -> HashMap<String, syn::Lit> {
+fn get_meta_items(ast: &syn::DeriveInput, name: &str) -> HashMap<String, syn::Lit> {
     let mut items = HashMap::new();
 
     for attr in ast.attrs.iter() {
-        if path_equals(&attr.path, "mozaic_event") {
+        if path_equals(&attr.path, name) {
             let meta_list = match attr.parse_meta() {
                 Err(_) => panic!("could not interpret meta"),
                 Ok(syn::Meta::List(list)) => list,
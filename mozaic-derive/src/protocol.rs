@@ -0,0 +1,98 @@
+use proc_macro2::TokenStream;
+use syn;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+
+/// #[side(name: T1, T2, ...)], the types this side sends
+struct Side {
+    name: syn::Ident,
+    sends: Vec<syn::Type>,
+}
+
+impl Parse for Side {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let sends = Punctuated::<syn::Type, Token![,]>::parse_terminated(input)?;
+
+        Ok(Side {
+            name,
+            sends: sends.into_iter().collect(),
+        })
+    }
+}
+
+/// Builds the constructor for one side, sending its own types and receiving the other side's
+fn side_params(
+    protocol: &syn::Ident,
+    marker: &TokenStream,
+    side: &Side,
+    other: &Side,
+) -> TokenStream {
+    let name = &side.name;
+    let sends = &side.sends;
+    let receives = &other.sends;
+    let doc = format!(
+        "LinkParams for the {} side of {}, receiving from the {} side",
+        side.name, protocol, other.name
+    );
+
+    quote! {
+        #(impl ::mozaic::generic::Receives<#receives> for #marker<#protocol> {})*
+
+        impl #protocol {
+            #[doc = #doc]
+            pub fn #name<S, K, M>(state: S) -> ::mozaic::generic::ProtocolParams<#marker<#protocol>, S, K, M>
+            where
+                S: 'static + Send,
+                K: 'static + Send + Eq + ::std::hash::Hash,
+                M: 'static + Send #(+ ::mozaic::generic::Carrier<K, #sends>)* #(+ ::mozaic::generic::Carrier<K, #receives>)*,
+            {
+                ::mozaic::generic::ProtocolParams::new(
+                    ::mozaic::generic::LinkParams::new(state)
+                        #(.internal_handler(::mozaic::generic::i_to_e::<S, #sends, K, M>()))*
+                        #(.external_handler(::mozaic::generic::e_to_i::<S, #receives, K, M>(::mozaic::generic::TargetReactor::Reactor)))*
+                )
+            }
+        }
+    }
+}
+
+pub fn impl_link_protocol(ast: &syn::DeriveInput) -> TokenStream {
+    expand(ast).unwrap_or_else(|error| error.to_compile_error())
+}
+
+fn expand(ast: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    let attrs: Vec<&syn::Attribute> = ast
+        .attrs
+        .iter()
+        .filter(|attr| super::path_equals(&attr.path, "side"))
+        .collect();
+
+    if attrs.len() != 2 {
+        let message = "a link protocol has exactly two #[side(name: Type, ...)] attributes";
+        return Err(match attrs.get(2) {
+            Some(third) => syn::Error::new_spanned(third, message),
+            None => syn::Error::new_spanned(&ast.ident, message),
+        });
+    }
+
+    let sides = attrs
+        .iter()
+        .map(|attr| {
+            attr.parse_args::<Side>().map_err(|error| {
+                let message = format!("expected #[side(name: Type, ...)], {}", error);
+                syn::Error::new_spanned(attr, message)
+            })
+        })
+        .collect::<syn::Result<Vec<Side>>>()?;
+
+    let protocol = &ast.ident;
+    let a = side_params(protocol, &quote!(::mozaic::generic::SideA), &sides[0], &sides[1]);
+    let b = side_params(protocol, &quote!(::mozaic::generic::SideB), &sides[1], &sides[0]);
+
+    Ok(quote! {
+        #a
+        #b
+    })
+}
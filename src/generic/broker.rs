@@ -1,14 +1,16 @@
-use super::{CoreParams, Reactor, ReactorID, ReactorState, Receiver, Sender, SenderHandle};
+use super::{
+    CloseReason, CoreParams, Operation, Reactor, ReactorID, ReactorState, ReactorStats, Receiver,
+    Runtime, Sender, SenderHandle, Tap,
+};
 
-use futures::channel::mpsc;
-use futures::executor::ThreadPool;
+use futures::channel::{mpsc, oneshot};
 use futures::future::{Future, FutureExt, RemoteHandle};
 use futures::stream::StreamExt;
-use futures::task::SpawnExt;
 
 use tracing_futures::Instrument;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
 use std::hash::Hash;
 use std::sync::{Arc, Mutex};
 
@@ -30,12 +32,57 @@ struct Broker<K, M> {
     reactors: HashMap<ReactorID, ReactorChannel<K, M>>,
 }
 
+/// How many close reasons are kept for reactors that are watched after they closed
+const CLOSED_KEPT: usize = 1024;
+
+#[derive(Default)]
+struct Watchers {
+    waiting: HashMap<ReactorID, Vec<oneshot::Sender<CloseReason>>>,
+    closed: HashMap<ReactorID, CloseReason>,
+    closed_order: VecDeque<ReactorID>,
+}
+
+impl Watchers {
+    /// The id is live again, watchers wait for this run
+    fn spawned(&mut self, id: ReactorID) {
+        if self.closed.remove(&id).is_some() {
+            self.closed_order.retain(|other| *other != id);
+        }
+    }
+
+    /// Drops the watchers whose channel is gone
+    fn forget_dropped(&mut self) {
+        self.waiting.retain(|_, watchers| {
+            watchers.retain(|watcher| !watcher.is_canceled());
+            !watchers.is_empty()
+        });
+    }
+
+    fn closed(&mut self, id: ReactorID, reason: CloseReason) {
+        for watcher in self.waiting.remove(&id).into_iter().flatten() {
+            let _ = watcher.send(reason.clone());
+        }
+
+        if self.closed_order.len() == CLOSED_KEPT {
+            if let Some(oldest) = self.closed_order.pop_front() {
+                self.closed.remove(&oldest);
+            }
+        }
+        self.closed.insert(id, reason);
+        self.closed_order.push_back(id);
+    }
+}
+
+type Stats = HashMap<ReactorID, Arc<ReactorStats>>;
+
 ///
 /// BrokerHandle wraps the Broker, for easy mutex manipulation
 ///
 pub struct BrokerHandle<K, M> {
     broker: Arc<Mutex<Broker<K, M>>>,
-    pool: ThreadPool,
+    watchers: Arc<Mutex<Watchers>>,
+    stats: Arc<Mutex<Stats>>,
+    pool: Runtime,
     tx: mpsc::UnboundedSender<RemoteHandle<()>>,
 }
 
@@ -43,6 +90,8 @@ impl<K, M> Clone for BrokerHandle<K, M> {
     fn clone(&self) -> Self {
         BrokerHandle {
             broker: self.broker.clone(),
+            watchers: self.watchers.clone(),
+            stats: self.stats.clone(),
             pool: self.pool.clone(),
             tx: self.tx.clone(),
         }
@@ -50,8 +99,9 @@ impl<K, M> Clone for BrokerHandle<K, M> {
 }
 
 impl<K, M> BrokerHandle<K, M> {
-    /// Creates a new broker
-    pub fn new(pool: ThreadPool) -> (Self, RemoteHandle<()>) {
+    /// Creates a new broker, running its reactors on pool
+    pub fn new<R: Into<Runtime>>(pool: R) -> (Self, RemoteHandle<()>) {
+        let pool = pool.into();
         let (tx, mut rx) = mpsc::unbounded();
 
         let fut = async move {
@@ -65,9 +115,7 @@ impl<K, M> BrokerHandle<K, M> {
             Some(())
         };
 
-        let handle = pool
-            .spawn_with_handle(fut.map(|_| info!("Broker finished")))
-            .unwrap();
+        let handle = pool.spawn_with_handle(fut.map(|_| info!("Broker finished")));
 
         let broker = Broker {
             reactors: HashMap::new(),
@@ -76,6 +124,8 @@ impl<K, M> BrokerHandle<K, M> {
         (
             BrokerHandle {
                 broker: Arc::new(Mutex::new(broker)),
+                watchers: Arc::new(Mutex::new(Watchers::default())),
+                stats: Arc::new(Mutex::new(HashMap::new())),
                 pool,
                 tx,
             },
@@ -88,21 +138,81 @@ impl<K, M> BrokerHandle<K, M> {
         id: ReactorID,
         name: &str,
         fut: Fut,
+    ) {
+        self.spawn_watched(id, name, fut.map(|_| CloseReason::Closed));
+    }
+
+    /// Spawns a future that reports why it stopped to the watchers of that id
+    fn spawn_watched<Fut: Future<Output = CloseReason> + Send + 'static>(
+        &self,
+        id: ReactorID,
+        name: &str,
+        fut: Fut,
     ) {
         info!(%id, "Start Reactor");
         graph::add_node(&id, name);
+        self.watchers.lock().unwrap().spawned(id);
 
+        let watchers = self.watchers.clone();
+        let stats = self.stats.clone();
         let handle = self
             .pool
-            .spawn_with_handle(fut.map(move |_| {
+            .spawn_with_handle(fut.map(move |reason| {
                 graph::remove_node(&id);
-                info!(%id, "Closed Reactor");
-            }))
-            .unwrap();
+                info!(%id, ?reason, "Closed Reactor");
+
+                if let Some(stats) = stats.lock().unwrap().remove(&id) {
+                    info!(
+                        %id,
+                        messages = stats.messages(),
+                        budget_hits = stats.budget_hits(),
+                        "Reactor stats"
+                    );
+                }
+
+                watchers.lock().unwrap().closed(id, reason);
+            }));
 
         self.tx.unbounded_send(handle).unwrap();
     }
 
+    /// Returns a channel that resolves with the reason this reactor closed,
+    /// the reactor may not be spawned yet
+    /// Reactors that closed recently resolve right away. Dropping the channel stops watching,
+    /// so watching an id that never spawns does not keep anything around.
+    pub fn watch(&self, id: &ReactorID) -> oneshot::Receiver<CloseReason> {
+        let (tx, rx) = oneshot::channel();
+        let mut watchers = self.watchers.lock().unwrap();
+        watchers.forget_dropped();
+        match watchers.closed.get(id) {
+            Some(reason) => {
+                let _ = tx.send(reason.clone());
+            }
+            None => watchers.waiting.entry(*id).or_default().push(tx),
+        }
+        rx
+    }
+
+    /// The runtime this broker spawns its reactors on
+    pub fn runtime(&self) -> &Runtime {
+        &self.pool
+    }
+
+    /// Counters of a running reactor, None for reactor-likes and closed reactors
+    pub fn stats(&self, id: &ReactorID) -> Option<Arc<ReactorStats>> {
+        self.stats.lock().unwrap().get(id).cloned()
+    }
+
+    ///
+    /// Sets the tap on the link from source to target, None removes it
+    /// The tap sees every message that link handles, see modules::wiretap.
+    ///
+    pub fn tap(&self, source: ReactorID, target: ReactorID, tap: Option<Tap<K, M>>) {
+        if self.get(&source).unbounded_send(Operation::Tap(target, tap)).is_err() {
+            info!(%source, "Reactor to tap is already closed");
+        }
+    }
+
     /// Removes a perticular reactor
     // pub fn remove(&self, id: &ReactorID) {
     //     let mut broker = self.broker.lock().unwrap();
@@ -173,7 +283,7 @@ impl<K, M> BrokerHandle<K, M> {
 use crate::graph;
 impl<K, M> BrokerHandle<K, M>
 where
-    K: 'static + Eq + Hash + Send + Unpin,
+    K: 'static + Eq + Hash + Send + Unpin + Debug,
     M: 'static + Send,
 {
     /// Spawns a perticular reactor
@@ -192,8 +302,9 @@ where
         );
 
         reactor.init();
+        self.stats.lock().unwrap().insert(id, reactor.stats());
 
-        self.spawn_fut(
+        self.spawn_watched(
             id,
             S::NAME,
             reactor.instrument(trace_span!("Reactor", name = S::NAME, %id)),
@@ -208,3 +319,24 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::ThreadPool;
+
+    #[test]
+    fn dropped_watches_are_forgotten() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::<(), ()>::new(pool);
+
+        let never = ReactorID::rand();
+        drop(broker.watch(&never));
+        let _other = broker.watch(&ReactorID::rand());
+
+        let watchers = broker.watchers.lock().unwrap();
+        assert!(!watchers.waiting.contains_key(&never));
+        assert_eq!(watchers.waiting.len(), 1);
+    }
+}
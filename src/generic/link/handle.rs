@@ -2,6 +2,8 @@ use super::LinkState;
 
 use crate::generic::{IntoMessage, Operation, ReactorID, TargetReactor};
 
+use tracing::Span;
+
 /// Handle to manipulate a link
 /// Being able so send new messages and close the link
 #[derive(Clone)]
@@ -15,34 +17,33 @@ impl<'a, K, M> LinkHandle<'a, K, M> {
     }
     pub fn send_message<T: 'static + IntoMessage<K, M>>(&mut self, msg: T) {
         if let Some((id, msg)) = T::into_msg(msg) {
-            if self
-                .state
-                .target
-                .unbounded_send(Operation::ExternalMessage(
-                    self.state.source_id.clone(),
-                    id,
-                    msg,
-                ))
-                .is_err()
-            {
-                if self
-                    .state
-                    .source
-                    .unbounded_send(Operation::CloseLink(self.state.target_id))
-                    .is_err()
-                {
-                    trace!("Internal reactor is already closed, nothing to do.");
-                }
-            }
+            self.forward_message(id, msg);
         }
     }
 
     pub fn send_internal<T: 'static + IntoMessage<K, M>>(&mut self, msg: T, target: TargetReactor) {
         if let Some((id, msg)) = T::into_msg(msg) {
+            self.forward_internal(id, msg, target);
+        }
+    }
+
+    /// Sends an already built message over the link
+    pub(crate) fn forward_message(&mut self, id: K, msg: M) {
+        if self
+            .state
+            .target
+            .unbounded_send(Operation::ExternalMessage(
+                self.state.source_id.clone(),
+                id,
+                msg,
+                Span::current(),
+            ))
+            .is_err()
+        {
             if self
                 .state
                 .source
-                .unbounded_send(Operation::InternalMessage(id, msg, target))
+                .unbounded_send(Operation::CloseLink(self.state.target_id))
                 .is_err()
             {
                 trace!("Internal reactor is already closed, nothing to do.");
@@ -50,6 +51,18 @@ impl<'a, K, M> LinkHandle<'a, K, M> {
         }
     }
 
+    /// Sends an already built message to the reactor owning this link
+    pub(crate) fn forward_internal(&mut self, id: K, msg: M, target: TargetReactor) {
+        if self
+            .state
+            .source
+            .unbounded_send(Operation::InternalMessage(id, msg, target, Span::current()))
+            .is_err()
+        {
+            trace!("Internal reactor is already closed, nothing to do.");
+        }
+    }
+
     pub fn close_link(&mut self) {
         if self
             .state
@@ -68,4 +81,19 @@ impl<'a, K, M> LinkHandle<'a, K, M> {
     pub fn source_id(&'a self) -> &'a ReactorID {
         &self.state.source_id
     }
+
+    /// The phase this link is in, None for links without phases
+    pub fn phase(&self) -> Option<&'static str> {
+        *self.state.phase.lock().unwrap()
+    }
+
+    /// Moves the link to another phase, starting with the next message
+    /// A phase the link does not have is logged and ignored, the link stays where it is.
+    pub fn goto(&mut self, phase: &'static str) {
+        if self.state.phase_names.contains(&phase) {
+            *self.state.phase.lock().unwrap() = Some(phase);
+        } else {
+            error!(phase, "Link has no such phase, staying in the current one");
+        }
+    }
 }
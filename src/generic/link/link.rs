@@ -1,8 +1,10 @@
-use super::{Closer, LinkHandle, LinkParams};
+use super::phase::Phases;
+use super::{Closer, Direction, LinkHandle, LinkParams, Tap};
 use crate::generic::{Handler, LinkOperation, Operation, ReactorHandle, ReactorID, Sender};
 
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::sync::Mutex;
 
 /// Macro to create link handles
 /// This does not borrow the entire Link like a function would
@@ -18,6 +20,9 @@ pub struct LinkState<K, M> {
     pub target: Sender<K, M>,
     pub source_id: ReactorID,
     pub target_id: ReactorID,
+    pub phase: Mutex<Option<&'static str>>,
+    /// The phases this link has, goto only moves to one of these
+    pub phase_names: Vec<&'static str>,
 }
 
 /// A link pair links 2 reactors together
@@ -32,17 +37,72 @@ pub struct Link<S, K, M> {
 
     link_state: LinkState<K, M>,
     closer: Closer<S, K, M>,
+    phases: Phases<S, K, M>,
+    tap: Option<Tap<K, M>>,
 }
 
 impl<S, K, M> Link<S, K, M> {
-    pub fn new(link_state: LinkState<K, M>, params: LinkParams<S, K, M>) -> Self {
-        let (state, internal_handlers, external_handlers, closer) = params.consume();
+    pub fn new(mut link_state: LinkState<K, M>, params: LinkParams<S, K, M>) -> Self {
+        let (state, internal_handlers, external_handlers, closer, phases) = params.consume();
+        *link_state.phase.lock().unwrap() = phases.initial;
+        link_state.phase_names = phases.phases.keys().copied().collect();
         Self {
             link_state,
             state,
             internal_handlers,
             external_handlers,
             closer,
+            phases,
+            tap: None,
+        }
+    }
+}
+
+impl<S, K, M> Link<S, K, M>
+where
+    K: Hash + Eq,
+{
+    /// Finds the handler for this message in the current phase or on the link itself,
+    /// reporting messages that are only handled in another phase
+    fn handle_msg(&mut self, id: &K, message: &mut M, internal: bool) {
+        if let Some(tap) = &mut self.tap {
+            let direction = if internal {
+                Direction::Internal
+            } else {
+                Direction::External
+            };
+            tap(id, message, direction);
+        }
+
+        let current = *self.link_state.phase.lock().unwrap();
+        let phases = &mut self.phases.phases;
+        let in_phase = current
+            .and_then(|name| phases.get_mut(name))
+            .and_then(|phase| phase.handlers(internal).get_mut(id));
+
+        let handler = match in_phase {
+            Some(h) => Some(h),
+            None if internal => self.internal_handlers.get_mut(id),
+            None => self.external_handlers.get_mut(id),
+        };
+
+        if let Some(h) = handler {
+            h.handle(&mut self.state, &mut linkHandle!(self), (id, message));
+        } else if let Some(current) = current.filter(|_| {
+            self.phases
+                .phases
+                .values_mut()
+                .any(|phase| phase.handlers(internal).contains_key(id))
+        }) {
+            error!(phase = current, "Message is not valid in this phase of the link");
+            (self.phases.violation)(&mut self.state, &mut linkHandle!(self), current);
+        } else {
+            trace!("No handler found");
+        }
+
+        let next = *self.link_state.phase.lock().unwrap();
+        if next != current {
+            trace!(from = ?current, to = ?next, "Link changed phase");
         }
     }
 }
@@ -61,19 +121,11 @@ where
         m: &mut LinkOperation<K, M>,
     ) {
         match m {
-            LinkOperation::InternalMessage(id, message) => {
-                if let Some(h) = self.internal_handlers.get_mut(id) {
-                    h.handle(&mut self.state, &mut linkHandle!(self), (id, message));
-                } else {
-                    trace!("No handler found");
-                }
-            }
-            LinkOperation::ExternalMessage(id, message) => {
-                if let Some(h) = self.external_handlers.get_mut(id) {
-                    h.handle(&mut self.state, &mut linkHandle!(self), (id, message));
-                } else {
-                    trace!("No handler found");
-                }
+            LinkOperation::InternalMessage(id, message) => self.handle_msg(id, message, true),
+            LinkOperation::ExternalMessage(id, message) => self.handle_msg(id, message, false),
+            LinkOperation::Tap(tap) => {
+                trace!(tapped = tap.is_some(), "Set tap");
+                self.tap = tap.take();
             }
             LinkOperation::Close() => {
                 (self.closer)(&mut self.state, &mut linkHandle!(self));
@@ -1,9 +1,30 @@
 mod handle;
 mod link;
 mod params;
+mod phase;
+mod protocol;
+
+use serde::{Deserialize, Serialize};
 
 pub type Closer<S, K, M> = Box<dyn for<'a> Fn(&mut S, &mut LinkHandle<'a, K, M>) -> () + Send>;
+/// Called with the current phase when a message arrives that this phase does not handle
+pub type Violation<S, K, M> =
+    Box<dyn for<'a> Fn(&mut S, &mut LinkHandle<'a, K, M>, &'static str) + Send>;
+
+/// Sees every message a link handles, see BrokerHandle::tap
+pub type Tap<K, M> = Box<dyn FnMut(&K, &M, Direction) + Send>;
+
+/// Where a message on a link came from
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent by the reactor owning the link
+    Internal,
+    /// Sent by the reactor on the other side
+    External,
+}
 
 pub use handle::LinkHandle;
 pub use link::{Link, LinkState};
 pub use params::LinkParams;
+pub use phase::{goto, Goto, Phase};
+pub use protocol::{ProtocolParams, Receives, SideA, SideB};
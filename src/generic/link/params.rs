@@ -1,8 +1,10 @@
+use super::phase::{Phase, Phases};
 use super::{Closer, Link, LinkState};
 use crate::generic::{Handler, LinkHandle, LinkSpawner};
 
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::sync::Mutex;
 
 type HandlersMap<S, K, M> =
     HashMap<K, Box<dyn for<'a> Handler<S, LinkHandle<'a, K, M>, (&'a K, &'a mut M)> + Send>>;
@@ -14,6 +16,7 @@ pub struct LinkParams<S, K, M> {
     internal_handlers: HandlersMap<S, K, M>,
     external_handlers: HandlersMap<S, K, M>,
     closer: Closer<S, K, M>,
+    phases: Phases<S, K, M>,
 }
 
 impl<S, K, M> LinkParams<S, K, M> {
@@ -24,12 +27,14 @@ impl<S, K, M> LinkParams<S, K, M> {
         HandlersMap<S, K, M>,
         HandlersMap<S, K, M>,
         Closer<S, K, M>,
+        Phases<S, K, M>,
     ) {
         (
             self.state,
             self.internal_handlers,
             self.external_handlers,
             self.closer,
+            self.phases,
         )
     }
 }
@@ -44,6 +49,7 @@ where
             internal_handlers: HashMap::new(),
             external_handlers: HashMap::new(),
             closer: Box::new(|_, _| {}),
+            phases: Phases::new(),
         }
     }
 
@@ -74,25 +80,47 @@ where
         self.external_handlers.insert(id.into(), Box::new(handler));
         self
     }
+
+    ///
+    /// Adds a phase to this link, the link starts in the first phase that is added
+    /// Handlers of the current phase go first, handlers added to the link itself
+    /// work in every phase.
+    ///
+    pub fn phase(mut self, name: &'static str, phase: Phase<S, K, M>) -> Self {
+        self.phases.initial.get_or_insert(name);
+        self.phases.phases.insert(name, phase);
+        self
+    }
+
+    /// Called when a message arrives that is only handled in other phases
+    pub fn violation<F>(mut self, violation_f: F) -> Self
+    where
+        F: 'static + Send + for<'a> Fn(&mut S, &mut LinkHandle<'a, K, M>, &'static str),
+    {
+        self.phases.violation = Box::new(violation_f);
+        self
+    }
 }
 
 /// It is useful to be able to spawn a link when you have the bundled channels and ids
-impl<S, K, M> Into<LinkSpawner<K, M>> for LinkParams<S, K, M>
+impl<S, K, M> From<LinkParams<S, K, M>> for LinkSpawner<K, M>
 where
     S: 'static + Send,
     M: 'static + Send,
     K: 'static + Eq + Hash + Send,
 {
-    fn into(self) -> LinkSpawner<K, M> {
+    fn from(params: LinkParams<S, K, M>) -> LinkSpawner<K, M> {
         Box::new(move |(source, target, source_id, target_id)| {
             let handles = LinkState {
                 source,
                 target,
                 source_id,
                 target_id,
+                phase: Mutex::new(None),
+                phase_names: Vec::new(),
             };
 
-            Box::new(Link::new(handles, self))
+            Box::new(Link::new(handles, params))
         })
     }
 }
@@ -0,0 +1,238 @@
+use super::Violation;
+use crate::generic::{Handler, LinkHandle};
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+pub(super) type HandlersMap<S, K, M> =
+    HashMap<K, Box<dyn for<'a> Handler<S, LinkHandle<'a, K, M>, (&'a K, &'a mut M)> + Send>>;
+
+///
+/// A state of a link with its own handlers, see LinkParams::phase
+/// Handlers move the link to another phase with LinkHandle::goto or the goto wrapper.
+///
+pub struct Phase<S, K, M> {
+    pub(super) internal_handlers: HandlersMap<S, K, M>,
+    pub(super) external_handlers: HandlersMap<S, K, M>,
+}
+
+impl<S, K, M> Phase<S, K, M>
+where
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self {
+            internal_handlers: HashMap::new(),
+            external_handlers: HashMap::new(),
+        }
+    }
+
+    pub fn internal_handler<H, J>(mut self, handler: H) -> Self
+    where
+        H: Into<(K, J)>,
+        J: for<'a> Handler<S, LinkHandle<'a, K, M>, (&'a K, &'a mut M)> + Send + 'static,
+    {
+        let (id, handler) = handler.into();
+        self.internal_handlers.insert(id, Box::new(handler));
+        self
+    }
+
+    pub fn external_handler<H, J>(mut self, handler: H) -> Self
+    where
+        H: Into<(K, J)>,
+        J: for<'a> Handler<S, LinkHandle<'a, K, M>, (&'a K, &'a mut M)> + Send + 'static,
+    {
+        let (id, handler) = handler.into();
+        self.external_handlers.insert(id, Box::new(handler));
+        self
+    }
+
+    pub(super) fn handlers(&mut self, internal: bool) -> &mut HandlersMap<S, K, M> {
+        if internal {
+            &mut self.internal_handlers
+        } else {
+            &mut self.external_handlers
+        }
+    }
+}
+
+impl<S, K, M> Default for Phase<S, K, M>
+where
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// All phases of a link, the phase it starts in and what happens on a violation
+pub struct Phases<S, K, M> {
+    pub(super) phases: HashMap<&'static str, Phase<S, K, M>>,
+    pub(super) initial: Option<&'static str>,
+    pub(super) violation: Violation<S, K, M>,
+}
+
+impl<S, K, M> Phases<S, K, M> {
+    pub(super) fn new() -> Self {
+        Self {
+            phases: HashMap::new(),
+            initial: None,
+            violation: Box::new(|_, _, _| {}),
+        }
+    }
+}
+
+/// Wraps a handler, moving the link to another phase after it handled its message
+pub struct Goto<H> {
+    handler: H,
+    phase: &'static str,
+}
+
+/// Handle messages with handler, then move the link to phase
+pub fn goto<H>(handler: H, phase: &'static str) -> Goto<H> {
+    Goto { handler, phase }
+}
+
+impl<H, K, J> From<Goto<H>> for (K, Goto<J>)
+where
+    H: Into<(K, J)>,
+{
+    fn from(goto: Goto<H>) -> Self {
+        let (key, handler) = goto.handler.into();
+        (
+            key,
+            Goto {
+                handler,
+                phase: goto.phase,
+            },
+        )
+    }
+}
+
+impl<'a, 'k, 'm, S, K, M, J> Handler<S, LinkHandle<'a, K, M>, (&'k K, &'m mut M)> for Goto<J>
+where
+    J: Handler<S, LinkHandle<'a, K, M>, (&'k K, &'m mut M)>,
+{
+    fn handle(&mut self, state: &mut S, handle: &mut LinkHandle<'a, K, M>, msg: (&'k K, &'m mut M)) {
+        self.handler.handle(state, handle, msg);
+        handle.goto(self.phase);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generic::link::LinkState;
+    use crate::generic::*;
+
+    use futures::channel::{mpsc, oneshot};
+    use futures::executor::{block_on, ThreadPool};
+
+    use std::any;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone)]
+    struct Hello;
+    #[derive(Clone)]
+    struct Chat(&'static str);
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    fn hello(log: &mut Log, _: &mut LinkHandle<any::TypeId, Message>, _: &Hello) {
+        log.lock().unwrap().push(String::from("hello"));
+    }
+
+    struct Server(ReactorID, Log, Mutex<Option<oneshot::Sender<()>>>);
+    impl Server {
+        fn chat(&mut self, _: &mut ReactorHandle<any::TypeId, Message>, chat: &Chat) {
+            self.1.lock().unwrap().push(chat.0.to_string());
+            if chat.0 == "bye" {
+                if let Some(tx) = self.2.lock().unwrap().take() {
+                    let _ = tx.send(());
+                }
+            }
+        }
+    }
+
+    impl ReactorState<any::TypeId, Message> for Server {
+        const NAME: &'static str = "Server";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+            let log = self.1.clone();
+            let params = LinkParams::new(log)
+                .phase(
+                    "greeting",
+                    Phase::new().external_handler(goto(FunctionHandler::from(hello), "chatting")),
+                )
+                .phase(
+                    "chatting",
+                    Phase::new().external_handler(e_to_i::<_, Chat, _, _>(TargetReactor::Reactor)),
+                )
+                .violation(|log, handle, phase| {
+                    assert_eq!(handle.phase(), Some(phase));
+                    log.lock().unwrap().push(format!("violation in {}", phase));
+                });
+            handle.open_link(self.0, params, false);
+        }
+    }
+
+    struct Client(ReactorID);
+    impl ReactorState<any::TypeId, Message> for Client {
+        const NAME: &'static str = "Client";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+            let params = LinkParams::new(())
+                .internal_handler(i_to_e::<(), Hello, _, _>())
+                .internal_handler(i_to_e::<(), Chat, _, _>());
+            handle.open_link(self.0, params, false);
+
+            handle.send_internal(Chat("too soon"), TargetReactor::Links);
+            handle.send_internal(Hello, TargetReactor::Links);
+            handle.send_internal(Chat("bye"), TargetReactor::Links);
+        }
+    }
+
+    #[test]
+    fn phases_guard_messages() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::new(pool);
+
+        let server_id = ReactorID::rand();
+        let client_id = ReactorID::rand();
+        let log = Log::default();
+
+        let (tx, rx) = oneshot::channel();
+        broker.spawn(
+            CoreParams::new(Server(client_id, log.clone(), Mutex::new(Some(tx))))
+                .handler(FunctionHandler::from(Server::chat)),
+            Some(server_id),
+        );
+        broker.spawn(CoreParams::new(Client(server_id)), Some(client_id));
+
+        block_on(rx).unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["violation in greeting", "hello", "bye"]
+        );
+    }
+
+    #[test]
+    fn goto_ignores_unknown_phases() {
+        let (source, _source_rx) = mpsc::unbounded();
+        let (target, _target_rx) = mpsc::unbounded();
+        let state = LinkState::<any::TypeId, Message> {
+            source,
+            target,
+            source_id: ReactorID::rand(),
+            target_id: ReactorID::rand(),
+            phase: Mutex::new(Some("greeting")),
+            phase_names: vec!["greeting", "chatting"],
+        };
+        let mut handle = LinkHandle::new(&state);
+
+        handle.goto("chatting");
+        assert_eq!(handle.phase(), Some("chatting"));
+
+        handle.goto("chating");
+        assert_eq!(handle.phase(), Some("chatting"));
+    }
+}
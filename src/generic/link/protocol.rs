@@ -0,0 +1,68 @@
+use crate::generic::{e_to_i, Carrier, LinkParams, LinkSpawner, Message, TargetReactor};
+
+use std::any;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+/// The first side declared in a #[derive(LinkProtocol)]
+pub struct SideA<P>(PhantomData<P>);
+/// The second side declared in a #[derive(LinkProtocol)]
+pub struct SideB<P>(PhantomData<P>);
+
+/// Implemented by a protocol side for every message the other side sends
+pub trait Receives<T> {}
+
+///
+/// LinkParams for one side of a link protocol
+/// Everything this side sends is forwarded, everything it receives goes to the reactor
+/// unless it is routed elsewhere.
+///
+/// Routing a type this side does not receive is a compile error.
+///
+pub struct ProtocolParams<Side, S, K = any::TypeId, M = Message> {
+    pd: PhantomData<Side>,
+    params: LinkParams<S, K, M>,
+}
+
+impl<Side, S, K, M> ProtocolParams<Side, S, K, M>
+where
+    S: 'static + Send,
+    K: 'static + Send + Eq + Hash,
+    M: 'static + Send,
+{
+    pub fn new(params: LinkParams<S, K, M>) -> Self {
+        Self {
+            pd: PhantomData,
+            params,
+        }
+    }
+
+    /// Route incoming messages of type T to this target
+    pub fn route<T>(mut self, target: TargetReactor) -> Self
+    where
+        Side: Receives<T>,
+        T: 'static + Send + Sync + Clone,
+        M: Carrier<K, T>,
+    {
+        self.params = self
+            .params
+            .external_handler(e_to_i::<S, T, K, M>(target));
+        self
+    }
+
+    /// The underlying LinkParams, to add closers or custom handlers
+    pub fn params(self) -> LinkParams<S, K, M> {
+        self.params
+    }
+}
+
+impl<Side, S, K, M> From<ProtocolParams<Side, S, K, M>> for LinkSpawner<K, M>
+where
+    S: 'static + Send,
+    K: 'static + Send + Eq + Hash,
+    M: 'static + Send,
+{
+    fn from(protocol: ProtocolParams<Side, S, K, M>) -> LinkSpawner<K, M> {
+        protocol.params.into()
+    }
+}
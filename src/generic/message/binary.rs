@@ -0,0 +1,248 @@
+use super::json::{check_key, share_item, take_item};
+use super::Inspect;
+use crate::generic::*;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::any;
+use std::sync::Arc;
+
+///
+/// A serializable message like JSONMessage, but encoded as MessagePack
+/// The value is only decoded when it is borrowed, so large game states stay compact.
+///
+pub struct BinaryMessage {
+    bytes: Arc<[u8]>,
+    id: String,
+    version: u32,
+    item: Option<Option<Message>>,
+    local: bool,
+}
+
+impl BinaryMessage {
+    /// A message as read from the wire, tagged with its key and version
+    pub fn new(id: String, version: u32, bytes: Vec<u8>) -> Self {
+        Self {
+            bytes: bytes.into(),
+            id,
+            version,
+            item: None,
+            local: false,
+        }
+    }
+
+    pub fn borrow<'a, T: 'static + Send + for<'de> Deserialize<'de>>(
+        &'a mut self,
+    ) -> Option<&'a T> {
+        if self.item.is_none() {
+            self.item = Some(
+                rmp_serde::from_slice(&self.bytes)
+                    .map_err(|e| error!(%e, "From bytes failed"))
+                    .ok()
+                    .and_then(|item| {
+                        <Message as Carrier<any::TypeId, T>>::into_msg(item).map(|(_, i)| i)
+                    }),
+            );
+        }
+
+        if let Some(Some(item)) = &mut self.item {
+            item.borrow()
+        } else {
+            None
+        }
+    }
+
+    /// None for Local values, they never leave this process
+    pub fn bytes(&self) -> Option<&[u8]> {
+        if self.local {
+            None
+        } else {
+            Some(&self.bytes)
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.id
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Checks whether this message holds a T, comparing key and version
+    pub fn check<T: Key<String>>(&self) -> Result<(), MessageError> {
+        check_key::<T>(&self.id, self.version)
+    }
+
+    pub fn into_t<'a, T: 'static + Send + for<'de> Deserialize<'de> + Key<String>>(
+        &'a mut self,
+    ) -> Result<&'a T, MessageError> {
+        self.check::<T>()?;
+
+        let key = self.id.clone();
+        self.borrow().ok_or(MessageError::Invalid(key))
+    }
+}
+
+impl Inspect for BinaryMessage {
+    fn type_name(&self) -> &str {
+        &self.id
+    }
+
+    fn body(&self) -> Option<Value> {
+        rmp_serde::from_slice(self.bytes()?).ok()
+    }
+}
+
+impl<T> Carrier<String, T> for BinaryMessage
+where
+    T: 'static + Send + Serialize + for<'de> Deserialize<'de> + Key<String>,
+{
+    fn key() -> String {
+        T::key()
+    }
+
+    fn from_msg<'a>(key: &String, msg: &'a mut BinaryMessage) -> Option<&'a T> {
+        if *key != msg.id {
+            trace!("Trying to deref message with wrong type");
+            return None;
+        }
+
+        if let Err(e) = msg.check::<T>() {
+            error!(%e, "Rejected message");
+            return None;
+        }
+
+        msg.borrow()
+    }
+
+    fn into_msg(t: T) -> Option<(String, BinaryMessage)> {
+        match rmp_serde::to_vec_named(&t) {
+            Ok(bytes) => Some((T::key(), BinaryMessage::new(T::key(), T::version(), bytes))),
+            Err(e) => {
+                error!("To bytes failed {:?}", e);
+                None
+            }
+        }
+    }
+
+    fn share(msg: &mut BinaryMessage) -> Option<BinaryMessage>
+    where
+        T: Send + Sync,
+    {
+        Some(BinaryMessage {
+            bytes: msg.bytes.clone(),
+            id: msg.id.clone(),
+            version: msg.version,
+            item: share_item::<T>(&mut msg.item),
+            local: msg.local,
+        })
+    }
+
+    fn take(key: &String, msg: &mut BinaryMessage) -> Option<T>
+    where
+        T: Clone + Send + Sync,
+    {
+        <Self as Carrier<String, T>>::from_msg(key, msg)?;
+        take_item(&mut msg.item)
+    }
+}
+
+impl<T: 'static + Send> Carrier<String, Local<T>> for BinaryMessage {
+    fn key() -> String {
+        Local::<T>::key()
+    }
+
+    fn from_msg<'a>(key: &String, msg: &'a mut BinaryMessage) -> Option<&'a Local<T>> {
+        if *key != msg.id {
+            trace!("Trying to deref message with wrong type");
+            return None;
+        }
+
+        if let Some(Some(item)) = &mut msg.item {
+            item.borrow()
+        } else {
+            None
+        }
+    }
+
+    fn into_msg(t: Local<T>) -> Option<(String, BinaryMessage)> {
+        let id = Local::<T>::key();
+        let item = <Message as Carrier<any::TypeId, Local<T>>>::into_msg(t).map(|(_, i)| i);
+        Some((
+            id.clone(),
+            BinaryMessage {
+                bytes: Arc::new([]),
+                id,
+                version: 0,
+                item: Some(item),
+                local: true,
+            },
+        ))
+    }
+
+    fn share(msg: &mut BinaryMessage) -> Option<BinaryMessage>
+    where
+        Local<T>: Send + Sync,
+    {
+        Some(BinaryMessage {
+            bytes: msg.bytes.clone(),
+            id: msg.id.clone(),
+            version: 0,
+            item: Some(share_item::<Local<T>>(&mut msg.item)?),
+            local: true,
+        })
+    }
+
+    fn take(key: &String, msg: &mut BinaryMessage) -> Option<Local<T>>
+    where
+        Local<T>: Clone + Send + Sync,
+    {
+        <Self as Carrier<String, Local<T>>>::from_msg(key, msg)?;
+        take_item(&mut msg.item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Key, Debug, PartialEq)]
+    #[key(version = 1)]
+    struct Board {
+        cells: Vec<Option<u8>>,
+        turn: u64,
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let board = Board {
+            cells: vec![None, Some(1), Some(2)],
+            turn: 7,
+        };
+
+        let (key, msg) = <Board as IntoMessage<String, BinaryMessage>>::into_msg(board).unwrap();
+        assert_eq!(key, "board");
+
+        let json = serde_json::to_vec(&serde_json::json!({
+            "cells": [null, 1, 2],
+            "turn": 7,
+        }))
+        .unwrap();
+        assert!(msg.bytes().unwrap().len() < json.len());
+
+        // As if it came from the wire
+        let mut msg = BinaryMessage::new(key.clone(), 1, msg.bytes().unwrap().to_vec());
+        assert_eq!(Board::from_msg(&key, &mut msg).map(|b| b.turn), Some(7));
+
+        let mut old = BinaryMessage::new(key.clone(), 0, Vec::new());
+        assert_eq!(
+            old.into_t::<Board>().err(),
+            Some(MessageError::WrongVersion {
+                key,
+                expected: 1,
+                found: 0,
+            })
+        );
+    }
+}
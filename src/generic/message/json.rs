@@ -1,15 +1,18 @@
+use super::Inspect;
 use crate::generic::*;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{any, ops};
-
-pub static ID_FIELD: &str = "type_id";
+use std::sync::Arc;
+use std::{any, fmt, ops};
 
 #[derive(Deserialize, Serialize)]
 pub struct Typed<T> {
     type_id: String,
 
+    #[serde(default)]
+    type_version: u32,
+
     #[serde(flatten)]
     value: T,
 }
@@ -18,6 +21,7 @@ impl<T: Serialize + for<'de> Deserialize<'de> + Key<String>> From<T> for Typed<T
     fn from(value: T) -> Typed<T> {
         Self {
             type_id: T::key(),
+            type_version: T::version(),
             value,
         }
     }
@@ -35,6 +39,10 @@ impl<T: Key<String>> Key<String> for Typed<T> {
     fn key() -> String {
         T::key()
     }
+
+    fn version() -> u32 {
+        T::version()
+    }
 }
 
 impl Key<String> for Typed<String> {
@@ -43,10 +51,90 @@ impl Key<String> for Typed<String> {
     }
 }
 
+/// Why a JSONMessage could not be read as a certain type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageError {
+    WrongKey {
+        expected: String,
+        found: String,
+    },
+    WrongVersion {
+        key: String,
+        expected: u32,
+        found: u32,
+    },
+    Invalid(String),
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::WrongKey { expected, found } => {
+                write!(f, "Expected message {}, found {}", expected, found)
+            }
+            MessageError::WrongVersion {
+                key,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Expected version {} of message {}, found version {}",
+                expected, key, found
+            ),
+            MessageError::Invalid(key) => write!(f, "Message {} could not be deserialized", key),
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+/// Checks whether a message with this id and version holds a T
+pub(super) fn check_key<T: Key<String>>(id: &str, version: u32) -> Result<(), MessageError> {
+    let key = T::key();
+    if key != id {
+        return Err(MessageError::WrongKey {
+            expected: key,
+            found: id.to_string(),
+        });
+    }
+
+    if T::version() != version {
+        return Err(MessageError::WrongVersion {
+            key,
+            expected: T::version(),
+            found: version,
+        });
+    }
+
+    Ok(())
+}
+
+/// Shares the decoded value of a serialized message, None when it was not decoded yet
+pub(super) fn share_item<T: 'static + Send + Sync>(
+    item: &mut Option<Option<Message>>,
+) -> Option<Option<Message>> {
+    match item {
+        Some(Some(item)) => item.share::<T>().map(Some),
+        _ => None,
+    }
+}
+
+/// Takes the decoded value out of a serialized message
+pub(super) fn take_item<T: 'static + Clone + Send + Sync>(
+    item: &mut Option<Option<Message>>,
+) -> Option<T> {
+    match item {
+        Some(Some(item)) => item.take_or_clone(),
+        _ => None,
+    }
+}
+
 pub struct JSONMessage {
-    value: Value,
+    value: Arc<Value>,
     id: String,
+    version: u32,
     item: Option<Option<Message>>,
+    local: bool,
 }
 
 impl ops::Deref for JSONMessage {
@@ -58,13 +146,15 @@ impl ops::Deref for JSONMessage {
 }
 
 impl JSONMessage {
-    pub fn borrow<'a, T: 'static + for<'de> Deserialize<'de>>(&'a mut self) -> Option<&'a T> {
+    pub fn borrow<'a, T: 'static + Send + for<'de> Deserialize<'de>>(
+        &'a mut self,
+    ) -> Option<&'a T> {
         if self.item.is_none() {
             self.item = Some(
-                serde_json::from_value(self.value.clone())
+                serde_json::from_value((*self.value).clone())
                     .ok()
                     .and_then(|item| {
-                        <T as IntoMessage<any::TypeId, Message>>::into_msg(item).map(|(_, i)| i)
+                        <Message as Carrier<any::TypeId, T>>::into_msg(item).map(|(_, i)| i)
                     }),
             );
         }
@@ -80,53 +170,256 @@ impl JSONMessage {
         &self.value
     }
 
+    /// None for Local values, they never leave this process
     pub fn bytes(&self) -> Option<Vec<u8>> {
-        serde_json::to_vec(&self.value).ok()
+        if self.local {
+            return None;
+        }
+        serde_json::to_vec(&*self.value).ok()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Checks whether this message holds a T, comparing key and version
+    pub fn check<T: Key<String>>(&self) -> Result<(), MessageError> {
+        check_key::<T>(&self.id, self.version)
     }
 
-    pub fn into_t<'a, T: FromMessage<String, JSONMessage> + Key<String>>(
+    pub fn into_t<'a, T: 'static + Send + for<'de> Deserialize<'de> + Key<String>>(
         &'a mut self,
-    ) -> Option<&'a T> {
-        T::from_msg(&T::key(), self)
+    ) -> Result<&'a T, MessageError> {
+        self.check::<T>()?;
+
+        let key = self.id.clone();
+        self.borrow().ok_or(MessageError::Invalid(key))
+    }
+}
+
+impl Inspect for JSONMessage {
+    fn type_name(&self) -> &str {
+        &self.id
+    }
+
+    fn body(&self) -> Option<Value> {
+        if self.local {
+            None
+        } else {
+            Some((*self.value).clone())
+        }
     }
 }
 
 // Please don't puke
-impl<T: 'static + for<'de> Deserialize<'de>> FromMessage<String, JSONMessage> for T {
+impl<T> Carrier<String, T> for JSONMessage
+where
+    T: 'static + Send + Serialize + for<'de> Deserialize<'de> + Key<String>,
+{
+    fn key() -> String {
+        T::key()
+    }
+
     fn from_msg<'a>(key: &String, msg: &'a mut JSONMessage) -> Option<&'a T> {
         if *key != msg.id {
             trace!("Trying to deref message with wrong type");
             return None;
         }
 
+        if let Err(e) = msg.check::<T>() {
+            error!(%e, "Rejected message");
+            return None;
+        }
+
         msg.borrow()
     }
-}
 
-impl<T: 'static + Serialize> IntoMessage<String, JSONMessage> for T {
-    fn into_msg(self) -> Option<(String, JSONMessage)> {
-        match serde_json::to_value(&self) {
+    fn into_msg(t: T) -> Option<(String, JSONMessage)> {
+        match serde_json::to_value(&t) {
             Ok(value) => {
-                if let Some(id) = value
-                    .get(ID_FIELD)
-                    .and_then(|v| v.as_str())
-                    .map(String::from)
-                {
-                    return Some((
-                        id.clone(),
-                        JSONMessage {
-                            value,
-                            id,
-                            item: None,
-                        },
-                    ));
-                } else {
-                    error!("No id field found");
-                }
+                let id = T::key();
+                Some((
+                    id.clone(),
+                    JSONMessage {
+                        value: Arc::new(value),
+                        id,
+                        version: T::version(),
+                        item: None,
+                        local: false,
+                    },
+                ))
+            }
+            Err(e) => {
+                error!("To value failed {:?}", e);
+                None
             }
-            Err(e) => error!("To value failed {:?}", e),
         }
+    }
+
+    fn share(msg: &mut JSONMessage) -> Option<JSONMessage>
+    where
+        T: Send + Sync,
+    {
+        Some(JSONMessage {
+            value: msg.value.clone(),
+            id: msg.id.clone(),
+            version: msg.version,
+            item: share_item::<T>(&mut msg.item),
+            local: msg.local,
+        })
+    }
+
+    fn take(key: &String, msg: &mut JSONMessage) -> Option<T>
+    where
+        T: Clone + Send + Sync,
+    {
+        <Self as Carrier<String, T>>::from_msg(key, msg)?;
+        take_item(&mut msg.item)
+    }
+}
+
+///
+/// A value that is only ever sent inside this process, like a channel or a closure
+/// A JSONMessage carrying a Local has no value and cannot be written out.
+///
+#[derive(Clone)]
+pub struct Local<T>(pub T);
+
+impl<T> ops::Deref for Local<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: 'static> Local<T> {
+    pub(super) fn key() -> String {
+        format!("local:{}", any::type_name::<T>())
+    }
+}
+
+impl<T: 'static + Send> Carrier<String, Local<T>> for JSONMessage {
+    fn key() -> String {
+        Local::<T>::key()
+    }
+
+    fn from_msg<'a>(key: &String, msg: &'a mut JSONMessage) -> Option<&'a Local<T>> {
+        if *key != msg.id {
+            trace!("Trying to deref message with wrong type");
+            return None;
+        }
+
+        if let Some(Some(item)) = &mut msg.item {
+            item.borrow()
+        } else {
+            None
+        }
+    }
+
+    fn into_msg(t: Local<T>) -> Option<(String, JSONMessage)> {
+        let id = Local::<T>::key();
+        let item = <Message as Carrier<any::TypeId, Local<T>>>::into_msg(t).map(|(_, i)| i);
+        Some((
+            id.clone(),
+            JSONMessage {
+                value: Arc::new(Value::Null),
+                id,
+                version: 0,
+                item: Some(item),
+                local: true,
+            },
+        ))
+    }
+
+    fn share(msg: &mut JSONMessage) -> Option<JSONMessage>
+    where
+        Local<T>: Send + Sync,
+    {
+        Some(JSONMessage {
+            value: msg.value.clone(),
+            id: msg.id.clone(),
+            version: 0,
+            item: Some(share_item::<Local<T>>(&mut msg.item)?),
+            local: true,
+        })
+    }
+
+    fn take(key: &String, msg: &mut JSONMessage) -> Option<Local<T>>
+    where
+        Local<T>: Clone + Send + Sync,
+    {
+        <Self as Carrier<String, Local<T>>>::from_msg(key, msg)?;
+        take_item(&mut msg.item)
+    }
+}
+
+impl Key<String> for Value {
+    fn key() -> String {
+        "value".to_string()
+    }
+}
+
+impl Key<String> for u64 {
+    fn key() -> String {
+        "u64".to_string()
+    }
+}
+
+impl Key<String> for ReactorID {
+    fn key() -> String {
+        "reactorid".to_string()
+    }
+}
+
+impl<T: Key<String>> Key<String> for Vec<T> {
+    fn key() -> String {
+        format!("vec<{}>", T::key())
+    }
+
+    fn version() -> u32 {
+        T::version()
+    }
+}
+
+impl<A: Key<String>, B: Key<String>> Key<String> for (A, B) {
+    fn key() -> String {
+        format!("({}, {})", A::key(), B::key())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Key)]
+    #[key(name = "point", version = 2)]
+    struct Point {
+        x: i32,
+    }
+
+    #[derive(Serialize, Deserialize, Key)]
+    #[key(name = "point", version = 1)]
+    struct OldPoint {
+        x: i32,
+    }
+
+    #[test]
+    fn versioned_keys() {
+        let (key, mut msg) =
+            <Typed<Point> as IntoMessage<String, JSONMessage>>::into_msg(Typed::from(Point { x: 3 }))
+                .unwrap();
+        assert_eq!(key, "point");
+        assert_eq!(msg.version(), 2);
 
-        None
+        assert_eq!(
+            msg.into_t::<Typed<OldPoint>>().err(),
+            Some(MessageError::WrongVersion {
+                key: String::from("point"),
+                expected: 1,
+                found: 2,
+            })
+        );
+        assert_eq!(msg.into_t::<Typed<Point>>().map(|p| p.x), Ok(3));
     }
 }
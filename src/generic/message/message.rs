@@ -1,107 +1,322 @@
-use crate::generic::{FromMessage, IntoMessage};
-use std::any::TypeId;
-use std::sync::atomic::AtomicPtr;
+use super::Inspect;
+use crate::generic::Carrier;
+use serde_json::Value;
+use std::any::{self, Any, TypeId};
+use std::mem::{self, ManuallyDrop, MaybeUninit};
+use std::ptr;
+use std::sync::Arc;
+
+/// Values that fit in here are stored inline, so they do not allocate
+type InlineBuf = [usize; 2];
+
+///
+/// A small value stored inside the message itself
+/// This is the only unsafe part of Message, Message keeps track of the type.
+///
+struct Inline {
+    buf: MaybeUninit<InlineBuf>,
+    drop_fn: unsafe fn(*mut u8),
+}
+
+unsafe fn drop_value<T>(ptr: *mut u8) {
+    ptr::drop_in_place(ptr.cast::<T>());
+}
+
+impl Inline {
+    fn fits<T>() -> bool {
+        mem::size_of::<T>() <= mem::size_of::<InlineBuf>()
+            && mem::align_of::<T>() <= mem::align_of::<InlineBuf>()
+    }
+
+    fn new<T>(t: T) -> Self {
+        assert!(Self::fits::<T>());
+
+        let mut buf = MaybeUninit::<InlineBuf>::uninit();
+        // Safety: buf is large and aligned enough for a T, checked above
+        unsafe { ptr::write(buf.as_mut_ptr().cast::<T>(), t) };
+
+        Inline {
+            buf,
+            drop_fn: drop_value::<T>,
+        }
+    }
+
+    /// Safety: the stored value has to be a T
+    unsafe fn get<T>(&self) -> &T {
+        &*self.buf.as_ptr().cast::<T>()
+    }
+
+    /// Safety: the stored value has to be a T
+    unsafe fn read<T>(self) -> T {
+        // Moving the value out, so it may not be dropped here
+        let this = ManuallyDrop::new(self);
+        ptr::read(this.buf.as_ptr().cast::<T>())
+    }
+}
+
+impl Drop for Inline {
+    fn drop(&mut self) {
+        // Safety: drop_fn was made for the type that was written in buf
+        unsafe { (self.drop_fn)(self.buf.as_mut_ptr().cast()) };
+    }
+}
+
+enum Payload {
+    Taken,
+    Inline(Inline),
+    Boxed(Box<dyn Any + Send>),
+    Shared(Arc<dyn Any + Send + Sync>),
+}
 
 // messages, je stuurt ze.
+// Only Send values go in, so a Message can move to any thread of the pool.
 pub struct Message {
-    ptr: AtomicPtr<u8>,
     type_id: TypeId,
-    destroy: Box<dyn Fn(&mut *mut u8) -> () + 'static + Send + Sync>,
+    type_name: &'static str,
+    payload: Payload,
 }
 
 impl Message {
+    pub fn new<T: 'static + Send>(t: T) -> Self {
+        let payload = if Inline::fits::<T>() {
+            Payload::Inline(Inline::new(t))
+        } else {
+            Payload::Boxed(Box::new(t))
+        };
+
+        Message {
+            type_id: TypeId::of::<T>(),
+            type_name: any::type_name::<T>(),
+            payload,
+        }
+    }
+
+    fn is<T: 'static>(&self) -> bool {
+        if self.type_id == TypeId::of::<T>() {
+            true
+        } else {
+            trace!("Trying to deref message with wrong type");
+            false
+        }
+    }
+
+    /// Takes the value out of an owned message, see take_or_clone for shared messages
     pub fn take<T: 'static>(&mut self) -> Option<T> {
-        let ptr = self.ptr.get_mut();
-
-        match ptr.is_null() {
-            true => None, // When ptr is null return None
-            false => match TypeId::of::<T>() == self.type_id {
-                true => {
-                    // When types match
-
-                    // Transmute into returned value and set internal pointer to
-                    // null, so we avoid owning same value in several places.
-
-                    let result: Box<T> = unsafe { Box::from_raw(ptr.cast()) };
-                    self.ptr = AtomicPtr::new(std::ptr::null_mut());
-
-                    Some(*result) // Unbox and return Some
-                }
-                false => {
-                    // When types do not match return None
-                    trace!("Trying to deref message with wrong type");
-                    None
-                }
-            },
+        if !self.is::<T>() {
+            return None;
+        }
+
+        match mem::replace(&mut self.payload, Payload::Taken) {
+            // Safety: type_id says the value is a T
+            Payload::Inline(inline) => Some(unsafe { inline.read() }),
+            Payload::Boxed(boxed) => boxed.downcast().ok().map(|item| *item),
+            payload => {
+                self.payload = payload;
+                None
+            }
+        }
+    }
+
+    /// Takes the value out of this message, cloning it when it is still shared elsewhere
+    pub fn take_or_clone<T: 'static + Send + Sync + Clone>(&mut self) -> Option<T> {
+        if !self.is::<T>() {
+            return None;
+        }
+
+        match mem::replace(&mut self.payload, Payload::Taken) {
+            Payload::Shared(shared) => shared
+                .downcast::<T>()
+                .ok()
+                .map(|item| Arc::try_unwrap(item).unwrap_or_else(|item| (*item).clone())),
+            payload => {
+                self.payload = payload;
+                self.take()
+            }
+        }
+    }
+
+    ///
+    /// Another message pointing at the same value, nothing is cloned
+    /// An owned message is moved behind an Arc the first time it is shared.
+    ///
+    pub fn share<T: 'static + Send + Sync>(&mut self) -> Option<Message> {
+        if !self.is::<T>() {
+            return None;
+        }
+
+        if let Payload::Inline(_) | Payload::Boxed(_) = self.payload {
+            let item = self.take::<T>()?;
+            self.payload = Payload::Shared(Arc::new(item));
+        }
+
+        match &self.payload {
+            Payload::Shared(shared) => Some(Message {
+                type_id: self.type_id,
+                type_name: self.type_name,
+                payload: Payload::Shared(shared.clone()),
+            }),
+            _ => None,
         }
     }
 
     pub fn borrow<'a, T: 'static>(&'a mut self) -> Option<&'a T> {
-        let ptr = self.ptr.get_mut();
-        match ptr.is_null() {
-            true => None, // When ptr is null return None
-            false => match TypeId::of::<T>() == self.type_id {
-                true => {
-                    let ptr: *mut T = ptr.cast();
-                    unsafe { ptr.as_ref() }
-                }
-                false => {
-                    trace!("Trying to deref message with wrong type");
-                    None
-                }
-            },
+        if !self.is::<T>() {
+            return None;
+        }
+
+        match &self.payload {
+            Payload::Taken => None,
+            // Safety: type_id says the value is a T
+            Payload::Inline(inline) => Some(unsafe { inline.get() }),
+            Payload::Boxed(boxed) => boxed.downcast_ref(),
+            Payload::Shared(shared) => shared.downcast_ref(),
         }
     }
 }
 
-impl<T: 'static> FromMessage<TypeId, Message> for T {
+/// Plain messages are not serialized, only their type is known
+impl Inspect for Message {
+    fn type_name(&self) -> &str {
+        self.type_name
+    }
+
+    fn body(&self) -> Option<Value> {
+        None
+    }
+}
+
+impl<T: 'static + Send> Carrier<TypeId, T> for Message {
+    fn key() -> TypeId {
+        TypeId::of::<T>()
+    }
+
     fn from_msg<'a>(_: &TypeId, msg: &'a mut Message) -> Option<&'a T> {
         msg.borrow()
     }
-}
 
-impl<T: 'static> IntoMessage<TypeId, Message> for T {
-    fn into_msg(self) -> Option<(TypeId, Message)> {
-        let boxed = Box::new(self);
-        let type_id = TypeId::of::<T>();
-
-        Some((
-            type_id,
-            Message {
-                ptr: AtomicPtr::new(Box::into_raw(boxed).cast()),
-                type_id,
-
-                destroy: Box::new(|ptr| {
-                    if !ptr.is_null() {
-                        unsafe { Box::from_raw(ptr.cast::<T>()) };
-                    }
-                }),
-            },
-        ))
+    fn into_msg(t: T) -> Option<(TypeId, Message)> {
+        Some((TypeId::of::<T>(), Message::new(t)))
     }
-}
 
-impl Drop for Message {
-    fn drop(&mut self) {
-        (self.destroy)(self.ptr.get_mut());
+    fn share(msg: &mut Message) -> Option<Message>
+    where
+        T: Send + Sync,
+    {
+        msg.share::<T>()
+    }
+
+    fn take(_: &TypeId, msg: &mut Message) -> Option<T>
+    where
+        T: Clone + Send + Sync,
+    {
+        msg.take_or_clone()
     }
 }
 
+///
+/// These tests exercise the unsafe inline storage, CI runs them under Miri as well
+/// (.github/workflows/miri.yml): cargo +nightly miri test --lib generic::message::message
+///
 #[cfg(test)]
 mod tests {
-    use crate::generic::IntoMessage;
+    use super::Payload;
+    use crate::generic::{IntoMessage, Message};
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
+    #[derive(Clone)]
     struct Val {
         value: i32,
     }
 
+    /// Counts its drops, Large is too big to be stored inline
+    #[derive(Clone)]
+    struct Counted(Arc<AtomicUsize>);
+    #[derive(Clone)]
+    struct Large(Counted, [u64; 8]);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
     #[test]
     fn exploration() {
-        let (_, mut maybe) = Val::into_msg(Val { value: 333 }).unwrap();
+        let (_, mut maybe): (_, Message) = Val::into_msg(Val { value: 333 }).unwrap();
         let result = maybe.take::<Val>().map(|x| x.value);
         assert_eq!(result, Some(333));
 
         let result = maybe.take::<Val>().map(|x| x.value);
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn small_values_are_inline() {
+        struct Unit;
+
+        assert!(matches!(Message::new(Unit).payload, Payload::Inline(_)));
+        assert!(matches!(Message::new(5u64).payload, Payload::Inline(_)));
+        assert!(matches!(Message::new(String::new()).payload, Payload::Boxed(_)));
+        assert!(matches!(Message::new([0u64; 8]).payload, Payload::Boxed(_)));
+    }
+
+    #[test]
+    fn take_and_borrow() {
+        let mut inline = Message::new(7u64);
+        assert_eq!(inline.borrow::<u32>(), None);
+        assert_eq!(inline.take::<u32>(), None);
+        assert_eq!(inline.borrow::<u64>(), Some(&7));
+        assert_eq!(inline.take::<u64>(), Some(7));
+        assert_eq!(inline.borrow::<u64>(), None);
+
+        let mut boxed = Message::new(String::from("mozaic"));
+        assert_eq!(boxed.borrow::<String>().map(String::as_str), Some("mozaic"));
+        assert_eq!(boxed.take::<String>(), Some(String::from("mozaic")));
+        assert_eq!(boxed.take::<String>(), None);
+    }
+
+    #[test]
+    fn drops_exactly_once() {
+        let drops = Arc::new(AtomicUsize::new(0));
+
+        // Dropped with the message
+        drop(Message::new(Counted(drops.clone())));
+        drop(Message::new(Large(Counted(drops.clone()), [0; 8])));
+        assert_eq!(drops.load(Ordering::SeqCst), 2);
+
+        // Dropped by whoever took it, not by the message
+        let mut inline = Message::new(Counted(drops.clone()));
+        let mut boxed = Message::new(Large(Counted(drops.clone()), [0; 8]));
+        let taken = (inline.take::<Counted>(), boxed.take::<Large>());
+        drop(inline);
+        drop(boxed);
+        assert_eq!(drops.load(Ordering::SeqCst), 2);
+        drop(taken);
+        assert_eq!(drops.load(Ordering::SeqCst), 4);
+
+        // Dropped with the last shared message
+        let mut msg = Message::new(Counted(drops.clone()));
+        let shared = msg.share::<Counted>().unwrap();
+        drop(msg);
+        assert_eq!(drops.load(Ordering::SeqCst), 4);
+        drop(shared);
+        assert_eq!(drops.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn shared_without_cloning() {
+        let (_, mut msg): (_, Message) = Val::into_msg(Val { value: 333 }).unwrap();
+        let mut shared = msg.share::<Val>().unwrap();
+
+        let first: *const Val = msg.borrow::<Val>().unwrap();
+        let second: *const Val = shared.borrow::<Val>().unwrap();
+        assert_eq!(first, second);
+
+        // Copy on write, the last holder gets the value itself
+        assert_eq!(msg.take_or_clone::<Val>().map(|x| x.value), Some(333));
+        assert_eq!(msg.borrow::<Val>().map(|x| x.value), None);
+        assert_eq!(shared.take_or_clone::<Val>().map(|x| x.value), Some(333));
+    }
 }
@@ -1,4 +1,17 @@
+mod binary;
 mod json;
 mod message;
-pub use json::{JSONMessage, Typed};
+pub use binary::BinaryMessage;
+pub use json::{JSONMessage, Local, MessageError, Typed};
 pub use message::Message;
+
+use serde_json::Value;
+
+/// Messages that can describe what they carry, see modules::wiretap
+pub trait Inspect {
+    /// Name of the carried type
+    fn type_name(&self) -> &str;
+
+    /// The carried value, if this message is serialized
+    fn body(&self) -> Option<Value>;
+}
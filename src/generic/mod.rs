@@ -1,37 +1,95 @@
 use futures::channel::mpsc;
 
 use std::any;
+use std::fmt::Debug;
 use std::hash::Hash;
 use std::marker::PhantomData;
 
+use tracing::Span;
+
 mod message;
-pub use self::message::{JSONMessage, Message, Typed};
+pub use self::message::{
+    BinaryMessage, Inspect, JSONMessage, Local, Message, MessageError, Typed,
+};
 mod broker;
 mod link;
 mod reactor;
+mod runtime;
 mod types;
 pub use broker::BrokerHandle;
+pub use runtime::{AsyncStd, LocalExecutor, LocalRunner, Runtime, Spawner};
 
-pub use self::link::{Link, LinkHandle, LinkParams};
-pub use self::reactor::{CoreParams, Reactor, ReactorHandle, ReactorState, TargetReactor};
+pub use self::link::{
+    goto, Direction, Goto, Link, LinkHandle, LinkParams, Phase, ProtocolParams, Receives, SideA,
+    SideB, Tap,
+};
+pub use self::reactor::{
+    CloseReason, CoreParams, Reactor, ReactorHandle, ReactorState, ReactorStats, TargetReactor,
+    DEFAULT_BUDGET,
+};
 
 // ! Just some types to make things organised
 pub use self::types::ReactorID;
 
 // pub struct FunctionHandler<F, S, R, T, M>
 
-pub fn i_to_e<S, T: Clone + 'static + IntoMessage<any::TypeId, Message>>(
-) -> impl 'static + Fn(&mut S, &mut LinkHandle<any::TypeId, Message>, &T) -> () {
-    |_, handle, t| {
-        handle.send_message(t.clone());
+/// Forwards every T from the reactor over the link
+pub fn i_to_e<S, T, K, M>() -> Forward<S, T, K, M> {
+    Forward {
+        target: None,
+        pd: PhantomData,
+    }
+}
+
+/// Forwards every T from the link to target
+pub fn e_to_i<S, T, K, M>(target: TargetReactor) -> Forward<S, T, K, M> {
+    Forward {
+        target: Some(target),
+        pd: PhantomData,
+    }
+}
+
+///
+/// Link handler that passes a T on, see i_to_e and e_to_i
+/// When M can share its value the same value is passed on, so broadcasting
+/// to many links does not clone it. Otherwise T is cloned.
+///
+pub struct Forward<S, T, K, M> {
+    target: Option<TargetReactor>,
+    pd: PhantomData<fn(S, T, K, M)>,
+}
+
+impl<S, T, K, M> From<Forward<S, T, K, M>> for (K, Forward<S, T, K, M>)
+where
+    M: Carrier<K, T>,
+{
+    fn from(forward: Forward<S, T, K, M>) -> Self {
+        (M::key(), forward)
     }
 }
 
-pub fn e_to_i<S, T: Clone + 'static + IntoMessage<any::TypeId, Message>>(
-    target: TargetReactor,
-) -> impl 'static + Fn(&mut S, &mut LinkHandle<any::TypeId, Message>, &T) -> () {
-    move |_, handle, t| {
-        handle.send_internal(t.clone(), target);
+impl<'a, S, T, K, M> Handler<S, LinkHandle<'a, K, M>, (&K, &mut M)> for Forward<S, T, K, M>
+where
+    T: 'static + Send + Sync + Clone,
+    M: Carrier<K, T>,
+{
+    fn handle(&mut self, _: &mut S, handle: &mut LinkHandle<'a, K, M>, msg: (&K, &mut M)) {
+        let (key, message) = msg;
+        let forwarded = match M::share(message) {
+            Some(shared) => Some((M::key(), shared)),
+            // External messages reach one link only, nothing else needs them
+            None if self.target.is_some() => M::take(key, message).and_then(M::into_msg),
+            None => M::from_msg(key, message)
+                .cloned()
+                .and_then(|item| M::into_msg(item)),
+        };
+
+        if let Some((key, message)) = forwarded {
+            match self.target {
+                Some(target) => handle.forward_internal(key, message, target),
+                None => handle.forward_message(key, message),
+            }
+        }
     }
 }
 
@@ -67,18 +125,26 @@ pub enum LinkOperation<'a, K, M> {
     InternalMessage(&'a K, &'a mut M),
     ExternalMessage(&'a K, &'a mut M),
     Close(),
+    Tap(Option<Tap<K, M>>),
 }
 
 ///
 /// The actual messages that are sent
 /// These get consumed by the reactors
 ///
+/// Messages carry the span they were sent from, the receiving reactor handles them
+/// in a child of that span, so one message can be followed through every reactor.
+///
 pub enum Operation<K, M> {
-    InternalMessage(K, M, TargetReactor),
-    ExternalMessage(ReactorID, K, M),
+    InternalMessage(K, M, TargetReactor, Span),
+    ExternalMessage(ReactorID, K, M, Span),
     Close(),
     OpenLink(ReactorID, LinkSpawner<K, M>),
     CloseLink(ReactorID),
+    /// A reactor spawned with spawn_child stopped
+    ChildClosed(ReactorID, CloseReason),
+    /// Sets or removes the tap on the link to this reactor
+    Tap(ReactorID, Option<Tap<K, M>>),
 }
 
 pub trait FromMessage<K, M>
@@ -92,8 +158,56 @@ pub trait IntoMessage<K, M> {
     fn into_msg(self) -> Option<(K, M)>;
 }
 
+///
+/// A message type M that can carry a T, tagged with key K
+/// Implemented on M, so a bound on M can bundle many message types at once
+/// (supertraits are implied, bounds on other types are not)
+///
+pub trait Carrier<K, T>: Sized {
+    fn key() -> K;
+    fn from_msg<'a>(key: &K, msg: &'a mut Self) -> Option<&'a T>;
+    fn into_msg(t: T) -> Option<(K, Self)>;
+
+    /// Another message holding the same value without cloning it, if this message type can
+    fn share(_msg: &mut Self) -> Option<Self>
+    where
+        T: Send + Sync,
+    {
+        None
+    }
+
+    /// Moves the T out of msg, only cloning it when it is still shared with other messages
+    fn take(key: &K, msg: &mut Self) -> Option<T>
+    where
+        T: Clone + Send + Sync,
+    {
+        Self::from_msg(key, msg).cloned()
+    }
+}
+
+impl<T, K, M: Carrier<K, T>> FromMessage<K, M> for T {
+    fn from_msg<'a>(key: &K, msg: &'a mut M) -> Option<&'a T> {
+        M::from_msg(key, msg)
+    }
+}
+
+impl<T, K, M: Carrier<K, T>> IntoMessage<K, M> for T {
+    fn into_msg(self) -> Option<(K, M)> {
+        M::into_msg(self)
+    }
+}
+
+/// Everything a key has to be to run reactors with it
+pub trait KeyType: 'static + Send + Eq + Hash + Unpin + Debug {}
+impl<K: 'static + Send + Eq + Hash + Unpin + Debug> KeyType for K {}
+
 pub trait Key<K> {
     fn key() -> K;
+
+    /// Version of this message type, messages with another version are rejected
+    fn version() -> u32 {
+        0
+    }
 }
 
 impl<T: 'static> Key<any::TypeId> for T {
@@ -114,7 +228,7 @@ where
     pub fn send<T: IntoMessage<K, M>>(&self, from: ReactorID, msg: T) -> Option<()> {
         let (k, m) = msg.into_msg()?;
         self.sender
-            .unbounded_send(Operation::ExternalMessage(from, k, m))
+            .unbounded_send(Operation::ExternalMessage(from, k, m, Span::current()))
             .ok()?;
 
         Some(())
@@ -143,7 +257,7 @@ pub fn receiver_handle<K, M>(
 ) -> impl Stream<Item = Option<(ReactorID, K, M)>> {
     inner.filter_map(move |item| async {
         match item {
-            Operation::ExternalMessage(id, k, m) => Some(Some((id, k, m))),
+            Operation::ExternalMessage(id, k, m, _) => Some(Some((id, k, m))),
             _ => Some(None),
             // _ => None,
         }
@@ -186,12 +300,62 @@ where
     F: 'static + Send + Fn(&mut S, &mut R, &T) -> (),
     S: 'static + Send,
     R: 'static + Send,
-    T: 'static + Send + Key<K>,
-    M: 'static + Send,
+    T: 'static + Send,
+    M: 'static + Send + Carrier<K, T>,
     K: 'static + Send,
 {
     fn into(self) -> (K, Self) {
-        (T::key(), self)
+        (M::key(), self)
+    }
+}
+
+///
+/// OwnedHandler makes a Handler from a function taking the message by value
+///
+/// Only reactors get owned handlers, they are the final consumer of a message:
+/// with TargetReactor::All every link handles the message before the reactor does,
+/// and links only borrow or share it. The value is moved out of the message,
+/// it is only cloned when other messages still share it.
+///
+pub struct OwnedHandler<F, S, R, T, M> {
+    phantom: PhantomData<fn(S, R, T, M)>,
+    function: F,
+}
+
+impl<F, S, R, T, M> OwnedHandler<F, S, R, T, M>
+where
+    F: 'static + Send + Fn(&mut S, &mut R, T),
+{
+    pub fn from(function: F) -> Self {
+        Self {
+            phantom: PhantomData,
+            function,
+        }
+    }
+}
+
+impl<F, S, R, T, M, K> From<OwnedHandler<F, S, R, T, M>> for (K, OwnedHandler<F, S, R, T, M>)
+where
+    M: Carrier<K, T>,
+{
+    fn from(handler: OwnedHandler<F, S, R, T, M>) -> Self {
+        (M::key(), handler)
+    }
+}
+
+impl<'a, K, F, S, T, M> Handler<S, ReactorHandle<'a, K, M>, (&K, &mut M)>
+    for OwnedHandler<F, S, ReactorHandle<'_, K, M>, T, M>
+where
+    F: 'static + Send + for<'b> Fn(&mut S, &mut ReactorHandle<'b, K, M>, T),
+    T: 'static + Clone + Send + Sync,
+    M: Carrier<K, T>,
+{
+    fn handle(&mut self, state: &mut S, handle: &mut ReactorHandle<'a, K, M>, msg: (&K, &mut M)) {
+        let (key, message) = msg;
+        match M::take(key, message) {
+            Some(item) => (self.function)(state, handle, item),
+            None => error!("No {} found in message, dropping it", any::type_name::<T>()),
+        }
     }
 }
 
@@ -222,9 +386,10 @@ where
         msg: (&K, &mut M),
     ) {
         let (key, message) = msg;
-        T::from_msg(key, message)
-            .map(|item| (self.function)(state, handle, &item))
-            .expect("No message found at pointer location");
+        match T::from_msg(key, message) {
+            Some(item) => (self.function)(state, handle, item),
+            None => error!("No {} found in message, dropping it", any::type_name::<T>()),
+        }
     }
 }
 
@@ -239,8 +404,9 @@ where
 {
     fn handle<'b>(&mut self, state: &mut S, handle: &mut LinkHandle<'b, K, M>, msg: (&K, &mut M)) {
         let (key, message) = msg;
-        T::from_msg(key, message)
-            .map(|item| (self.function)(state, handle, &item))
-            .expect("No message found at pointer location");
+        match T::from_msg(key, message) {
+            Some(item) => (self.function)(state, handle, item),
+            None => error!("No {} found in message, dropping it", any::type_name::<T>()),
+        }
     }
 }
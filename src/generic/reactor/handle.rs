@@ -1,10 +1,14 @@
 use super::InnerOp;
 use crate::generic::{
-    BrokerHandle, CoreParams, IntoMessage, LinkSpawner, Operation, ReactorID, ReactorState, Sender,
-    SenderHandle, TargetReactor,
+    BrokerHandle, CloseReason, CoreParams, IntoMessage, LinkSpawner, Operation, ReactorID,
+    ReactorState, Sender, SenderHandle, TargetReactor,
 };
 
+use futures::channel::oneshot;
+use tracing::Span;
+
 use std::collections::VecDeque;
+use std::fmt::Debug;
 use std::hash::Hash;
 
 /// Handle to the reactor, managing operation and messages
@@ -34,7 +38,7 @@ impl<'a, K, M> ReactorHandle<'a, K, M> {
 use futures::future::Future;
 impl<'a, K, M> ReactorHandle<'a, K, M>
 where
-    K: 'static + Send + Eq + Hash + Unpin,
+    K: 'static + Send + Eq + Hash + Unpin + Debug,
     M: 'static + Send,
 {
     pub fn open_link<L>(&mut self, target: ReactorID, spawner: L, cascade: bool)
@@ -69,6 +73,38 @@ where
         self.broker.spawn(params, id)
     }
 
+    ///
+    /// Spawns a reactor owned by this one, it is closed when this reactor closes
+    /// With cascade this reactor closes as well when the child stops.
+    ///
+    pub fn spawn_child<S: 'static + Send + ReactorState<K, M> + Unpin>(
+        &mut self,
+        params: CoreParams<S, K, M>,
+        id: Option<ReactorID>,
+        cascade: bool,
+    ) -> ReactorID {
+        let id = id.unwrap_or_else(ReactorID::rand);
+
+        // Watch before spawning, the child might be gone before it is polled once
+        let closed = self.broker.watch(&id);
+        let parent = self.chan.clone();
+        self.broker.runtime().spawn(async move {
+            if let Ok(reason) = closed.await {
+                // The parent may be closed already, then there is nothing to notify
+                let _ = parent.unbounded_send(Operation::ChildClosed(id, reason));
+            }
+        });
+
+        self.broker.spawn(params, Some(id));
+        self.inner_ops.push_back(InnerOp::Child(id, cascade));
+        id
+    }
+
+    /// Resolves with the reason the target reactor closed
+    pub fn watch(&self, id: &ReactorID) -> oneshot::Receiver<CloseReason> {
+        self.broker.watch(id)
+    }
+
     pub fn id(&mut self) -> &'a ReactorID {
         &self.id
     }
@@ -94,7 +130,7 @@ impl<'a, K, M> ReactorHandle<'a, K, M> {
         if let Some((id, msg)) = T::into_msg(msg) {
             if self
                 .chan
-                .unbounded_send(Operation::InternalMessage(id, msg, to))
+                .unbounded_send(Operation::InternalMessage(id, msg, to, Span::current()))
                 .is_err()
             {
                 trace!("Internal reactor is already closed, nothing to do");
@@ -3,11 +3,13 @@ mod params;
 mod reactor;
 
 pub use handle::ReactorHandle;
-pub use params::CoreParams;
+pub use params::{CoreParams, DEFAULT_BUDGET};
 pub use reactor::{Reactor, ReactorState};
 
 use super::{LinkSpawner, ReactorID};
 
+use std::sync::atomic::{AtomicU64, Ordering};
+
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub enum TargetReactor {
     All,
@@ -16,8 +18,43 @@ pub enum TargetReactor {
     Link(ReactorID),
 }
 
+/// Why a reactor stopped, reported to everyone watching it
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum CloseReason {
+    Closed,
+    Panicked(String),
+}
+
+/// Counters of a reactor, see BrokerHandle::stats
+#[derive(Debug, Default)]
+pub struct ReactorStats {
+    messages: AtomicU64,
+    budget_hits: AtomicU64,
+}
+
+impl ReactorStats {
+    /// Operations handled so far
+    pub fn messages(&self) -> u64 {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    /// How often the reactor yielded because it used up its budget
+    pub fn budget_hits(&self) -> u64 {
+        self.budget_hits.load(Ordering::Relaxed)
+    }
+
+    fn handled(&self) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn hit_budget(&self) {
+        self.budget_hits.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 /// Inner op for reactors
 pub enum InnerOp<K, M> {
     OpenLink(ReactorID, LinkSpawner<K, M>, bool),
     CloseLink(ReactorID),
+    Child(ReactorID, bool),
 }
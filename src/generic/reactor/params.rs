@@ -6,15 +6,19 @@ use std::hash::Hash;
 
 type HandlersMap<S, K, M> =
     HashMap<K, Box<dyn for<'a> Handler<S, ReactorHandle<'a, K, M>, (&'a K, &'a mut M)> + Send>>;
+/// Messages a reactor handles in one poll before it yields, unless set with CoreParams::budget
+pub const DEFAULT_BUDGET: usize = 128;
+
 /// Builder pattern for constructing reactors
 pub struct CoreParams<S, K, M> {
     state: S,
     handlers: HandlersMap<S, K, M>,
+    budget: usize,
 }
 
 impl<S, K, M> CoreParams<S, K, M> {
-    pub fn consume(self) -> (S, HandlersMap<S, K, M>) {
-        (self.state, self.handlers)
+    pub fn consume(self) -> (S, HandlersMap<S, K, M>, usize) {
+        (self.state, self.handlers, self.budget)
     }
 }
 
@@ -27,9 +31,17 @@ where
         CoreParams {
             state,
             handlers: HashMap::new(),
+            budget: DEFAULT_BUDGET,
         }
     }
 
+    /// Handle at most budget messages per poll, then let other futures on the pool run
+    pub fn budget(mut self, budget: usize) -> Self {
+        assert!(budget > 0, "a reactor needs a budget of at least one message");
+        self.budget = budget;
+        self
+    }
+
     pub fn handler<H, J>(mut self, handler: H) -> Self
     where
         H: Into<(K, J)>,
@@ -1,13 +1,18 @@
 use super::*;
 use crate::generic::{
     BrokerHandle, Handler, LinkOperation, LinkSpawner, Operation, ReactorID, Receiver, Sender,
+    Tap,
 };
 use crate::graph;
 
 use tracing::{instrument, Span};
 
+use std::any::Any;
 use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
 use std::hash::Hash;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
 
 use futures::stream::Stream;
 use futures::task::{Context, Poll};
@@ -18,10 +23,15 @@ use std::pin::Pin;
 /// This does not borrow the entire Reactor like a function would
 macro_rules! reactorHandle {
     ($e:expr) => {
-        ReactorHandle::new(&$e.channels.0, &$e.id, &mut $e.inner_ops, &mut $e.broker);
+        ReactorHandle::new(&$e.channels.0, &$e.id, &mut $e.inner_ops, &mut $e.broker)
     };
 }
 
+/// Links get a span per message, so they are part of the trace of that message
+fn link_span(source: &ReactorID, target: &ReactorID) -> Span {
+    trace_span!("Link", %target, %source)
+}
+
 /// Gives the option for an init function on a reactor
 pub trait ReactorState<K, M> {
     const NAME: &'static str;
@@ -60,14 +70,21 @@ where
                         &'a mut LinkOperation<'a, K, M>,
                     > + Send,
             >,
-            Span,
             bool,
         ),
     >,
 
+    /// Reactors spawned with spawn_child, with their cascade flag
+    children: HashMap<ReactorID, bool>,
+
     channels: (Sender<K, M>, Receiver<K, M>),
 
     inner_ops: VecDeque<InnerOp<K, M>>,
+
+    close_reason: CloseReason,
+
+    budget: usize,
+    stats: Arc<ReactorStats>,
 }
 
 impl<S, K, M> Reactor<S, K, M>
@@ -83,18 +100,26 @@ where
         params: CoreParams<S, K, M>,
         channels: (Sender<K, M>, Receiver<K, M>),
     ) -> Self {
-        let (state, msg_handlers) = params.consume();
+        let (state, msg_handlers, budget) = params.consume();
         Reactor {
             id,
             broker,
             state,
             msg_handlers,
             links: HashMap::new(),
+            children: HashMap::new(),
             channels,
             inner_ops: VecDeque::new(),
+            close_reason: CloseReason::Closed,
+            budget,
+            stats: Arc::new(ReactorStats::default()),
         }
     }
 
+    pub fn stats(&self) -> Arc<ReactorStats> {
+        self.stats.clone()
+    }
+
     /// Returns a handle to the reactor
     pub fn get_handle<'a>(&'a mut self) -> ReactorHandle<'a, K, M> {
         reactorHandle!(self)
@@ -105,7 +130,7 @@ where
     /// First looking up his own internal message handler for that message
     /// Then letting all links handle that message
     #[instrument(skip(self, msg, id))]
-    fn handle_internal_msg(&mut self, id: K, mut msg: M, target: TargetReactor) {
+    fn handle_internal_msg(&mut self, id: &K, mut msg: M, target: TargetReactor) {
         let mut handle = reactorHandle!(self);
 
         match target {
@@ -113,18 +138,19 @@ where
                 let mut found = false;
                 let mut state = ();
 
-                for (_, (handler, span, _)) in self.links.iter_mut() {
+                for (target, (handler, _)) in self.links.iter_mut() {
+                    let span = link_span(&self.id, target);
                     let _enter = span.enter();
                     handler.handle(
                         &mut state,
                         &mut handle,
-                        &mut LinkOperation::InternalMessage(&id, &mut msg),
+                        &mut LinkOperation::InternalMessage(id, &mut msg),
                     );
                     found = true;
                 }
-                if let Some(h) = self.msg_handlers.get_mut(&id) {
+                if let Some(h) = self.msg_handlers.get_mut(id) {
                     found = true;
-                    h.handle(&mut self.state, &mut handle, (&id, &mut msg));
+                    h.handle(&mut self.state, &mut handle, (id, &mut msg));
                 }
 
                 if !found {
@@ -135,12 +161,13 @@ where
                 let mut found = false;
                 let mut state = ();
 
-                for (_, (handler, span, _)) in self.links.iter_mut() {
+                for (target, (handler, _)) in self.links.iter_mut() {
+                    let span = link_span(&self.id, target);
                     let _enter = span.enter();
                     handler.handle(
                         &mut state,
                         &mut handle,
-                        &mut LinkOperation::InternalMessage(&id, &mut msg),
+                        &mut LinkOperation::InternalMessage(id, &mut msg),
                     );
                     found = true;
                 }
@@ -150,20 +177,21 @@ where
                 }
             }
             TargetReactor::Reactor => {
-                if let Some(h) = self.msg_handlers.get_mut(&id) {
-                    h.handle(&mut self.state, &mut handle, (&id, &mut msg));
+                if let Some(h) = self.msg_handlers.get_mut(id) {
+                    h.handle(&mut self.state, &mut handle, (id, &mut msg));
                 } else {
                     trace!("No handler found!");
                 }
             }
             TargetReactor::Link(target) => {
-                if let Some((handler, span, _)) = self.links.get_mut(&target) {
+                if let Some((handler, _)) = self.links.get_mut(&target) {
+                    let span = link_span(&self.id, &target);
                     let _enter = span.enter();
                     trace!("Sending to {:?}", target);
                     handler.handle(
                         &mut (),
                         &mut handle,
-                        &mut LinkOperation::InternalMessage(&id, &mut msg),
+                        &mut LinkOperation::InternalMessage(id, &mut msg),
                     );
                 } else {
                     trace!("No handler found!");
@@ -177,15 +205,16 @@ where
     /// This message is sent by a link to this reactor
     /// Look up the corresponding link and letting him/her handle the message
     #[instrument(skip(self, id, msg))]
-    fn handle_external_msg(&mut self, origin: ReactorID, id: K, mut msg: M) {
+    fn handle_external_msg(&mut self, origin: ReactorID, id: &K, mut msg: M) {
         let mut handle = reactorHandle!(self);
 
-        let mut m = LinkOperation::ExternalMessage(&id, &mut msg);
+        let mut m = LinkOperation::ExternalMessage(id, &mut msg);
+        let span = link_span(&self.id, &origin);
 
         if self
             .links
             .get_mut(&origin)
-            .map(|(handler, span, _)| {
+            .map(|(handler, _)| {
                 let _enter = span.enter();
                 handler.handle(&mut (), &mut handle, &mut m)
             })
@@ -204,14 +233,7 @@ where
 
         let tx = self.broker.get(&target);
         let handles = (self.channels.0.clone(), tx, self.id, target);
-        self.links.insert(
-            target,
-            (
-                spawner(handles),
-                trace_span!("Link", %target, source = %self.id),
-                cascade,
-            ),
-        );
+        self.links.insert(target, (spawner(handles), cascade));
     }
 
     /// Closes a link to the target reactor
@@ -221,7 +243,8 @@ where
 
         let mut handle = reactorHandle!(self);
 
-        if let Some((mut link, span, cascade)) = self.links.remove(&target) {
+        if let Some((mut link, cascade)) = self.links.remove(&target) {
+            let span = link_span(&self.id, &target);
             let _enter = span.enter();
             trace!(%target, source = %self.id, "Close link");
 
@@ -236,12 +259,63 @@ where
         }
     }
 
+    /// Takes ownership of a reactor spawned with spawn_child
+    fn add_child(&mut self, child: ReactorID, cascade: bool) {
+        graph::add_child(&self.id, &child);
+        trace!(%child, parent = %self.id, cascade, "Add child");
+        self.children.insert(child, cascade);
+    }
+
+    /// A child stopped, with cascade its parent stops as well
+    #[instrument(skip(self))]
+    fn child_closed(&mut self, child: ReactorID, reason: CloseReason) {
+        // The child might stop before its inner op got applied
+        self.apply_inner_ops();
+
+        if let Some(cascade) = self.children.remove(&child) {
+            graph::remove_child(&self.id, &child);
+            trace!(%child, parent = %self.id, ?reason, "Child closed");
+
+            if cascade {
+                info!(%child, parent = %self.id, ?reason, "Closing parent of closed child");
+                if self.channels.0.unbounded_send(Operation::Close()).is_err() {
+                    info!("Couldn't send close operation");
+                }
+            }
+        }
+    }
+
+    /// Sets or removes the tap on the link to target
+    #[instrument(skip(self, tap))]
+    fn tap_link(&mut self, target: ReactorID, tap: Option<Tap<K, M>>) {
+        // The link might be opened by the handler that came before
+        self.apply_inner_ops();
+
+        let mut handle = reactorHandle!(self);
+        if let Some((link, _)) = self.links.get_mut(&target) {
+            let span = link_span(&self.id, &target);
+            let _enter = span.enter();
+            link.handle(&mut (), &mut handle, &mut LinkOperation::Tap(tap));
+        } else {
+            info!(%target, source = %self.id, "No link to tap");
+        }
+    }
+
     #[instrument(skip(self))]
     fn close(&mut self) {
+        self.apply_inner_ops();
+        for (child, _) in self.children.drain() {
+            graph::remove_child(&self.id, &child);
+            if self.broker.get(&child).unbounded_send(Operation::Close()).is_err() {
+                trace!(%child, "Child is already closed");
+            }
+        }
+
         let mut handle = reactorHandle!(self);
         let mut state = ();
 
-        for (_, (handler, span, _)) in self.links.iter_mut() {
+        for (target, (handler, _)) in self.links.iter_mut() {
+            let span = link_span(&self.id, target);
             let _enter = span.enter();
             handler.handle(&mut state, &mut handle, &mut LinkOperation::Close());
         }
@@ -249,6 +323,24 @@ where
         // Stop Future
         self.channels.1.close();
     }
+
+    /// Called when a handler panicked, the state of this reactor cannot be trusted anymore
+    /// So close every link, notifying the other side, and stop
+    fn recover(&mut self, payload: Box<dyn Any + Send>, msg_type: &dyn Debug) {
+        let msg = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("Unknown panic"));
+
+        error!(name = S::NAME, id = %self.id, ?msg_type, %msg, "Reactor panicked");
+        self.close_reason = CloseReason::Panicked(msg);
+
+        if panic::catch_unwind(AssertUnwindSafe(|| self.close())).is_err() {
+            error!(name = S::NAME, id = %self.id, "Reactor panicked while closing");
+            self.channels.1.close();
+        }
+    }
 }
 
 impl<S, K, M> Reactor<S, K, M>
@@ -256,64 +348,444 @@ where
     K: Hash + Eq,
     S: ReactorState<K, M>,
 {
-    /// Initializes the spawned reactor
+    /// Initializes the spawned reactor, a panicking init closes it like a panicking handler
     pub fn init(&mut self) {
-        let mut handle = reactorHandle!(self);
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut handle = reactorHandle!(self);
+
+            self.state.init(&mut handle);
 
-        self.state.init(&mut handle);
+            self.apply_inner_ops();
+        }));
 
+        if let Err(e) = res {
+            self.recover(e, &"Init");
+        }
+    }
+
+    /// Opens and closes the links that were requested through the handle
+    fn apply_inner_ops(&mut self) {
         while let Some(op) = self.inner_ops.pop_back() {
             match op {
                 InnerOp::OpenLink(id, spawner, cascade) => self.open_link(id, spawner, cascade),
                 InnerOp::CloseLink(id) => self.close_link(id),
+                InnerOp::Child(id, cascade) => self.add_child(id, cascade),
             }
         }
     }
 }
 
+///
+/// The span a message is handled in, a child of the span it was sent from
+/// Messages sent from outside any span stay under the span of this reactor.
+///
+fn message_span<S, K, M>(id: &ReactorID, key: &K, cause: &Span) -> Span
+where
+    S: ReactorState<K, M>,
+    K: Debug,
+{
+    let parent = cause.id().or_else(|| Span::current().id());
+    trace_span!(parent: parent, "Message", reactor = S::NAME, %id, ?key)
+}
+
 /// Reactors get spawned with tokio, they only read from their channel and act on the messages
 /// They reduce over an OperationStream
 impl<S, K, M> Future for Reactor<S, K, M>
 where
     S: Unpin + ReactorState<K, M>,
-    K: Hash + Eq + 'static + Unpin,
+    K: Hash + Eq + 'static + Unpin + Debug,
 {
-    type Output = ();
+    type Output = CloseReason;
 
     /// Handles on message at a time, clearing the inner ops queue every time
     /// This opens/closes links and has to be up to date at all times
+    ///
+    /// After budget messages the reactor clears the inner ops queue and yields,
+    /// so a flooded reactor does not starve the other futures on its thread
+    ///
+    /// Every operation is handled inside catch_unwind, a panicking handler closes the reactor
     fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
         let this = Pin::into_inner(self);
+        let mut handled = 0;
+
+        // Its init panicked, recover closed it already
+        if let CloseReason::Panicked(_) = this.close_reason {
+            return Poll::Ready(this.close_reason.clone());
+        }
 
         loop {
+            if handled == this.budget {
+                this.apply_inner_ops();
+                this.stats.hit_budget();
+                ctx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+
             match Stream::poll_next(Pin::new(&mut this.channels.1), ctx) {
                 Poll::Ready(v) => match v {
                     None => break,
-                    Some(item) => match item {
-                        Operation::InternalMessage(id, msg, target) => {
-                            this.handle_internal_msg(id, msg, target)
-                        }
-                        Operation::ExternalMessage(target, id, msg) => {
-                            this.handle_external_msg(target, id, msg)
+                    Some(item) => {
+                        handled += 1;
+                        this.stats.handled();
+
+                        let res = match item {
+                            Operation::InternalMessage(id, msg, target, cause) => {
+                                let span = message_span::<S, K, M>(&this.id, &id, &cause);
+                                let _enter = span.enter();
+                                panic::catch_unwind(AssertUnwindSafe(|| {
+                                    this.handle_internal_msg(&id, msg, target)
+                                }))
+                                .map_err(|e| this.recover(e, &id))
+                            }
+                            Operation::ExternalMessage(target, id, msg, cause) => {
+                                let span = message_span::<S, K, M>(&this.id, &id, &cause);
+                                let _enter = span.enter();
+                                panic::catch_unwind(AssertUnwindSafe(|| {
+                                    this.handle_external_msg(target, &id, msg)
+                                }))
+                                .map_err(|e| this.recover(e, &id))
+                            }
+                            Operation::CloseLink(id) => {
+                                panic::catch_unwind(AssertUnwindSafe(|| this.close_link(id)))
+                                    .map_err(|e| this.recover(e, &"CloseLink"))
+                            }
+                            Operation::ChildClosed(id, reason) => {
+                                panic::catch_unwind(AssertUnwindSafe(|| {
+                                    this.child_closed(id, reason)
+                                }))
+                                .map_err(|e| this.recover(e, &"ChildClosed"))
+                            }
+                            Operation::Tap(target, tap) => {
+                                panic::catch_unwind(AssertUnwindSafe(|| this.tap_link(target, tap)))
+                                    .map_err(|e| this.recover(e, &"Tap"))
+                            }
+                            Operation::Close() => {
+                                panic::catch_unwind(AssertUnwindSafe(|| this.close()))
+                                    .map_err(|e| this.recover(e, &"Close"))
+                            }
+                            Operation::OpenLink(target, spawner) => {
+                                panic::catch_unwind(AssertUnwindSafe(|| {
+                                    this.open_link(target, spawner, false)
+                                }))
+                                .map_err(|e| this.recover(e, &"OpenLink"))
+                            }
+                        };
+
+                        if res.is_err() {
+                            break;
                         }
-                        Operation::CloseLink(id) => this.close_link(id),
-                        Operation::Close() => this.close(),
-                        _ => unimplemented!(),
-                    },
+                    }
                 },
                 Poll::Pending => {
-                    while let Some(op) = this.inner_ops.pop_back() {
-                        match op {
-                            InnerOp::OpenLink(id, spawner, cascade) => this.open_link(id, spawner, cascade),
-                            InnerOp::CloseLink(id) => this.close_link(id),
-                        }
-                    }
+                    this.apply_inner_ops();
                     return Poll::Pending;
                 }
             }
         }
 
-        info!(name = S::NAME, id = %this.id, "Reactor finished");
-        return Poll::Ready(());
+        info!(name = S::NAME, id = %this.id, reason = ?this.close_reason, "Reactor finished");
+        Poll::Ready(this.close_reason.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generic::*;
+
+    use futures::channel::oneshot;
+    use futures::executor::{block_on, ThreadPool};
+    use tracing::{span, Span};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    use std::any;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    struct Boom;
+
+    struct Panicky(ReactorID);
+    impl Panicky {
+        fn boom(&mut self, _: &mut ReactorHandle<any::TypeId, Message>, _: &Boom) {
+            panic!("boom");
+        }
+    }
+
+    impl ReactorState<any::TypeId, Message> for Panicky {
+        const NAME: &'static str = "Panicky";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+            handle.open_link(self.0, LinkParams::new(()), false);
+            handle.send_internal(Boom, TargetReactor::Reactor);
+        }
+    }
+
+    struct Peer(ReactorID);
+    impl ReactorState<any::TypeId, Message> for Peer {
+        const NAME: &'static str = "Peer";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+            handle.open_link(self.0, LinkParams::new(()), true);
+        }
+    }
+
+    #[test]
+    fn panic_closes_reactor_and_links() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::new(pool);
+
+        let panicky_id = ReactorID::rand();
+        let peer_id = ReactorID::rand();
+        let panicky_closed = broker.watch(&panicky_id);
+        let peer_closed = broker.watch(&peer_id);
+
+        broker.spawn(CoreParams::new(Peer(panicky_id)), Some(peer_id));
+        broker.spawn(
+            CoreParams::new(Panicky(peer_id)).handler(FunctionHandler::from(Panicky::boom)),
+            Some(panicky_id),
+        );
+
+        assert_eq!(
+            block_on(panicky_closed),
+            Ok(CloseReason::Panicked(String::from("boom")))
+        );
+        assert_eq!(block_on(peer_closed), Ok(CloseReason::Closed));
+
+        // Watching after the fact still tells why
+        assert_eq!(
+            block_on(broker.watch(&panicky_id)),
+            Ok(CloseReason::Panicked(String::from("boom")))
+        );
+    }
+
+    struct Broken(ReactorID);
+    impl ReactorState<any::TypeId, Message> for Broken {
+        const NAME: &'static str = "Broken";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+            handle.open_link(self.0, LinkParams::new(()), false);
+            panic!("broken");
+        }
+    }
+
+    #[test]
+    fn panicking_init_closes_reactor() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::new(pool);
+
+        let broken_id = ReactorID::rand();
+        let closed = broker.watch(&broken_id);
+
+        // The panic stays inside the reactor instead of unwinding into spawn
+        broker.spawn(CoreParams::new(Broken(ReactorID::rand())), Some(broken_id));
+
+        assert_eq!(
+            block_on(closed),
+            Ok(CloseReason::Panicked(String::from("broken")))
+        );
+    }
+
+    struct Quitter;
+    impl ReactorState<any::TypeId, Message> for Quitter {
+        const NAME: &'static str = "Quitter";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+            handle.close();
+        }
+    }
+
+    struct Parent(ReactorID, ReactorID);
+    impl ReactorState<any::TypeId, Message> for Parent {
+        const NAME: &'static str = "Parent";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+            handle.spawn_child(CoreParams::new(()), Some(self.0), false);
+            handle.spawn_child(CoreParams::new(Quitter), Some(self.1), true);
+        }
+    }
+
+    #[test]
+    fn children_follow_their_parent() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::new(pool);
+
+        let parent_id = ReactorID::rand();
+        let idle_id = ReactorID::rand();
+        let parent_closed = broker.watch(&parent_id);
+        let idle_closed = broker.watch(&idle_id);
+
+        // The quitter takes its parent down, which takes the idle child down
+        broker.spawn(
+            CoreParams::new(Parent(idle_id, ReactorID::rand())),
+            Some(parent_id),
+        );
+
+        assert_eq!(block_on(parent_closed), Ok(CloseReason::Closed));
+        assert_eq!(block_on(idle_closed), Ok(CloseReason::Closed));
+    }
+
+    /// Records the name and parent of every span
+    type Spans = HashMap<u64, (&'static str, Option<u64>)>;
+    #[derive(Clone, Default)]
+    struct Parents(Arc<Mutex<Spans>>);
+
+    impl<T> Layer<T> for Parents
+    where
+        T: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, T>) {
+            let parent = ctx.span(id).and_then(|s| s.parent()).map(|p| p.id().into_u64());
+            let name = attrs.metadata().name();
+            self.0.lock().unwrap().insert(id.into_u64(), (name, parent));
+        }
+    }
+
+    struct Ping;
+    struct Pong;
+
+    struct Tracer(Mutex<Option<oneshot::Sender<Option<u64>>>>);
+    impl Tracer {
+        fn ping(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, _: &Ping) {
+            handle.send_internal(Pong, TargetReactor::Reactor);
+        }
+
+        fn pong(&mut self, _: &mut ReactorHandle<any::TypeId, Message>, _: &Pong) {
+            if let Some(tx) = self.0.lock().unwrap().take() {
+                let _ = tx.send(Span::current().id().map(|id| id.into_u64()));
+            }
+        }
+    }
+
+    impl ReactorState<any::TypeId, Message> for Tracer {
+        const NAME: &'static str = "Tracer";
+    }
+
+    #[test]
+    fn messages_are_traced_to_their_cause() {
+        let parents = Parents::default();
+        let subscriber = tracing_subscriber::registry().with(parents.clone());
+
+        // Everything runs on this thread, inside the subscriber
+        let handled = tracing::subscriber::with_default(subscriber, || {
+            let (executor, runner) = LocalExecutor::new();
+            let (broker, _handle) = BrokerHandle::new(executor);
+
+            let (tx, rx) = oneshot::channel();
+            let id = broker.spawn(
+                CoreParams::new(Tracer(Mutex::new(Some(tx))))
+                    .handler(FunctionHandler::from(Tracer::ping))
+                    .handler(FunctionHandler::from(Tracer::pong)),
+                None,
+            );
+
+            let cause = trace_span!("Move");
+            let (key, msg) = Ping.into_msg().unwrap();
+            let op = Operation::InternalMessage(key, msg, TargetReactor::Reactor, cause);
+            broker.get(&id).unbounded_send(op).unwrap();
+
+            runner.run_until(rx).unwrap()
+        });
+
+        let parents = parents.0.lock().unwrap();
+        let mut names = Vec::new();
+        let mut current = handled;
+        while let Some(id) = current {
+            let (name, parent) = parents[&id];
+            names.push(name);
+            current = parent;
+        }
+
+        // Pong was sent while handling Ping, which was sent from Move
+        assert_eq!(names.last(), Some(&"Move"));
+        assert_eq!(names.iter().filter(|name| **name == "Message").count(), 2);
+    }
+
+    struct Owner {
+        sent: usize,
+        tx: Mutex<Option<oneshot::Sender<bool>>>,
+    }
+    impl Owner {
+        fn keep(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, payload: Vec<u8>) {
+            let moved = payload.as_ptr() as usize == self.sent;
+            if let Some(tx) = self.tx.lock().unwrap().take() {
+                let _ = tx.send(moved);
+            }
+            handle.close();
+        }
+    }
+
+    impl ReactorState<any::TypeId, Message> for Owner {
+        const NAME: &'static str = "Owner";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+            let payload = vec![7u8; 1024];
+            self.sent = payload.as_ptr() as usize;
+            handle.send_internal(payload, TargetReactor::All);
+        }
+    }
+
+    #[test]
+    fn owned_handler_gets_the_value() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::new(pool);
+
+        let (tx, rx) = oneshot::channel();
+        let owner = Owner {
+            sent: 0,
+            tx: Mutex::new(Some(tx)),
+        };
+        broker.spawn(CoreParams::new(owner).handler(OwnedHandler::from(Owner::keep)), None);
+
+        assert_eq!(block_on(rx), Ok(true));
+    }
+
+    struct Tick;
+
+    struct Flooder {
+        left: usize,
+        tx: Mutex<Option<oneshot::Sender<()>>>,
+    }
+    impl Flooder {
+        fn tick(&mut self, _: &mut ReactorHandle<any::TypeId, Message>, _: &Tick) {
+            self.left -= 1;
+            if self.left == 0 {
+                if let Some(tx) = self.tx.lock().unwrap().take() {
+                    let _ = tx.send(());
+                }
+            }
+        }
+    }
+
+    impl ReactorState<any::TypeId, Message> for Flooder {
+        const NAME: &'static str = "Flooder";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+            for _ in 0..self.left {
+                handle.send_internal(Tick, TargetReactor::Reactor);
+            }
+        }
+    }
+
+    #[test]
+    fn flooded_reactor_yields() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::new(pool);
+
+        let (tx, rx) = oneshot::channel();
+        let flooder = Flooder {
+            left: 1000,
+            tx: Mutex::new(Some(tx)),
+        };
+        let id = broker.spawn(
+            CoreParams::new(flooder)
+                .handler(FunctionHandler::from(Flooder::tick))
+                .budget(10),
+            None,
+        );
+
+        block_on(rx).unwrap();
+        let stats = broker.stats(&id).unwrap();
+        assert_eq!(stats.messages(), 1000);
+        assert!(stats.budget_hits() >= 99);
     }
 }
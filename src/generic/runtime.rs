@@ -0,0 +1,142 @@
+use futures::channel::mpsc;
+use futures::executor::{LocalPool, ThreadPool};
+use futures::future::{self, BoxFuture, Future, FutureExt, RemoteHandle};
+use futures::stream::StreamExt;
+use futures::task::LocalSpawnExt;
+
+use std::sync::Arc;
+
+///
+/// Something that runs futures to completion in the background
+/// Implement this to run MOZAIC on the executor your application already uses.
+///
+pub trait Spawner: Send + Sync + 'static {
+    fn spawn_boxed(&self, fut: BoxFuture<'static, ()>);
+}
+
+impl Spawner for ThreadPool {
+    fn spawn_boxed(&self, fut: BoxFuture<'static, ()>) {
+        self.spawn_ok(fut);
+    }
+}
+
+/// Spawns on the global async-std executor
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStd;
+
+impl Spawner for AsyncStd {
+    fn spawn_boxed(&self, fut: BoxFuture<'static, ()>) {
+        async_std::task::spawn(fut);
+    }
+}
+
+///
+/// Spawner for a single-threaded executor, every future runs on the thread
+/// that drives the matching LocalRunner
+///
+#[derive(Clone)]
+pub struct LocalExecutor {
+    tx: mpsc::UnboundedSender<BoxFuture<'static, ()>>,
+}
+
+/// Runs everything spawned on its LocalExecutor, on the current thread
+pub struct LocalRunner {
+    rx: mpsc::UnboundedReceiver<BoxFuture<'static, ()>>,
+}
+
+impl LocalExecutor {
+    pub fn new() -> (Self, LocalRunner) {
+        let (tx, rx) = mpsc::unbounded();
+        (LocalExecutor { tx }, LocalRunner { rx })
+    }
+}
+
+impl Spawner for LocalExecutor {
+    fn spawn_boxed(&self, fut: BoxFuture<'static, ()>) {
+        if self.tx.unbounded_send(fut).is_err() {
+            error!("Local runner is gone, dropping future");
+        }
+    }
+}
+
+impl LocalRunner {
+    /// Runs the spawned futures until fut completes
+    pub fn run_until<F: Future>(self, fut: F) -> F::Output {
+        let mut pool = LocalPool::new();
+        let spawner = pool.spawner();
+
+        let forward = self.rx.for_each(move |fut| {
+            if spawner.spawn_local(fut).is_err() {
+                error!("Local pool shut down, dropping future");
+            }
+            future::ready(())
+        });
+        if pool.spawner().spawn_local(forward).is_err() {
+            error!("Local pool shut down before it started");
+        }
+
+        pool.run_until(fut)
+    }
+}
+
+///
+/// Cheap handle to a Spawner, this is what the broker and modules hold on to
+/// Everything that is a Spawner converts into a Runtime.
+///
+#[derive(Clone)]
+pub struct Runtime {
+    spawner: Arc<dyn Spawner>,
+}
+
+impl Runtime {
+    pub fn new<S: Spawner>(spawner: S) -> Self {
+        Runtime {
+            spawner: Arc::new(spawner),
+        }
+    }
+
+    pub fn spawn<F: Future<Output = ()> + Send + 'static>(&self, fut: F) {
+        self.spawner.spawn_boxed(fut.boxed());
+    }
+
+    /// Spawns fut, the returned handle resolves with its output, dropping it cancels fut
+    pub fn spawn_with_handle<F>(&self, fut: F) -> RemoteHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send,
+    {
+        let (remote, handle) = fut.remote_handle();
+        self.spawn(remote);
+        handle
+    }
+}
+
+impl<S: Spawner> From<S> for Runtime {
+    fn from(spawner: S) -> Self {
+        Runtime::new(spawner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::channel::oneshot;
+    use futures::executor::block_on;
+    use std::thread;
+
+    #[test]
+    fn local_runs_on_this_thread() {
+        let (executor, runner) = LocalExecutor::new();
+        let runtime = Runtime::from(executor);
+
+        let (tx, rx) = oneshot::channel();
+        let handle = runtime.spawn_with_handle(async move {
+            let _ = tx.send(thread::current().id());
+            21 * 2
+        });
+
+        assert_eq!(runner.run_until(handle), 42);
+        assert_eq!(block_on(rx), Ok(thread::current().id()));
+    }
+}
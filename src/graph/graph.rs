@@ -12,6 +12,8 @@ enum EventWrapper {
     AddEdge(u64, u64),
     RemoveNode(u64),
     RemoveEdge(u64, u64),
+    AddChild(u64, u64),
+    RemoveChild(u64, u64),
 
     Conn(Sender),
 }
@@ -54,6 +56,8 @@ struct Edge {
     id: u64,
     from: u64,
     to: u64,
+    /// Ownership edges are drawn dashed, so the reactor tree stands out from the links
+    dashes: bool,
 }
 
 struct GraphState {
@@ -97,6 +101,8 @@ impl GraphState {
                         EventWrapper::AddNode(f, t) => this.add_node(f, t),
                         EventWrapper::RemoveEdge(f, t) => this.remove_edge(f, t),
                         EventWrapper::RemoveNode(t) => this.remove_node(t),
+                        EventWrapper::AddChild(f, t) => this.add_child(f, t),
+                        EventWrapper::RemoveChild(f, t) => this.remove_child(f, t),
                     }
                 } else {
                     break;
@@ -141,10 +147,19 @@ impl GraphState {
     }
 
     fn add_edge(&mut self, from: u64, to: u64) {
+        self.push_edge(from, to, false);
+    }
+
+    fn add_child(&mut self, parent: u64, child: u64) {
+        self.push_edge(parent, child, true);
+    }
+
+    fn push_edge(&mut self, from: u64, to: u64, dashes: bool) {
         let edge = Edge {
             id: self.get_new_edge_id(),
-            from: from,
-            to: to,
+            from,
+            to,
+            dashes,
         };
 
         let event = Event::Add(Add::Edge(edge.clone()));
@@ -165,8 +180,18 @@ impl GraphState {
     }
 
     fn remove_edge(&mut self, from: u64, to: u64) {
-        if let Some(id) = first_index(&self.edges, |n| n.from == from && n.to == to)
-            .map(|idx| self.edges.remove(idx).id)
+        self.drop_edge(from, to, false);
+    }
+
+    fn remove_child(&mut self, parent: u64, child: u64) {
+        self.drop_edge(parent, child, true);
+    }
+
+    fn drop_edge(&mut self, from: u64, to: u64, dashes: bool) {
+        if let Some(id) = first_index(&self.edges, |n| {
+            n.from == from && n.to == to && n.dashes == dashes
+        })
+        .map(|idx| self.edges.remove(idx).id)
         {
             let event = Event::Remove(Remove {
                 data_type: String::from("Edge"),
@@ -263,4 +288,22 @@ impl GraphLike for Graph {
             error!("Couldn't send message to graph");
         }
     }
+
+    fn add_child(&self, parent: &ReactorID, child: &ReactorID) {
+        if let Err(_) = self
+            .tx
+            .unbounded_send(EventWrapper::AddChild(**parent, **child))
+        {
+            error!("Couldn't send message to graph");
+        }
+    }
+
+    fn remove_child(&self, parent: &ReactorID, child: &ReactorID) {
+        if let Err(_) = self
+            .tx
+            .unbounded_send(EventWrapper::RemoveChild(**parent, **child))
+        {
+            error!("Couldn't send message to graph");
+        }
+    }
 }
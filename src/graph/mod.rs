@@ -1,6 +1,6 @@
 // use crate::messaging::types::ReactorID;
 use crate::generic::ReactorID;
-use std::mem;
+use std::ptr;
 use std::sync::Arc;
 
 use futures::future::Future;
@@ -22,45 +22,62 @@ pub fn set_graph<T: GraphLike + 'static>(graph: T) {
     let graph = Arc::new(graph);
 
     unsafe {
-        let _ = mem::replace(&mut GRAPH, Some(graph));
+        *ptr::addr_of_mut!(GRAPH) = Some(graph);
     }
 }
 
+/// The installed graph, read through a raw pointer so no reference to GRAPH itself is taken
+fn graph() -> Option<&'static Arc<dyn GraphLike>> {
+    unsafe { (*ptr::addr_of!(GRAPH)).as_ref() }
+}
+
 pub trait GraphLike: Send + Sync {
     fn add_node(&self, id: &ReactorID, name: &str);
     fn add_edge(&self, from: &ReactorID, to: &ReactorID);
     fn remove_node(&self, id: &ReactorID);
     fn remove_edge(&self, from: &ReactorID, to: &ReactorID);
+
+    /// Parent owns child, see ReactorHandle::spawn_child
+    fn add_child(&self, parent: &ReactorID, child: &ReactorID) {
+        self.add_edge(parent, child);
+    }
+    fn remove_child(&self, parent: &ReactorID, child: &ReactorID) {
+        self.remove_edge(parent, child);
+    }
 }
 
 pub fn add_node(id: &ReactorID, name: &str) {
-    unsafe {
-        if let Some(g) = &GRAPH {
-            g.add_node(id, name);
-        }
+    if let Some(g) = graph() {
+        g.add_node(id, name);
     }
 }
 
 pub fn add_edge(from: &ReactorID, to: &ReactorID) {
-    unsafe {
-        if let Some(g) = &GRAPH {
-            g.add_edge(from, to);
-        }
+    if let Some(g) = graph() {
+        g.add_edge(from, to);
     }
 }
 
 pub fn remove_node(id: &ReactorID) {
-    unsafe {
-        if let Some(g) = &GRAPH {
-            g.remove_node(id);
-        }
+    if let Some(g) = graph() {
+        g.remove_node(id);
     }
 }
 
 pub fn remove_edge(from: &ReactorID, to: &ReactorID) {
-    unsafe {
-        if let Some(g) = &GRAPH {
-            g.remove_edge(from, to);
-        }
+    if let Some(g) = graph() {
+        g.remove_edge(from, to);
+    }
+}
+
+pub fn add_child(parent: &ReactorID, child: &ReactorID) {
+    if let Some(g) = graph() {
+        g.add_child(parent, child);
+    }
+}
+
+pub fn remove_child(parent: &ReactorID, child: &ReactorID) {
+    if let Some(g) = graph() {
+        g.remove_child(parent, child);
     }
 }
@@ -12,6 +12,7 @@ extern crate rand;
 
 extern crate serde;
 extern crate serde_json;
+extern crate rmp_serde;
 
 #[macro_use]
 extern crate tracing;
@@ -20,6 +21,12 @@ extern crate tracing_futures;
 #[macro_use]
 extern crate mozaic_derive;
 
+// Lets generated code refer to ::mozaic, also from inside this crate
+extern crate self as mozaic;
+
+// Lets generated message types derive ::mozaic::Key without depending on mozaic-derive
+pub use mozaic_derive::Key;
+
 pub mod modules;
 
 pub mod generic;
@@ -1,14 +1,25 @@
 use crate::generic::*;
 use crate::modules::types::*;
+use crate::modules::Transport;
 use crate::util::request::*;
 
+use serde::{Deserialize, Serialize};
+
 use std::any;
 use std::collections::HashMap;
+use std::marker::PhantomData;
 
-#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
 pub struct InitConnect(pub PlayerId, pub String);
 
-pub struct Aggregator {
+/// The link between a ClientController and the Aggregator of its game
+#[derive(LinkProtocol)]
+#[side(controller: PlayerMsg, Res<Connect>, InitConnect)]
+#[side(aggregator: HostMsg, Req<Connect>)]
+pub struct ClientProtocol;
+
+pub struct Aggregator<K = any::TypeId, M = Message> {
+    pd: PhantomData<fn() -> (K, M)>,
     host_id: ReactorID,
     clients: HashMap<PlayerId, ReactorID>,
 
@@ -16,25 +27,36 @@ pub struct Aggregator {
     current_requests: HashMap<UUID, HashMap<PlayerId, Option<Connect>>>,
 }
 
-impl Aggregator {
+#[handlers(key = "K", message = "M", params = "into_params")]
+impl<K: KeyType, M: Transport<K>> Aggregator<K, M> {
     pub fn params(
         host_id: ReactorID,
         clients: HashMap<PlayerId, ReactorID>,
-    ) -> CoreParams<Self, any::TypeId, Message> {
-        CoreParams::new(Aggregator {
+    ) -> CoreParams<Self, K, M> {
+        Aggregator {
+            pd: PhantomData,
             host_id,
             init_connected: clients.keys().map(|x| (*x, None)).collect(),
             clients,
             current_requests: HashMap::new(),
-        })
-        .handler(FunctionHandler::from(Self::handle_state_req))
-        .handler(FunctionHandler::from(Self::handle_conn))
-        .handler(FunctionHandler::from(Self::handle_init_connect))
+        }
+        .into_params()
     }
 
+    #[init]
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, K, M>) {
+        for client_id in self.clients.values() {
+            let params = ClientProtocol::aggregator(())
+                .route::<PlayerMsg>(TargetReactor::Link(self.host_id));
+            handle.open_link(*client_id, params, false);
+        }
+        handle.open_link(self.host_id, HostLink::params::<K, M>(self.clients.clone()), true);
+    }
+
+    #[handler]
     fn handle_init_connect(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
+        handle: &mut ReactorHandle<K, M>,
         con: &InitConnect,
     ) {
         self.init_connected.get_mut(&con.0).map(|x| *x = Some(con.1.clone()));
@@ -46,9 +68,10 @@ impl Aggregator {
         }
     }
 
+    #[handler]
     fn handle_conn(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
+        handle: &mut ReactorHandle<K, M>,
         Res(uuid, conn): &Res<Connect>,
     ) {
         let (id, res) = match conn {
@@ -79,9 +102,10 @@ impl Aggregator {
         }
     }
 
+    #[handler]
     fn handle_state_req(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
+        handle: &mut ReactorHandle<K, M>,
         req: &Req<State>,
     ) {
         let waiting = self.clients.keys().map(|id| (*id, None)).collect();
@@ -93,52 +117,20 @@ impl Aggregator {
     }
 }
 
-impl ReactorState<any::TypeId, Message> for Aggregator {
-    const NAME: &'static str = "Aggregator";
-
-    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
-        for client_id in self.clients.values() {
-            handle.open_link(*client_id, ClientLink::params(self.host_id), false);
-        }
-        handle.open_link(self.host_id, HostLink::params(self.clients.clone()), true);
-    }
-}
-
-struct ClientLink;
-impl ClientLink {
-    fn params(host_id: ReactorID) -> LinkParams<Self, any::TypeId, Message> {
-        LinkParams::new(Self)
-            .internal_handler(FunctionHandler::from(i_to_e::<Self, HostMsg>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<Self, Req<Connect>>()))
-            .external_handler(FunctionHandler::from(e_to_i::<Self, PlayerMsg>(
-                TargetReactor::Link(host_id),
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<Self, Res<Connect>>(
-                TargetReactor::Reactor,
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<Self, InitConnect>(
-                TargetReactor::Reactor,
-            )))
-    }
-}
-
 struct HostLink {
     clients: HashMap<PlayerId, ReactorID>,
 }
 
 impl HostLink {
-    fn params(clients: HashMap<PlayerId, ReactorID>) -> LinkParams<Self, any::TypeId, Message> {
-        LinkParams::new(Self { clients })
-            .internal_handler(FunctionHandler::from(i_to_e::<Self, PlayerMsg>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<Self, Start>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<Self, Res<State>>()))
+    fn params<K: KeyType, M: Transport<K>>(
+        clients: HashMap<PlayerId, ReactorID>,
+    ) -> LinkParams<Self, K, M> {
+        GameProtocol::players(Self { clients })
+            .params()
             .external_handler(FunctionHandler::from(Self::handle_from_host))
-            .external_handler(FunctionHandler::from(e_to_i::<Self, Req<State>>(
-                TargetReactor::Reactor,
-            )))
     }
 
-    fn handle_from_host(&mut self, handle: &mut LinkHandle<any::TypeId, Message>, e: &HostMsg) {
+    fn handle_from_host<K, M: Transport<K>>(&mut self, handle: &mut LinkHandle<K, M>, e: &HostMsg) {
         let target = match e {
             HostMsg::Data(_, id) => id.clone(),
             HostMsg::Kick(id) => Some(*id),
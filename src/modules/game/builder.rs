@@ -1,3 +1,4 @@
+use super::replay::Recorder;
 use super::{Controller, Runner};
 
 use crate::generic::*;
@@ -5,12 +6,14 @@ use crate::modules::net::client_controller;
 use crate::modules::types::*;
 use crate::modules::*;
 
+use async_std::path::PathBuf;
+
 use std::any;
 use std::collections::HashMap;
 
-pub type BoxedBuilder = Box<
+pub type BoxedBuilder<K = any::TypeId, M = Message> = Box<
     dyn FnOnce(
-            BrokerHandle<any::TypeId, Message>,
+            BrokerHandle<K, M>,
             ReactorID,
             ReactorID,
             ReactorID,
@@ -19,23 +22,27 @@ pub type BoxedBuilder = Box<
         + Send,
 >;
 
-pub struct Builder<G> {
-    steplock: Option<StepLock>,
+pub struct Builder<G, K = any::TypeId, M = Message> {
+    steplock: Option<StepLock<K, M>>,
+    turnlock: Option<TurnLock<K, M>>,
     players: Vec<PlayerId>,
+    replays: Option<PathBuf>,
     game: G,
 }
 
-impl<G: Clone> Clone for Builder<G> {
+impl<G: Clone, K, M> Clone for Builder<G, K, M> {
     fn clone(&self) -> Self {
         Self {
             steplock: self.steplock.clone(),
+            turnlock: self.turnlock.clone(),
             players: self.players.clone(),
+            replays: self.replays.clone(),
             game: self.game.clone(),
         }
     }
 }
 
-impl<G: Controller + Send + 'static> Builder<G> {
+impl<G: Controller + Send + 'static, K: KeyType, M: Transport<K>> Builder<G, K, M> {
     /// When called, you might want to wait a little
     /// It is a known but that not all connections are established
     /// right away.
@@ -44,18 +51,34 @@ impl<G: Controller + Send + 'static> Builder<G> {
         Self {
             players,
             steplock: None,
+            turnlock: None,
+            replays: None,
             game,
         }
     }
 
-    pub fn with_step_lock(mut self, lock: StepLock) -> Self {
+    pub fn with_step_lock(mut self, lock: StepLock<K, M>) -> Self {
         self.steplock = Some(lock);
+        self.turnlock = None;
+        self
+    }
+
+    /// For games that take turns, replaces the step lock
+    pub fn with_turn_lock(mut self, lock: TurnLock<K, M>) -> Self {
+        self.turnlock = Some(lock);
+        self.steplock = None;
+        self
+    }
+
+    /// Records the game to `<id>.replay` in dir, see replay::load
+    pub fn with_replays<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.replays = Some(dir.into());
         self
     }
 
     fn build(
         self,
-        broker: BrokerHandle<any::TypeId, Message>,
+        broker: BrokerHandle<K, M>,
         gm_id: ReactorID,
         cm_id: ReactorID,
         logger_id: ReactorID,
@@ -70,26 +93,35 @@ impl<G: Controller + Send + 'static> Builder<G> {
             .iter()
             .map(|&x| {
                 let key = rand::random();
-                let params = client_controller::ClientController::params(cm_id, agg_id, x, key);
+                let params =
+                    client_controller::ClientController::<K, M>::params(cm_id, agg_id, x, key);
                 let id = broker.spawn(params, None);
                 (key, (x, id))
             })
             .collect();
 
-        let game = Runner::params(
-            if self.steplock.is_some() {
-                step_id
-            } else {
-                agg_id
-            },
-            gm_id,
-            logger_id,
-            Box::new(self.game),
-            id,
-        );
+        let locked = self.steplock.is_some() || self.turnlock.is_some();
 
-        let agg = Aggregator::params(
-            if self.steplock.is_some() {
+        let runner_clients = if locked { step_id } else { agg_id };
+        let game = match self.replays {
+            Some(dir) => {
+                let recorder = Recorder::new(dir.join(format!("{}.replay", id)), broker.runtime());
+                Runner::<K, M>::with_recorder(
+                    runner_clients,
+                    gm_id,
+                    logger_id,
+                    Box::new(self.game),
+                    id,
+                    recorder,
+                )
+            }
+            None => {
+                Runner::<K, M>::params(runner_clients, gm_id, logger_id, Box::new(self.game), id)
+            }
+        };
+
+        let agg = Aggregator::<K, M>::params(
+            if locked {
                 step_id
             } else {
                 game_id
@@ -100,6 +132,9 @@ impl<G: Controller + Send + 'static> Builder<G> {
         if let Some(lock) = self.steplock.map(|lock| lock.params(game_id, agg_id)) {
             broker.spawn(lock, Some(step_id));
         }
+        if let Some(lock) = self.turnlock.map(|lock| lock.params(game_id, agg_id)) {
+            broker.spawn(lock, Some(step_id));
+        }
         broker.spawn(game, Some(game_id));
 
         broker.spawn(agg, Some(agg_id));
@@ -108,8 +143,13 @@ impl<G: Controller + Send + 'static> Builder<G> {
     }
 }
 
-impl<G: Controller + Send + 'static> Into<BoxedBuilder> for Builder<G> {
-    fn into(self) -> BoxedBuilder {
-        Box::new(|broker, gm_id, cm_id, logger_id, id| self.build(broker, gm_id, cm_id, logger_id, id))
+impl<G, K, M> From<Builder<G, K, M>> for BoxedBuilder<K, M>
+where
+    G: Controller + Send + 'static,
+    K: KeyType,
+    M: Transport<K>,
+{
+    fn from(builder: Builder<G, K, M>) -> Self {
+        Box::new(|broker, gm_id, cm_id, logger_id, id| builder.build(broker, gm_id, cm_id, logger_id, id))
     }
 }
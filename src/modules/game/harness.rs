@@ -0,0 +1,168 @@
+//! Games, clients and an endpoint to run a Manager with in tests
+
+use super::{BoxedBuilder, Controller, Runner};
+use crate::generic::*;
+use crate::modules::logger::{BoxFuture, LogHandler};
+use crate::modules::net::{BoxSpawnPlayer, EndpointBuilder, Register, SpawnPlayer};
+use crate::modules::types::{Data, GameProtocol, HostMsg, PlayerId, PlayerMsg, Start};
+
+use futures::channel::{mpsc, oneshot};
+use futures::prelude::*;
+
+use serde_json::Value;
+
+use std::any;
+use std::collections::HashMap;
+use std::pin::Pin;
+
+/// The game is done after one move, the lowest id wins
+struct OneMove(Vec<PlayerId>, bool);
+impl Controller for OneMove {
+    fn step(&mut self, _: Vec<PlayerMsg>) -> Vec<HostMsg> {
+        self.1 = true;
+        Vec::new()
+    }
+
+    fn is_done(&mut self) -> Option<Value> {
+        let mut ranking = self.0.clone();
+        ranking.sort_unstable();
+        let winner = ranking.first();
+        self.1
+            .then(|| serde_json::json!({ "winner": winner, "ranking": ranking }))
+    }
+}
+
+/// Starts the game with its players, the first of them makes the move
+struct Players(ReactorID, Vec<PlayerId>);
+impl ReactorState<any::TypeId, Message> for Players {
+    const NAME: &'static str = "Players";
+
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+        handle.open_link(self.0, GameProtocol::players(()), true);
+        let players = self.1.iter().map(|&id| (id, format!("player {}", id))).collect();
+        handle.send_internal(Start { players }, TargetReactor::Links);
+
+        let data = Some(Data {
+            value: String::from("move"),
+        });
+        let id = self.1.first().copied().unwrap_or_default();
+        handle.send_internal(PlayerMsg { id, data }, TargetReactor::Links);
+    }
+}
+
+/// Closes as soon as it starts, like a game that crashed
+struct Crash;
+impl ReactorState<any::TypeId, Message> for Crash {
+    const NAME: &'static str = "Crash";
+
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+        handle.close();
+    }
+}
+
+/// Game between players that ends with {"winner": lowest id, "ranking": ids from low to high}
+pub fn lowest_wins(players: Vec<PlayerId>) -> BoxedBuilder {
+    Box::new(move |broker, gm_id, _, logger_id, id| {
+        let (game_id, players_id) = (ReactorID::rand(), ReactorID::rand());
+        let game = Box::new(OneMove(players.clone(), false));
+        broker.spawn(
+            Runner::params(players_id, gm_id, logger_id, game, id),
+            Some(game_id),
+        );
+        broker.spawn(CoreParams::new(Players(game_id, players)), Some(players_id));
+        (game_id, HashMap::new())
+    })
+}
+
+/// Game that closes without a result
+pub fn crash() -> BoxedBuilder {
+    Box::new(|broker, _, _, _, _| {
+        let game_id = ReactorID::rand();
+        broker.spawn(CoreParams::new(Crash), Some(game_id));
+        (game_id, HashMap::new())
+    })
+}
+
+/// A client manager and a logger that drop everything they get
+pub fn sinks(broker: &BrokerHandle<any::TypeId, Message>) -> (ReactorID, ReactorID) {
+    let (cm_id, logger_id) = (ReactorID::rand(), ReactorID::rand());
+    for id in [cm_id, logger_id] {
+        let (tx, rx): (Sender<any::TypeId, Message>, _) = mpsc::unbounded();
+        broker.spawn_reactorlike(id, tx, rx.for_each(|_| async {}), "Sink");
+    }
+    (cm_id, logger_id)
+}
+
+/// Logger handler that drops every log
+pub struct NoLogs;
+impl LogHandler<Value> for NoLogs {
+    fn handle<'a>(&'a mut self, _: Value) -> BoxFuture<'a> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Hands the players it gets to the client manager, see client
+pub struct Endpoint(pub mpsc::UnboundedReceiver<BoxSpawnPlayer>);
+impl EndpointBuilder for Endpoint {
+    fn build(
+        self,
+        id: ReactorID,
+        cm_chan: SenderHandle<any::TypeId, Message>,
+    ) -> (
+        Sender<any::TypeId, Message>,
+        Pin<Box<dyn Future<Output = Option<()>> + Send>>,
+    ) {
+        let (tx, rx) = mpsc::unbounded();
+        let fut = async move {
+            let _rx = rx;
+            self.0
+                .for_each(|spawn| async {
+                    cm_chan.send(id, spawn);
+                })
+                .await;
+            Some(())
+        };
+        (tx, fut.boxed())
+    }
+}
+
+///
+/// A player connecting with register, it tells seen the data it gets as (register.id, data)
+/// It answers what it gets with the next of replies, and closes when leave resolves.
+///
+pub fn client(
+    register: Register,
+    seen: mpsc::UnboundedSender<(u64, String)>,
+    mut replies: Vec<&'static str>,
+    leave: oneshot::Receiver<()>,
+) -> BoxSpawnPlayer {
+    let player = register.id;
+    replies.reverse();
+
+    SpawnPlayer::new(register, move |id, cc_chan| {
+        let (tx, rx) = mpsc::unbounded();
+        let fut = async move {
+            let mut rx = receiver_handle(rx).boxed().fuse();
+            let mut leave = leave.fuse();
+            loop {
+                select! {
+                    item = rx.next() => match item {
+                        Some(Some((_, key, mut msg))) => {
+                            if let Some(data) = Data::from_msg(&key, &mut msg) {
+                                let _ = seen.unbounded_send((player, data.value.clone()));
+                                if let Some(value) = replies.pop() {
+                                    cc_chan.send(id, Data { value: value.to_string() });
+                                }
+                            }
+                        }
+                        Some(None) => {}
+                        None => break,
+                    },
+                    _ = leave => break,
+                }
+            }
+            cc_chan.close(id);
+        };
+        (tx, fut.boxed(), "Client")
+    })
+}
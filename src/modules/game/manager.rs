@@ -1,25 +1,31 @@
 use super::builder::BoxedBuilder;
 use crate::generic::*;
-use crate::modules::net::{RegisterGame};
+use crate::modules::net::{BoxSpawnPlayer, RegisterGame, SpawnPlayer, Watch};
 use crate::modules::logger::GameJoin;
+use crate::modules::ratings::{Identity, Outcome, Rating, Ratings};
+use crate::modules::types::PlayerId;
+use crate::modules::Transport;
 
 use futures::channel::mpsc::{self, UnboundedSender};
 use futures::channel::oneshot;
-use futures::executor::ThreadPool;
 use futures::future::RemoteHandle;
 use futures::prelude::*;
 
 use serde_json::Value;
 
 use std::any;
+use std::marker::PhantomData;
 
 pub mod builder {
-    use super::Manager;
+    use super::{Manager, Rated};
+    use crate::modules::game::Matchmaker;
     use crate::generic::*;
-    use crate::modules::{ClientManager, EndpointBuilder};
+    use crate::modules::{ClientManager, EndpointBuilder, Transport};
     use crate::modules::logger::*;
+    use crate::modules::ratings::{Outcome, Ratings, RatingsFile};
 
-    use futures::executor::ThreadPool;
+    use async_std::path::PathBuf;
+    use futures::channel::mpsc;
     use futures::future::RemoteHandle;
 
     use std::any;
@@ -28,17 +34,23 @@ pub mod builder {
     pub struct ToInsert;
     pub struct Inserted;
 
-    pub struct Builder<Ep, Logger> {
+    pub struct Builder<Ep, Logger, K = any::TypeId, M = Message> {
         pd: PhantomData<(Ep, Logger)>,
-        broker: BrokerHandle<any::TypeId, Message>,
+        broker: BrokerHandle<K, M>,
         eps: Vec<ReactorID>,
         gm_id: ReactorID,
         cm_id: ReactorID,
         logger_id: ReactorID,
+        ratings: Option<Rated>,
+        matchmaker: Option<Matchmaker<K, M>>,
     }
 
-    impl<Ep, T> Builder<Ep, T> {
-        pub fn add_endpoint<E: EndpointBuilder>(self, ep: E, name: &str) -> Builder<Inserted, T> {
+    impl<Ep, T, K: KeyType, M: Transport<K>> Builder<Ep, T, K, M> {
+        pub fn add_endpoint<E: EndpointBuilder<K, M>>(
+            self,
+            ep: E,
+            name: &str,
+        ) -> Builder<Inserted, T, K, M> {
             let Builder {
                 pd: _,
                 broker,
@@ -46,6 +58,8 @@ pub mod builder {
                 cm_id,
                 gm_id,
                 logger_id,
+                ratings,
+                matchmaker,
             } = self;
             let ep_id = ReactorID::rand();
             let (sender, fut) = ep.build(ep_id, broker.get_sender(&cm_id));
@@ -60,12 +74,44 @@ pub mod builder {
                 gm_id,
                 cm_id,
                 logger_id,
+                ratings,
+                matchmaker,
             }
         }
+
+        ///
+        /// Keeps ratings up to date with every finished game, saving them to path after each one
+        /// outcome reads the result of a game, games it returns None for are not rated.
+        /// Only games started with Manager::start_rated_game are rated, the outcome names
+        /// their PlayerIds and is recorded for the identities the game was started with.
+        ///
+        pub fn with_ratings<P, F>(mut self, ratings: Ratings, path: P, outcome: F) -> Self
+        where
+            P: Into<PathBuf>,
+            F: Fn(&Value) -> Option<Outcome> + Send + 'static,
+        {
+            let (save, rx) = mpsc::unbounded();
+            self.broker
+                .runtime()
+                .spawn(start_handler(RatingsFile(path.into()), rx));
+
+            self.ratings = Some(Rated {
+                ratings,
+                outcome: Box::new(outcome),
+                save,
+            });
+            self
+        }
+
+        /// Players that register with an unknown key join the matchmaker's queues
+        pub fn with_matchmaker(mut self, matchmaker: Matchmaker<K, M>) -> Self {
+            self.matchmaker = Some(matchmaker);
+            self
+        }
     }
 
-    impl Builder<ToInsert, ToInsert> {
-        pub fn new(pool: ThreadPool) -> (Self, RemoteHandle<()>) {
+    impl<K: KeyType, M: Transport<K>> Builder<ToInsert, ToInsert, K, M> {
+        pub fn new<R: Into<Runtime>>(pool: R) -> (Self, RemoteHandle<()>) {
             let (broker, handle) = BrokerHandle::new(pool);
             (
                 Builder {
@@ -75,6 +121,8 @@ pub mod builder {
                     cm_id: ReactorID::rand(),
                     gm_id: ReactorID::rand(),
                     logger_id: ReactorID::rand(),
+                    ratings: None,
+                    matchmaker: None,
                 },
                 handle,
             )
@@ -82,8 +130,12 @@ pub mod builder {
     }
 
     use serde_json::Value;
-    impl<I> Builder<I, ToInsert> {
-        pub fn set_logger<H: LogHandler<Value> + Send + 'static>(self, handler: H, tp: ThreadPool) -> Builder<I, Inserted> {
+    impl<I, K: KeyType, M: Transport<K>> Builder<I, ToInsert, K, M> {
+        pub fn set_logger<H: LogHandler<Value> + Send + 'static, R: Into<Runtime>>(
+            self,
+            handler: H,
+            tp: R,
+        ) -> Builder<I, Inserted, K, M> {
             let Builder {
                 pd: _,
                 broker,
@@ -91,9 +143,11 @@ pub mod builder {
                 cm_id,
                 gm_id,
                 logger_id,
+                ratings,
+                matchmaker,
             } = self;
 
-            let logger = Logger::params(gm_id, handler, tp);
+            let logger = Logger::<Value, K, M>::params(gm_id, handler, tp);
             broker.spawn(logger, Some(logger_id));
 
             Builder {
@@ -103,19 +157,25 @@ pub mod builder {
                 cm_id,
                 gm_id,
                 logger_id,
+                ratings,
+                matchmaker,
             }
         }
     }
 
-    impl Builder<Inserted, ToInsert> {
-        pub async fn build<P: AsRef<async_std::path::Path> + Send>(self, p: P, tp: ThreadPool) -> Option<Manager> {
+    impl<K: KeyType, M: Transport<K>> Builder<Inserted, ToInsert, K, M> {
+        pub async fn build<P: AsRef<async_std::path::Path> + Send, R: Into<Runtime>>(
+            self,
+            p: P,
+            tp: R,
+        ) -> Option<Manager<K, M>> {
             let log_handler = DefaultLogHandler::new(p).await?;
             Some(self.set_logger(log_handler, tp).build())
         }
     }
 
-    impl Builder<Inserted, Inserted> {
-        pub fn build(self) -> Manager {
+    impl<K: KeyType, M: Transport<K>> Builder<Inserted, Inserted, K, M> {
+        pub fn build(self) -> Manager<K, M> {
             let Builder {
                 pd: _,
                 broker,
@@ -123,60 +183,132 @@ pub mod builder {
                 cm_id,
                 gm_id,
                 logger_id,
+                ratings,
+                matchmaker,
             } = self;
 
-            let cm_params = ClientManager::new(gm_id, eps);
+            let mm_id = ReactorID::rand();
+            let cm_params = match matchmaker {
+                Some(_) => ClientManager::<K, M>::with_matchmaker(gm_id, eps, mm_id),
+                None => ClientManager::<K, M>::new(gm_id, eps),
+            };
+
+            // Reactor-likes replace the channel of their id, so they go before the client
+            // manager opens its links to them
+            let manager = Manager::spawn(broker.clone(), gm_id, cm_id, logger_id, ratings);
+            if let Some(matchmaker) = matchmaker {
+                matchmaker.spawn(&broker, mm_id, cm_id, manager.clone());
+            }
             broker.spawn(cm_params, Some(cm_id));
-
-            Manager::new(broker, gm_id, cm_id, logger_id)
+            manager
         }
     }
 }
 
 use builder::Builder;
 
-struct GameOpReq(GameOp, oneshot::Sender<GameOpRes>);
-impl GameOpReq {
-    fn new(inner: GameOp) -> (Self, oneshot::Receiver<GameOpRes>) {
+type OutcomeFn = Box<dyn Fn(&Value) -> Option<Outcome> + Send>;
+
+/// Ratings the game manager keeps up to date, see Builder::with_ratings
+struct Rated {
+    ratings: Ratings,
+    outcome: OutcomeFn,
+    save: UnboundedSender<Value>,
+}
+
+struct GameOpReq<K, M>(GameOp<K, M>, oneshot::Sender<GameOpRes>);
+impl<K, M> GameOpReq<K, M> {
+    fn new(inner: GameOp<K, M>) -> (Self, oneshot::Receiver<GameOpRes>) {
         let (tx, rx) = oneshot::channel();
         (Self(inner, tx), rx)
     }
 }
 
-enum GameOp {
-    Build(BoxedBuilder),
+enum GameOp<K, M> {
+    Build(BoxedBuilder<K, M>, Option<HashMap<PlayerId, Identity>>),
     Kill(GameID),
     State(GameID),
+    Wait(GameID),
+    Rating(Identity),
+    Leaderboard,
 }
 
 pub enum GameOpRes {
     Built(Option<GameID>),
     State(Option<Result<(Value, Vec<Connect>), Value>>),
     Kill(Option<()>),
+    Done(Option<Value>),
+    Rating(Option<Rating>),
+    Leaderboard(Vec<(Identity, Rating)>),
 }
 
 /// Game manager 'front end'
-pub struct Manager {
-    op_tx: UnboundedSender<GameOpReq>,
+pub struct Manager<K = any::TypeId, M = Message> {
+    op_tx: UnboundedSender<GameOpReq<K, M>>,
+}
+
+impl<K, M> Clone for Manager<K, M> {
+    fn clone(&self) -> Self {
+        Self {
+            op_tx: self.op_tx.clone(),
+        }
+    }
 }
 
 impl Manager {
-    pub fn builder(pool: ThreadPool) -> (Builder<builder::ToInsert, builder::ToInsert>, RemoteHandle<()>) {
+    /// Builder for a game manager with TypeId keyed Messages
+    /// Use manager::builder::Builder::new for other transports
+    pub fn builder<R: Into<Runtime>>(
+        pool: R,
+    ) -> (Builder<builder::ToInsert, builder::ToInsert>, RemoteHandle<()>) {
         Builder::new(pool)
     }
+}
 
+impl<K: KeyType, M: Transport<K>> Manager<K, M> {
     pub fn new(
-        broker: BrokerHandle<any::TypeId, Message>,
+        broker: BrokerHandle<K, M>,
+        self_id: ReactorID,
+        cm_id: ReactorID,
+        logger_id: ReactorID,
+    ) -> Self {
+        Self::spawn(broker, self_id, cm_id, logger_id, None)
+    }
+
+    fn spawn(
+        broker: BrokerHandle<K, M>,
         self_id: ReactorID,
         cm_id: ReactorID,
         logger_id: ReactorID,
+        ratings: Option<Rated>,
     ) -> Self {
-        let op_tx = GameManagerFuture::spawn(broker, self_id, cm_id, logger_id);
+        let op_tx = GameManagerFuture::spawn(broker, self_id, cm_id, logger_id, ratings);
         Self { op_tx }
     }
 
-    pub async fn start_game<B: Into<BoxedBuilder>>(&self, builder: B) -> Option<u64> {
-        let (req, chan) = GameOpReq::new(GameOp::Build(builder.into()));
+    /// Starts a game that is not rated, see start_rated_game
+    pub async fn start_game<B: Into<BoxedBuilder<K, M>>>(&self, builder: B) -> Option<u64> {
+        self.build(builder.into(), None).await
+    }
+
+    ///
+    /// Starts a game whose outcome counts for the ratings, see Builder::with_ratings
+    /// identities maps every PlayerId of the game to the player it is.
+    ///
+    pub async fn start_rated_game<B: Into<BoxedBuilder<K, M>>>(
+        &self,
+        builder: B,
+        identities: HashMap<PlayerId, Identity>,
+    ) -> Option<u64> {
+        self.build(builder.into(), Some(identities)).await
+    }
+
+    async fn build(
+        &self,
+        builder: BoxedBuilder<K, M>,
+        identities: Option<HashMap<PlayerId, Identity>>,
+    ) -> Option<u64> {
+        let (req, chan) = GameOpReq::new(GameOp::Build(builder, identities));
         self.op_tx.unbounded_send(req).ok()?;
 
         if let GameOpRes::Built(x) = chan.await.ok()? {
@@ -199,6 +331,48 @@ impl Manager {
         }
     }
 
+    ///
+    /// Resolves with the result of a game once it is done, None for unknown games
+    /// Games that close without a result, killed or crashed ones, resolve with None as well.
+    ///
+    pub async fn wait_game(&self, game: u64) -> Option<Value> {
+        let (req, chan) = GameOpReq::new(GameOp::Wait(game));
+        self.op_tx.unbounded_send(req).ok()?;
+
+        if let GameOpRes::Done(x) = chan.await.ok()? {
+            x
+        } else {
+            error!("Got wrong Game Op Response, this should not happen");
+            None
+        }
+    }
+
+    /// None for players without a rated game, or without ratings, see Builder::with_ratings
+    pub async fn get_rating(&self, player: Identity) -> Option<Rating> {
+        let (req, chan) = GameOpReq::new(GameOp::Rating(player));
+        self.op_tx.unbounded_send(req).ok()?;
+
+        if let GameOpRes::Rating(x) = chan.await.ok()? {
+            x
+        } else {
+            error!("Got wrong Game Op Response, this should not happen");
+            None
+        }
+    }
+
+    /// Every rated player, the best first
+    pub async fn leaderboard(&self) -> Option<Vec<(Identity, Rating)>> {
+        let (req, chan) = GameOpReq::new(GameOp::Leaderboard);
+        self.op_tx.unbounded_send(req).ok()?;
+
+        if let GameOpRes::Leaderboard(x) = chan.await.ok()? {
+            Some(x)
+        } else {
+            error!("Got wrong Game Op Response, this should not happen");
+            None
+        }
+    }
+
     pub async fn kill_game(&self, game: u64) -> Option<()> {
         let (req, chan) = GameOpReq::new(GameOp::Kill(game));
         self.op_tx.unbounded_send(req).ok()?;
@@ -217,45 +391,63 @@ use std::collections::HashMap;
 type GameID = u64;
 
 /// Game manager 'back end'
-struct GameManagerFuture {
-    broker: BrokerHandle<any::TypeId, Message>,
-    games: HashMap<GameID, Result<SenderHandle<any::TypeId, Message>, Value>>,
+struct GameManagerFuture<K, M> {
+    pd: PhantomData<fn() -> (K, M)>,
+    broker: BrokerHandle<K, M>,
+    games: HashMap<GameID, Result<SenderHandle<K, M>, Value>>,
     requests: HashMap<UUID, oneshot::Sender<GameOpRes>>,
+    waiting: HashMap<GameID, Vec<UUID>>,
+    /// Kill requests on their way to the game
+    killing: HashMap<UUID, GameID>,
+    /// Games report here when their reactor closed, see handle_closed
+    closed_tx: UnboundedSender<GameID>,
+    ratings: Option<Rated>,
+    /// Who played the rated games that are still running
+    identities: HashMap<GameID, HashMap<PlayerId, Identity>>,
 
     id: ReactorID,
     cm_id: ReactorID,
     logger_id: ReactorID,
 
-    cm_chan: SenderHandle<any::TypeId, Message>,
-    logger_chan: SenderHandle<any::TypeId, Message>,
+    cm_chan: SenderHandle<K, M>,
+    logger_chan: SenderHandle<K, M>,
 }
 
-impl GameManagerFuture {
+impl<K: KeyType, M: Transport<K>> GameManagerFuture<K, M> {
     fn spawn(
-        broker: BrokerHandle<any::TypeId, Message>,
+        broker: BrokerHandle<K, M>,
         self_id: ReactorID,
         cm_id: ReactorID,
         logger_id: ReactorID,
-    ) -> UnboundedSender<GameOpReq> {
+        ratings: Option<Rated>,
+    ) -> UnboundedSender<GameOpReq<K, M>> {
         let (op_tx, mut op_rx) = mpsc::unbounded();
         let (ch_tx, ch_rx) = mpsc::unbounded();
+        let (closed_tx, mut closed_rx) = mpsc::unbounded();
 
         let mut ch_rx = receiver_handle(ch_rx).boxed().fuse();
 
         let mut this = Self {
+            pd: PhantomData,
             cm_chan: broker.get_sender(&cm_id),
             logger_chan: broker.get_sender(&logger_id),
             broker: broker.clone(),
             games: HashMap::new(),
             requests: HashMap::new(),
+            waiting: HashMap::new(),
+            killing: HashMap::new(),
+            closed_tx,
+            ratings,
+            identities: HashMap::new(),
             id: self_id,
             cm_id,
             logger_id,
         };
 
+        // A game sends its result before it closes, so results go before closes
         let fut = async move {
                 loop {
-                    select! {
+                    select_biased! {
                         req = op_rx.next() => {
                             // Handle request
                             if let Some(GameOpReq(req, chan)) = req {
@@ -263,9 +455,14 @@ impl GameManagerFuture {
                                 this.requests.insert(uuid, chan);
 
                                 match req {
-                                    GameOp::Build(builder) => this.handle_gamebuilder(uuid, builder),
+                                    GameOp::Build(builder, identities) => {
+                                        this.handle_gamebuilder(uuid, builder, identities)
+                                    }
                                     GameOp::State(game) => this.handle_state(uuid, game),
                                     GameOp::Kill(game) => this.handle_kill(uuid, game),
+                                    GameOp::Wait(game) => this.handle_wait(uuid, game),
+                                    GameOp::Rating(player) => this.handle_rating(uuid, player),
+                                    GameOp::Leaderboard => this.handle_leaderboard(uuid),
                                 }
                             } else {
                                 error!("Breaking here here");
@@ -275,22 +472,32 @@ impl GameManagerFuture {
                         res = ch_rx.next() => {
                             if let Some((from, key, mut msg)) = res? {
                                 // Handle response
-                                if if key == any::TypeId::of::<Res::<(Value, State)>>() {
+                                if if key == <M as Carrier<K, Res<(Value, State)>>>::key() {
                                     Res::<(Value, State)>::from_msg(&key, &mut msg).map(|Res(id, (value, state))| {
                                         this.send_msg(*id, GameOpRes::State(Some(Ok((value.clone(), state.res().clone())))))
                                     }).is_none()
-                                } else if key == any::TypeId::of::<(u64, Value)>() {
+                                } else if key == <M as Carrier<K, (u64, Value)>>::key() {
                                     <(u64, Value)>::from_msg(&key, &mut msg).map(|(id, value)| {
-                                        this.games.insert(*id, Err(value.clone()))
+                                        this.handle_done(*id, value.clone())
                                     }).is_none()
+                                } else if key == <M as Carrier<K, BoxSpawnPlayer<K, M>>>::key() {
+                                    BoxSpawnPlayer::<K, M>::from_msg(&key, &mut msg)
+                                        .and_then(|spawn| spawn.lock().unwrap().take())
+                                        .map(|spawn| this.handle_spectator(spawn))
+                                        .is_none()
                                 } else {
                                     Res::<Kill>::from_msg(&key, &mut msg).map(|Res::<Kill>(id, _)| {
-                                        this.send_msg(*id, GameOpRes::Kill(Some(())))
+                                        this.handle_killed(*id)
                                     }).is_none()
                                 } {
                                     error!("HELP");
                                 }
                             }
+                        },
+                        game = closed_rx.next() => {
+                            if let Some(game) = game {
+                                this.handle_closed(game);
+                            }
                         }
                     }
                 }
@@ -314,7 +521,12 @@ impl GameManagerFuture {
         }
     }
 
-    fn handle_gamebuilder(&mut self, uuid: UUID, builder: BoxedBuilder) {
+    fn handle_gamebuilder(
+        &mut self,
+        uuid: UUID,
+        builder: BoxedBuilder<K, M>,
+        identities: Option<HashMap<PlayerId, Identity>>,
+    ) {
         let game_uuid = rand::random();
         let (game_id, players) = builder(self.broker.clone(), self.id, self.cm_id, self.logger_id, game_uuid);
         self.cm_chan.send(
@@ -328,6 +540,16 @@ impl GameManagerFuture {
         self.logger_chan.send(self.id, GameJoin(game_id)).unwrap();
         self.games
             .insert(game_uuid, Ok(self.broker.get_sender(&game_id)));
+        if let Some(identities) = identities {
+            self.identities.insert(game_uuid, identities);
+        }
+
+        let closed = self.broker.watch(&game_id);
+        let closed_tx = self.closed_tx.clone();
+        self.broker.runtime().spawn(async move {
+            let _ = closed.await;
+            let _ = closed_tx.unbounded_send(game_uuid);
+        });
 
         self.send_msg(uuid, GameOpRes::Built(Some(game_uuid)));
     }
@@ -348,13 +570,164 @@ impl GameManagerFuture {
         }
     }
 
+    fn handle_wait(&mut self, uuid: UUID, game: GameID) {
+        match self.games.get(&game) {
+            Some(Ok(_)) => self.waiting.entry(game).or_default().push(uuid),
+            Some(Err(resolved)) => {
+                let res = resolved.clone();
+                self.send_msg(uuid, GameOpRes::Done(Some(res)));
+            }
+            None => self.send_msg(uuid, GameOpRes::Done(None)),
+        }
+    }
+
+    /// The game finished, everybody waiting for it gets the result
+    fn handle_done(&mut self, game: GameID, value: Value) {
+        let identities = self.identities.remove(&game);
+        if let (Some(rated), Some(identities)) = (&mut self.ratings, identities) {
+            match (rated.outcome)(&value).map(|outcome| outcome.identities(&identities)) {
+                Some(Some(outcome)) => {
+                    rated.ratings.record(&outcome);
+                    if rated.save.unbounded_send(rated.ratings.to_json()).is_err() {
+                        error!("Ratings are not saved anymore");
+                    }
+                }
+                Some(None) => {
+                    error!(game, "Outcome names a player without identity, not rating it")
+                }
+                None => {}
+            }
+        }
+
+        for uuid in self.waiting.remove(&game).unwrap_or_default() {
+            self.send_msg(uuid, GameOpRes::Done(Some(value.clone())));
+        }
+        self.games.insert(game, Err(value));
+    }
+
+    /// Connects a spectator to its game, it cannot send the game anything
+    fn handle_spectator(&mut self, spawn: SpawnPlayer<K, M>) {
+        let spectate = match spawn.register.spectate.clone() {
+            Some(spectate) => spectate,
+            None => return,
+        };
+        let game = match self.games.get(&spectate.game) {
+            Some(Ok(game)) => game.clone(),
+            _ => {
+                info!(game = spectate.game, "Dropping spectator of a game that is not running");
+                return;
+            }
+        };
+
+        let id = ReactorID::rand();
+        let (chan, fut, name) = (spawn.builder)(id, game.clone());
+        self.broker.spawn_reactorlike(id, chan, fut, name);
+
+        if game.send(self.id, Watch { spectator: id, spectate }).is_none() {
+            error!(%id, "Game is gone before the spectator joined");
+        }
+    }
+
+    fn handle_rating(&mut self, uuid: UUID, player: Identity) {
+        let rating = self.ratings.as_ref().and_then(|r| r.ratings.get(player));
+        self.send_msg(uuid, GameOpRes::Rating(rating));
+    }
+
+    fn handle_leaderboard(&mut self, uuid: UUID) {
+        let board = self.ratings.as_ref().map(|r| r.ratings.leaderboard());
+        self.send_msg(uuid, GameOpRes::Leaderboard(board.unwrap_or_default()));
+    }
+
     fn handle_kill(&mut self, uuid: UUID, game: GameID) {
         if let Some(Ok(ch)) = self.games.get(&game) {
             if ch.send(self.id, Req(uuid, Kill)).is_none() {
                 self.send_msg(uuid, GameOpRes::Kill(None));
+            } else {
+                self.killing.insert(uuid, game);
             }
         } else {
             self.send_msg(uuid, GameOpRes::Kill(None));
         }
     }
+
+    fn handle_killed(&mut self, uuid: UUID) {
+        self.send_msg(uuid, GameOpRes::Kill(Some(())));
+        if let Some(game) = self.killing.remove(&uuid) {
+            self.handle_closed(game);
+        }
+    }
+
+    /// The game is gone, when it did not finish everybody waiting for it gets None
+    fn handle_closed(&mut self, game: GameID) {
+        let kills: Vec<UUID> = self
+            .killing
+            .iter()
+            .filter(|(_, &killed)| killed == game)
+            .map(|(&uuid, _)| uuid)
+            .collect();
+        for uuid in kills {
+            self.killing.remove(&uuid);
+            self.send_msg(uuid, GameOpRes::Kill(Some(())));
+        }
+
+        if let Some(Ok(_)) = self.games.get(&game) {
+            info!(game, "Game closed without a result");
+            self.games.remove(&game);
+            self.identities.remove(&game);
+            for uuid in self.waiting.remove(&game).unwrap_or_default() {
+                self.send_msg(uuid, GameOpRes::Done(None));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::game::harness;
+
+    use futures::executor::{block_on, ThreadPool};
+
+    #[test]
+    fn rates_the_identities_of_rated_games() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::new(pool);
+        let (cm_id, logger_id) = harness::sinks(&broker);
+
+        let (save, mut saved) = mpsc::unbounded();
+        let rated = Rated {
+            ratings: Ratings::new(),
+            outcome: Box::new(|result| {
+                serde_json::from_value(result["ranking"].clone()).ok().map(Outcome::ranking)
+            }),
+            save,
+        };
+        let manager = Manager::spawn(broker, ReactorID::rand(), cm_id, logger_id, Some(rated));
+
+        block_on(async {
+            let identities = vec![(1, 10), (2, 20)].into_iter().collect();
+            let game = manager.start_rated_game(harness::lowest_wins(vec![1, 2]), identities);
+            manager.wait_game(game.await.unwrap()).await.unwrap();
+
+            // Neither an unrated game nor one with a player without identity counts
+            let game = manager.start_game(harness::lowest_wins(vec![1, 2]));
+            manager.wait_game(game.await.unwrap()).await.unwrap();
+            let identities = vec![(1, 10)].into_iter().collect();
+            let game = manager.start_rated_game(harness::lowest_wins(vec![1, 2]), identities);
+            manager.wait_game(game.await.unwrap()).await.unwrap();
+
+            let winner = manager.get_rating(10).await.unwrap();
+            assert_eq!(winner.games, 1);
+            assert_eq!(winner.elo, 1516.0);
+            assert_eq!(manager.get_rating(1).await, None);
+
+            let board = manager.leaderboard().await.unwrap();
+            let board: Vec<_> = board.iter().map(|(id, r)| (*id, r.games)).collect();
+            assert_eq!(board, vec![(10, 1), (20, 1)]);
+        });
+
+        // One snapshot, for the one rated game
+        assert!(saved.try_recv().is_ok());
+        assert!(saved.try_recv().is_err());
+    }
 }
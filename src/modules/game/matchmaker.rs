@@ -0,0 +1,495 @@
+use super::{BoxedBuilder, Manager};
+use crate::generic::*;
+use crate::modules::net::{BoxSpawnPlayer, Register, SpawnPlayer};
+use crate::modules::types::PlayerId;
+use crate::modules::Transport;
+
+use futures::channel::mpsc::{self, UnboundedSender};
+use futures::channel::oneshot;
+use futures::prelude::*;
+
+use std::any;
+use std::collections::HashMap;
+
+/// A kind of game players queue for, see Matchmaker::queue
+pub struct Queue<K = any::TypeId, M = Message> {
+    players: usize,
+    max_gap: Option<f64>,
+    game: Box<dyn FnMut(Vec<PlayerId>) -> BoxedBuilder<K, M> + Send>,
+}
+
+impl<K, M> Queue<K, M> {
+    /// Games for this many players, game builds one for the matched identities
+    pub fn new<F, B>(players: usize, mut game: F) -> Self
+    where
+        F: FnMut(Vec<PlayerId>) -> B + Send + 'static,
+        B: Into<BoxedBuilder<K, M>>,
+    {
+        Self {
+            players,
+            max_gap: None,
+            game: Box::new(move |players| game(players).into()),
+        }
+    }
+
+    /// Only matches players whose Glicko-2 ratings are at most gap apart, see Builder::with_ratings
+    pub fn with_rating_gap(mut self, gap: f64) -> Self {
+        self.max_gap = Some(gap);
+        self
+    }
+}
+
+/// A queued player, its client already runs and talks to a lobby, see lobby
+struct Waiting<K, M> {
+    rating: f64,
+    register: Register,
+    client: ReactorID,
+    handoff: oneshot::Sender<Handoff<K, M>>,
+}
+
+/// The id the client manager gave the client and its client controller
+type Handoff<K, M> = (ReactorID, Sender<K, M>);
+
+impl<K: KeyType, M: Transport<K>> Waiting<K, M> {
+    /// Spawns the client of the player, its lobby tells left when the player disconnects
+    fn new(
+        spawn: SpawnPlayer<K, M>,
+        rating: f64,
+        broker: &BrokerHandle<K, M>,
+        left: UnboundedSender<ReactorID>,
+    ) -> Self {
+        let (client, lobby_id) = (ReactorID::rand(), ReactorID::rand());
+        let (handoff, handoff_rx) = oneshot::channel();
+
+        let (tx, rx) = mpsc::unbounded();
+        let fut = lobby(broker.clone(), lobby_id, client, rx, handoff_rx, left);
+        broker.spawn_reactorlike(lobby_id, tx, fut, "Lobby");
+
+        let (chan, fut, name) = (spawn.builder)(client, broker.get_sender(&lobby_id));
+        broker.spawn_reactorlike(client, chan, fut, name);
+
+        Waiting {
+            rating,
+            register: spawn.register,
+            client,
+            handoff,
+        }
+    }
+
+    ///
+    /// The player for the client manager, registering with key
+    /// The client manager spawns a stand-in for the running client, the lobby passes on what
+    /// the client sends to cc as coming from that stand-in.
+    ///
+    fn into_spawn(self, key: u64, cc: ReactorID, broker: &BrokerHandle<K, M>) -> SpawnPlayer<K, M> {
+        let Waiting {
+            mut register,
+            client,
+            handoff,
+            ..
+        } = self;
+        register.id = key;
+
+        let (chan, cc) = (broker.get(&client), broker.get(&cc));
+        let closed = broker.watch(&client);
+        SpawnPlayer {
+            register,
+            builder: Box::new(move |id, _| {
+                let _ = handoff.send((id, cc));
+                (chan, closed.map(|_| ()).boxed(), "Client")
+            }),
+        }
+    }
+}
+
+///
+/// Stands between a queued client and the game, dropping what the client sends until the game
+/// starts. A client that closes before that left the queue. When the player is not waiting
+/// anymore without a game, because it connected again, the lobby closes the client.
+///
+async fn lobby<K: KeyType, M: Transport<K>>(
+    broker: BrokerHandle<K, M>,
+    id: ReactorID,
+    client: ReactorID,
+    mut rx: Receiver<K, M>,
+    handoff: oneshot::Receiver<Handoff<K, M>>,
+    left: UnboundedSender<ReactorID>,
+) {
+    let mut handoff = handoff.fuse();
+    let (stand_in, cc) = loop {
+        select! {
+            op = rx.next() => match op {
+                Some(Operation::CloseLink(_)) | None => {
+                    let _ = left.unbounded_send(client);
+                    return;
+                }
+                Some(_) => trace!(%client, "Dropping message of a queued player"),
+            },
+            handoff = handoff => match handoff {
+                Ok(handoff) => break handoff,
+                Err(_) => {
+                    broker.get_sender(&client).close(id);
+                    return;
+                }
+            },
+        }
+    };
+
+    while let Some(op) = rx.next().await {
+        let (op, closed) = match op {
+            Operation::ExternalMessage(_, k, m, span) => {
+                (Operation::ExternalMessage(stand_in, k, m, span), false)
+            }
+            Operation::CloseLink(_) => (Operation::CloseLink(stand_in), true),
+            _ => continue,
+        };
+        if cc.unbounded_send(op).is_err() || closed {
+            break;
+        }
+    }
+}
+
+/// Why start did not start a game
+enum StartError<K, M> {
+    /// The manager did not build the game, the players can wait for the next one
+    NotStarted(Vec<Waiting<K, M>>),
+    /// The game has no PlayerId for these identities
+    Unknown(u64, Vec<PlayerId>),
+}
+
+///
+/// Starts games for players that connect with a persistent identity instead of a game key
+/// The id a player registers with is its identity, its queue is picked by name.
+/// Matched players are the players of the game, with their identity as PlayerId.
+/// Their games are rated, see Manager::start_rated_game.
+/// Players that wait in a queue and connect again take their old place, players that
+/// disconnect leave it.
+///
+pub struct Matchmaker<K = any::TypeId, M = Message> {
+    queues: HashMap<String, Queue<K, M>>,
+    waiting: HashMap<String, Vec<Waiting<K, M>>>,
+}
+
+impl<K: KeyType, M: Transport<K>> Matchmaker<K, M> {
+    pub fn new() -> Self {
+        Self {
+            queues: HashMap::new(),
+            waiting: HashMap::new(),
+        }
+    }
+
+    pub fn queue(mut self, name: &str, queue: Queue<K, M>) -> Self {
+        self.queues.insert(name.to_string(), queue);
+        self
+    }
+
+    /// Reactor-like that gets unknown players from the client manager and hands them back
+    pub(super) fn spawn(
+        mut self,
+        broker: &BrokerHandle<K, M>,
+        id: ReactorID,
+        cm_id: ReactorID,
+        manager: Manager<K, M>,
+    ) {
+        let (tx, rx) = mpsc::unbounded();
+        let (left, mut left_rx) = mpsc::unbounded();
+        let cm_chan = broker.get_sender(&cm_id);
+        let handle = broker.clone();
+
+        let fut = async move {
+            let mut rx = receiver_handle(rx).boxed().fuse();
+
+            loop {
+                let (key, mut msg) = select_biased! {
+                    client = left_rx.next() => {
+                        if let Some(client) = client {
+                            self.leave(client);
+                        }
+                        continue;
+                    },
+                    item = rx.next() => match item {
+                        Some(Some((_, key, msg))) => (key, msg),
+                        // The client manager opening its link
+                        Some(None) => continue,
+                        None => break,
+                    },
+                };
+                let spawn = BoxSpawnPlayer::<K, M>::from_msg(&key, &mut msg)
+                    .and_then(|spawn| spawn.lock().unwrap().take());
+
+                if let Some(spawn) = spawn {
+                    for (game, players) in self.join(spawn, &handle, &manager, &left).await {
+                        for spawn in players {
+                            if cm_chan.send(id, spawn.boxed()).is_none() {
+                                error!(game, "Client manager is gone");
+                            }
+                        }
+                    }
+                }
+            }
+
+            Some(())
+        }
+        .map(|_| ());
+
+        broker.spawn_reactorlike(id, tx, fut, "Matchmaker");
+    }
+
+    /// Queues the player, returning the games that started with the players to send to them
+    async fn join(
+        &mut self,
+        spawn: SpawnPlayer<K, M>,
+        broker: &BrokerHandle<K, M>,
+        manager: &Manager<K, M>,
+        left: &UnboundedSender<ReactorID>,
+    ) -> Vec<(u64, Vec<SpawnPlayer<K, M>>)> {
+        let identity = spawn.register.id;
+        let name = match &spawn.register.queue {
+            Some(name) if self.queues.contains_key(name) => name.clone(),
+            queue => {
+                info!(player = identity, ?queue, "Dropping player without a known queue");
+                return Vec::new();
+            }
+        };
+
+        let rating = match self.queues[&name].max_gap {
+            Some(_) => manager.get_rating(identity).await.map(|r| r.glicko.rating),
+            None => None,
+        };
+
+        let rating = rating.unwrap_or(1500.0);
+        let waiting = self.waiting.entry(name.clone()).or_default();
+        waiting.retain(|w| w.register.id != identity);
+        waiting.push(Waiting::new(spawn, rating, broker, left.clone()));
+
+        let mut started = Vec::new();
+        loop {
+            let queue = self.queues.get_mut(&name).unwrap();
+            let waiting = self.waiting.get_mut(&name).unwrap();
+
+            let ratings: Vec<f64> = waiting.iter().map(|w| w.rating).collect();
+            let mut matched = match find_match(&ratings, queue.players, queue.max_gap) {
+                Some(matched) => matched,
+                None => break,
+            };
+
+            // Highest index first, so the others stay put
+            matched.sort_unstable_by(|a, b| b.cmp(a));
+            let mut players: Vec<_> = matched.iter().map(|&i| waiting.remove(i)).collect();
+            players.reverse();
+
+            let identities = players.iter().map(|w| w.register.id).collect();
+            let game = (queue.game)(identities);
+            match start(broker, manager, game, players).await {
+                Ok(game) => started.push(game),
+                Err(StartError::NotStarted(players)) => {
+                    error!(queue = %name, "Game did not start, the players wait for the next one");
+                    let waiting = self.waiting.get_mut(&name).unwrap();
+                    for (&i, player) in matched.iter().rev().zip(players) {
+                        waiting.insert(i, player);
+                    }
+                    break;
+                }
+                Err(StartError::Unknown(game, identities)) => {
+                    // Dropping the players closes their clients, this queue cannot seat them
+                    error!(
+                        game,
+                        queue = %name,
+                        ?identities,
+                        "Game does not use the identities of its players as PlayerIds, killing it"
+                    );
+                    let _ = manager.kill_game(game).await;
+                }
+            }
+        }
+        started
+    }
+
+    /// The client of a queued player closed, it leaves its queue
+    fn leave(&mut self, client: ReactorID) {
+        for (queue, waiting) in self.waiting.iter_mut() {
+            if let Some(i) = waiting.iter().position(|w| w.client == client) {
+                let player = waiting.remove(i).register.id;
+                info!(player, %queue, "Player left the queue");
+            }
+        }
+    }
+}
+
+impl<K: KeyType, M: Transport<K>> Default for Matchmaker<K, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Starts the game, the players register again with their key for it
+async fn start<K: KeyType, M: Transport<K>>(
+    broker: &BrokerHandle<K, M>,
+    manager: &Manager<K, M>,
+    game: BoxedBuilder<K, M>,
+    players: Vec<Waiting<K, M>>,
+) -> Result<(u64, Vec<SpawnPlayer<K, M>>), StartError<K, M>> {
+    let (keys_tx, keys_rx) = oneshot::channel();
+    let builder: BoxedBuilder<K, M> = Box::new(move |broker, gm_id, cm_id, logger_id, id| {
+        let (game_id, players) = game(broker, gm_id, cm_id, logger_id, id);
+        let _ = keys_tx.send(players.clone());
+        (game_id, players)
+    });
+
+    let identities = players
+        .iter()
+        .map(|waiting| (waiting.register.id, waiting.register.id))
+        .collect();
+    let game = match manager.start_rated_game(builder, identities).await {
+        Some(game) => game,
+        None => return Err(StartError::NotStarted(players)),
+    };
+    // The game is built once start_rated_game returns it
+    let keys: HashMap<PlayerId, (u64, ReactorID)> = keys_rx
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(key, (player, cc))| (player, (key, cc)))
+        .collect();
+
+    let unknown: Vec<PlayerId> = players
+        .iter()
+        .map(|waiting| waiting.register.id)
+        .filter(|identity| !keys.contains_key(identity))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(StartError::Unknown(game, unknown));
+    }
+    info!(game, players = keys.len(), "Matched players");
+
+    let spawns = players
+        .into_iter()
+        .map(|waiting| {
+            let (key, cc) = keys[&waiting.register.id];
+            waiting.into_spawn(key, cc, broker)
+        })
+        .collect();
+    Ok((game, spawns))
+}
+
+///
+/// Picks players for a game, in the order they are waiting
+/// With a maximum gap this is the group with the closest ratings within that gap.
+///
+fn find_match(ratings: &[f64], players: usize, max_gap: Option<f64>) -> Option<Vec<usize>> {
+    if players == 0 || ratings.len() < players {
+        return None;
+    }
+
+    let max_gap = match max_gap {
+        Some(max_gap) => max_gap,
+        None => return Some((0..players).collect()),
+    };
+
+    let mut sorted: Vec<usize> = (0..ratings.len()).collect();
+    sorted.sort_by(|&a, &b| {
+        ratings[a]
+            .partial_cmp(&ratings[b])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let gap = |window: &[usize]| ratings[window[players - 1]] - ratings[window[0]];
+    let best = sorted
+        .windows(players)
+        .filter(|window| gap(window) <= max_gap)
+        .min_by(|a, b| gap(a).partial_cmp(&gap(b)).unwrap_or(std::cmp::Ordering::Equal))?;
+
+    let mut matched = best.to_vec();
+    matched.sort_unstable();
+    Some(matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::game::{harness, Builder, Controller};
+    use crate::modules::types::{HostMsg, PlayerMsg};
+
+    use futures::executor::{block_on, ThreadPool};
+
+    use serde_json::Value;
+
+    /// Greets everybody when all players are there
+    struct Greet;
+    impl Controller for Greet {
+        fn start(&mut self) -> Vec<HostMsg> {
+            vec![HostMsg::new(String::from("go"), None)]
+        }
+
+        fn step(&mut self, _: Vec<PlayerMsg>) -> Vec<HostMsg> {
+            Vec::new()
+        }
+
+        fn is_done(&mut self) -> Option<Value> {
+            None
+        }
+    }
+
+    /// Queues for a duel, closing when leave resolves
+    fn player(
+        identity: u64,
+        seen: mpsc::UnboundedSender<(u64, String)>,
+        leave: oneshot::Receiver<()>,
+    ) -> BoxSpawnPlayer {
+        let register = Register {
+            id: identity,
+            name: format!("player {}", identity),
+            queue: Some(String::from("duel")),
+            spectate: None,
+        };
+        harness::client(register, seen, Vec::new(), leave)
+    }
+
+    #[test]
+    fn queued_players_play_through_the_client_manager() {
+        let pool = ThreadPool::new().unwrap();
+        let (players, rx) = mpsc::unbounded();
+        let matchmaker = Matchmaker::new().queue(
+            "duel",
+            Queue::new(2, |players| Builder::new(players, Greet)),
+        );
+        let (builder, _handle) = Manager::builder(pool.clone());
+        let _manager = builder
+            .add_endpoint(harness::Endpoint(rx), "Endpoint")
+            .set_logger(harness::NoLogs, pool)
+            .with_matchmaker(matchmaker)
+            .build();
+
+        let (gone, mut closed) = mpsc::unbounded();
+        let (leave, left) = oneshot::channel();
+        players.unbounded_send(player(1, gone, left)).unwrap();
+        leave.send(()).unwrap();
+        // The client of player 1 drops its channel once it closed, its lobby already got the close
+        assert_eq!(block_on(closed.next()), None);
+
+        let (seen, mut greeted) = mpsc::unbounded();
+
+        let mut stay = Vec::new();
+        for identity in [2, 3] {
+            let (tx, rx) = oneshot::channel::<()>();
+            stay.push(tx);
+            players.unbounded_send(player(identity, seen.clone(), rx)).unwrap();
+        }
+
+        let mut got = vec![block_on(greeted.next()), block_on(greeted.next())];
+        got.sort();
+        let go = |identity| Some((identity, String::from("go")));
+        assert_eq!(got, vec![go(2), go(3)]);
+    }
+
+    #[test]
+    fn matches_close_ratings() {
+        let ratings = [1500.0, 1900.0, 1450.0, 1880.0, 1000.0];
+
+        assert_eq!(find_match(&ratings, 2, None), Some(vec![0, 1]));
+        assert_eq!(find_match(&ratings, 2, Some(100.0)), Some(vec![1, 3]));
+        assert_eq!(find_match(&ratings, 3, Some(100.0)), None);
+        assert_eq!(find_match(&ratings, 3, Some(500.0)), Some(vec![0, 1, 3]));
+        assert_eq!(find_match(&ratings[..1], 2, None), None);
+    }
+}
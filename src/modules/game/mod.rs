@@ -1,22 +1,40 @@
 pub use crate::util::request;
 mod builder;
+#[cfg(test)]
+pub(crate) mod harness;
 mod manager;
+mod matchmaker;
+pub mod replay;
 mod runner;
 
-pub use builder::Builder;
+pub use builder::{BoxedBuilder, Builder};
 pub use manager::Manager;
+pub use matchmaker::{Matchmaker, Queue};
 pub use runner::Runner;
 
-use crate::modules::types::{HostMsg, PlayerMsg};
+use crate::modules::types::{HostMsg, PlayerId, PlayerMsg};
 
 use serde_json::Value;
 
+use std::time::Duration;
+
 pub trait Controller {
     fn start(&mut self) -> Vec<HostMsg>{
         info!("Starting this game");
         Vec::new()
     }
     fn step(&mut self, turns: Vec<PlayerMsg>) -> Vec<HostMsg>;
+    /// The players that are to move next, None when everybody moves at once
+    /// Asked after start and after every step, see TurnLock
+    fn turn(&mut self) -> Option<Vec<PlayerId>> {
+        None
+    }
+    /// A player timed out too often and forfeits, see StepLock::with_strikes
+    fn forfeit(&mut self, _player: PlayerId) -> Vec<HostMsg> {
+        Vec::new()
+    }
+    /// Time a player has left after its move, see StepLock::with_clock
+    fn time_left(&mut self, _player: PlayerId, _remaining: Duration) {}
     fn state(&mut self) -> Value {
         Value::Null
     }
@@ -0,0 +1,320 @@
+use super::Controller;
+use crate::generic::Runtime;
+use crate::modules::logger::{start_handler, DefaultLogHandler};
+use crate::modules::types::{HostMsg, PlayerId, PlayerMsg, Start};
+
+use async_std::path::{Path, PathBuf};
+use futures::channel::mpsc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Something that happened to the Controller of a game, see Runner
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    Start(Start),
+    Step(Vec<PlayerMsg>),
+    Forfeit(PlayerId),
+    TimeLeft(PlayerId, u64),
+    /// What the Controller answered the input before this
+    Output {
+        msgs: Vec<HostMsg>,
+        turn: Option<Vec<PlayerId>>,
+    },
+    /// The result from is_done, before the players are added
+    Done(Value),
+}
+
+/// One line of a replay file
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Entry {
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+    pub event: Event,
+}
+
+/// Where a replay and the Controller replaying it disagree
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    /// Index of the recorded entry
+    pub entry: usize,
+    pub expected: Value,
+    pub got: Value,
+}
+
+///
+/// Records the events of one game to a replay file, a line of JSON per Entry
+/// Clones write to the same file.
+///
+#[derive(Clone)]
+pub struct Recorder {
+    tx: mpsc::UnboundedSender<Entry>,
+}
+
+impl Recorder {
+    pub fn new<P: Into<PathBuf>>(path: P, runtime: &Runtime) -> Self {
+        let (tx, rx) = mpsc::unbounded();
+        let path = path.into();
+
+        runtime.spawn(async move {
+            match DefaultLogHandler::new(&path).await {
+                Some(handler) => start_handler(handler, rx).await,
+                None => error!(?path, "Cannot open replay file"),
+            }
+        });
+
+        Recorder { tx }
+    }
+
+    pub(super) fn record(&self, event: Event) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        if self.tx.unbounded_send(Entry { timestamp, event }).is_err() {
+            trace!("Replay file is closed");
+        }
+    }
+}
+
+/// Reads a replay file written by a Recorder
+pub async fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<Entry>> {
+    let content = async_std::fs::read_to_string(path).await?;
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+        .collect()
+}
+
+///
+/// Feeds the recorded inputs to a fresh game, checking that it answers like the recording
+/// Calls the Controller in the same order the Runner does. The players in Start are not
+/// compared, Controller::start does not get them, so they cannot change what the game does.
+///
+pub fn verify<G: Controller + ?Sized>(game: &mut G, entries: &[Entry]) -> Result<(), Mismatch> {
+    let mut expected = entries.iter().enumerate();
+
+    while let Some((i, entry)) = expected.next() {
+        let msgs = match &entry.event {
+            // Only where the game starts matters, see above
+            Event::Start(_) => game.start(),
+            Event::Step(msgs) => game.step(msgs.clone()),
+            Event::Forfeit(player) => game.forfeit(*player),
+            Event::TimeLeft(player, remaining) => {
+                game.time_left(*player, Duration::from_millis(*remaining));
+                continue;
+            }
+            // The game should have answered or been done already
+            recorded => return compare(i, Some(recorded), None),
+        };
+
+        let output = Event::Output {
+            msgs,
+            turn: game.turn(),
+        };
+        let (i, recorded) = match expected.next() {
+            Some((i, recorded)) => (i, Some(&recorded.event)),
+            None => (entries.len(), None),
+        };
+        compare(i, recorded, Some(&output))?;
+
+        if let Some(done) = game.is_done() {
+            let recorded = expected.next().map(|(_, recorded)| &recorded.event);
+            return compare(i + 1, recorded, Some(&Event::Done(done)));
+        }
+    }
+
+    Ok(())
+}
+
+fn compare(entry: usize, recorded: Option<&Event>, got: Option<&Event>) -> Result<(), Mismatch> {
+    let expected = serde_json::to_value(recorded).unwrap_or(Value::Null);
+    let got = serde_json::to_value(got).unwrap_or(Value::Null);
+
+    if expected == got {
+        Ok(())
+    } else {
+        Err(Mismatch {
+            entry,
+            expected,
+            got,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::game::{harness, BoxedBuilder, Builder, Manager};
+    use crate::modules::net::Register;
+    use crate::modules::types::Data;
+
+    use futures::channel::oneshot;
+    use futures::executor::{block_on, ThreadPool};
+
+    /// Echoes every move back to the player, done after `moves` moves
+    struct Echo {
+        moves: usize,
+        /// Tells player 1 to go when the game starts
+        greet: bool,
+    }
+
+    impl Controller for Echo {
+        fn start(&mut self) -> Vec<HostMsg> {
+            match self.greet {
+                true => vec![HostMsg::new(String::from("go"), Some(1))],
+                false => Vec::new(),
+            }
+        }
+
+        fn step(&mut self, turns: Vec<PlayerMsg>) -> Vec<HostMsg> {
+            self.moves = self.moves.saturating_sub(turns.len());
+            turns
+                .into_iter()
+                .filter_map(|msg| Some(HostMsg::Data(msg.data?, Some(msg.id))))
+                .collect()
+        }
+
+        fn is_done(&mut self) -> Option<Value> {
+            if self.moves == 0 {
+                Some(serde_json::json!({ "winner": 1 }))
+            } else {
+                None
+            }
+        }
+    }
+
+    fn entry(event: Event) -> Entry {
+        Entry {
+            timestamp: 0,
+            event,
+        }
+    }
+
+    fn step(value: &str) -> (Event, Event) {
+        let data = Data {
+            value: String::from(value),
+        };
+        let step = Event::Step(vec![PlayerMsg {
+            id: 1,
+            data: Some(data.clone()),
+        }]);
+        let output = Event::Output {
+            msgs: vec![HostMsg::Data(data, Some(1))],
+            turn: None,
+        };
+        (step, output)
+    }
+
+    #[test]
+    fn replays_match_their_game() {
+        let (first, first_out) = step("left");
+        let (second, second_out) = step("right");
+        let mut entries = vec![
+            entry(Event::Start(Start {
+                players: vec![(1, String::from("one"))],
+            })),
+            entry(Event::Output {
+                msgs: Vec::new(),
+                turn: None,
+            }),
+            entry(first),
+            entry(first_out),
+            entry(Event::TimeLeft(1, 500)),
+            entry(second),
+            entry(second_out),
+            entry(Event::Done(serde_json::json!({ "winner": 1 }))),
+        ];
+
+        let echo = |moves| Echo {
+            moves,
+            greet: false,
+        };
+        assert_eq!(verify(&mut echo(2), &entries), Ok(()));
+
+        // A game that takes longer is done later than the recording says
+        let mismatch = verify(&mut echo(3), &entries).unwrap_err();
+        assert_eq!(mismatch.entry, 7);
+        assert_eq!(mismatch.got, Value::Null);
+
+        // A disputed move shows up at its output
+        entries[3] = entry(step("up").1);
+        let mismatch = verify(&mut echo(2), &entries).unwrap_err();
+        assert_eq!(mismatch.entry, 3);
+    }
+
+    #[test]
+    fn recorded_games_replay() {
+        let dir = std::env::temp_dir().join(format!("mozaic-replays-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let pool = ThreadPool::new().unwrap();
+        let (players, rx) = mpsc::unbounded();
+        let (builder, _handle) = Manager::builder(pool.clone());
+        let manager = builder
+            .add_endpoint(harness::Endpoint(rx), "Endpoint")
+            .set_logger(harness::NoLogs, pool)
+            .build();
+
+        // The player needs the key the game was built with
+        let (keys_tx, keys_rx) = oneshot::channel();
+        let game = Echo {
+            moves: 2,
+            greet: true,
+        };
+        let game: BoxedBuilder = Builder::new(vec![1], game).with_replays(&dir).into();
+        let game: BoxedBuilder = Box::new(move |broker, gm_id, cm_id, logger_id, id| {
+            let (game_id, players) = game(broker, gm_id, cm_id, logger_id, id);
+            let _ = keys_tx.send(players.clone());
+            (game_id, players)
+        });
+
+        let (seen, _seen) = mpsc::unbounded();
+        let (_stay, leave) = oneshot::channel();
+        let (game, result) = block_on(async {
+            let game = manager.start_game(game).await.unwrap();
+            let key = keys_rx.await.unwrap().keys().copied().next().unwrap();
+            let register = Register {
+                id: key,
+                name: String::from("one"),
+                queue: None,
+                spectate: None,
+            };
+            let client = harness::client(register, seen, vec!["left", "right"], leave);
+            players.unbounded_send(client).unwrap();
+
+            (game, manager.wait_game(game).await.unwrap())
+        });
+        assert_eq!(result["winner"], 1);
+
+        // The recorder writes on its own, the game can be done before its replay is
+        let path = PathBuf::from(dir.join(format!("{}.replay", game)));
+        let mut entries = Vec::new();
+        for _ in 0..100 {
+            entries = block_on(load(&path)).unwrap_or_default();
+            if let Some(Event::Done(_)) = entries.last().map(|e| &e.event) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let moves = entries
+            .iter()
+            .filter(|e| matches!(e.event, Event::Step(_)))
+            .count();
+        assert_eq!(moves, 2);
+        let mut game = Echo {
+            moves: 2,
+            greet: true,
+        };
+        assert_eq!(verify(&mut game, &entries), Ok(()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
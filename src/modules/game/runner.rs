@@ -1,98 +1,235 @@
 use crate::generic::*;
-use crate::modules::types::{HostMsg, PlayerId, PlayerMsg, Start};
+use crate::modules::net::{Feed, Spectate, SpectatorLeft, Watch};
+use crate::modules::types::{
+    Data, GameProtocol, HostMsg, PlayerId, PlayerMsg, Start, TimeLeft, TimedOut, Turn,
+};
+use crate::modules::Transport;
 
+use super::replay::{Event, Recorder};
 use super::request::*;
 use super::GameBox;
 
 use std::any;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::time::Duration;
 
 use serde_json::Value;
 
-pub struct Runner {
+pub struct Runner<K = any::TypeId, M = Message> {
+    pd: PhantomData<fn() -> (K, M)>,
     clients_id: ReactorID,
     gm_id: ReactorID,
     logger_id: ReactorID,
     game: GameBox,
     game_id: u64,
+    recorder: Option<Recorder>,
 
     players: Vec<(PlayerId, String)>,
+    spectators: HashMap<ReactorID, (Spectate, SenderHandle<K, M>)>,
 }
 
-impl Runner {
+#[handlers(name = "Game", key = "K", message = "M", params = "into_params")]
+impl<K: KeyType, M: Transport<K>> Runner<K, M> {
     pub fn params(
         clients_id: ReactorID,
         gm_id: ReactorID,
         logger_id: ReactorID,
         game: GameBox,
         game_id: u64,
-    ) -> CoreParams<Self, any::TypeId, Message> {
-        let me = Self {
+    ) -> CoreParams<Self, K, M> {
+        Self::build(clients_id, gm_id, logger_id, game, game_id, None)
+    }
+
+    /// Also records everything the game gets and answers, see replay::verify
+    pub fn with_recorder(
+        clients_id: ReactorID,
+        gm_id: ReactorID,
+        logger_id: ReactorID,
+        game: GameBox,
+        game_id: u64,
+        recorder: Recorder,
+    ) -> CoreParams<Self, K, M> {
+        Self::build(clients_id, gm_id, logger_id, game, game_id, Some(recorder))
+    }
+
+    fn build(
+        clients_id: ReactorID,
+        gm_id: ReactorID,
+        logger_id: ReactorID,
+        game: GameBox,
+        game_id: u64,
+        recorder: Option<Recorder>,
+    ) -> CoreParams<Self, K, M> {
+        Self {
+            pd: PhantomData,
             clients_id,
             gm_id,
             game,
             logger_id,
             game_id,
+            recorder,
             players: Vec::new(),
-        };
-
-        CoreParams::new(me)
-            .handler(FunctionHandler::from(Self::handle_client_msg))
-            .handler(FunctionHandler::from(Self::handle_client_msgs))
-            .handler(FunctionHandler::from(Self::handle_kill))
-            .handler(FunctionHandler::from(Self::handle_start))
-            .handler(FunctionHandler::from(Self::handle_state_res))
+            spectators: HashMap::new(),
+        }
+        .into_params()
     }
 
+    #[handler]
     fn handle_state_res(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
+        handle: &mut ReactorHandle<K, M>,
         res: &Res<State>,
     ) {
         let res = Res::new(res.0, (self.game.state(), res.1.clone()));
         handle.send_internal(res, TargetReactor::Link(self.gm_id));
     }
 
-    fn handle_start(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, start: &Start) {
+    #[handler]
+    fn handle_start(&mut self, handle: &mut ReactorHandle<K, M>, start: &Start) {
         self.players = start.players.clone();
+        self.record(|| Event::Start(start.clone()));
 
-        for msg in self.game.start() {
-            handle.send_internal(msg, TargetReactor::Links);
-        }
-
-        self.maybe_close(handle);
+        let msgs = self.game.start();
+        self.output(handle, msgs);
     }
 
+    #[handler]
     fn handle_client_msg(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
-        msg: &PlayerMsg,
+        handle: &mut ReactorHandle<K, M>,
+        msg: PlayerMsg,
     ) {
-        for msg in self.game.step(vec![msg.clone()]) {
-            handle.send_internal(msg, TargetReactor::Links);
-        }
+        let msgs = vec![msg];
+        self.record(|| Event::Step(msgs.clone()));
 
-        self.maybe_close(handle);
+        let msgs = self.game.step(msgs);
+        self.output(handle, msgs);
     }
 
+    #[handler]
     fn handle_client_msgs(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
-        msgs: &Vec<PlayerMsg>,
+        handle: &mut ReactorHandle<K, M>,
+        msgs: Vec<PlayerMsg>,
     ) {
-        for msg in self.game.step(msgs.clone()) {
-            handle.send_internal(msg, TargetReactor::Links);
+        self.record(|| Event::Step(msgs.clone()));
+
+        let msgs = self.game.step(msgs);
+        self.output(handle, msgs);
+    }
+
+    /// Logs the timeout, a forfeit is up to the game
+    #[handler]
+    fn handle_timed_out(&mut self, handle: &mut ReactorHandle<K, M>, timed_out: &TimedOut) {
+        let event = serde_json::json!({ "game": self.game_id, "timed_out": timed_out });
+        handle.send_internal(event, TargetReactor::Link(self.logger_id));
+
+        if timed_out.forfeit {
+            self.record(|| Event::Forfeit(timed_out.player));
+
+            let msgs = self.game.forfeit(timed_out.player);
+            self.output(handle, msgs);
         }
+    }
 
-        self.maybe_close(handle);
+    #[handler]
+    fn handle_time_left(&mut self, _handle: &mut ReactorHandle<K, M>, time_left: &TimeLeft) {
+        self.record(|| Event::TimeLeft(time_left.player, time_left.remaining_ms));
+
+        let remaining = Duration::from_millis(time_left.remaining_ms);
+        self.game.time_left(time_left.player, remaining);
+    }
+
+    ///
+    /// The link to a spectator drops whatever it sends and tells when it leaves
+    /// Its feed is sent straight to it, a link only opens after the queued messages
+    /// are handled and the snapshot would be lost.
+    ///
+    #[handler]
+    fn handle_watch(&mut self, handle: &mut ReactorHandle<K, M>, watch: &Watch) {
+        let params = LinkParams::new(()).closer(|_, handle| {
+            handle.send_internal(SpectatorLeft(*handle.target_id()), TargetReactor::Reactor);
+        });
+        handle.open_link(watch.spectator, params, false);
+
+        let chan = handle.get(&watch.spectator);
+        if watch.spectate.snapshot {
+            let value = self.game.state().to_string();
+            chan.send(*handle.id(), Data { value });
+        }
+        self.spectators.insert(watch.spectator, (watch.spectate.clone(), chan));
     }
 
-    fn handle_kill(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, req: &Req<Kill>) {
+    #[handler]
+    fn handle_spectator_left(&mut self, _: &mut ReactorHandle<K, M>, left: &SpectatorLeft) {
+        self.spectators.remove(&left.0);
+    }
+
+    #[handler]
+    fn handle_kill(&mut self, handle: &mut ReactorHandle<K, M>, req: &Req<Kill>) {
         handle.send_internal(Res::<Kill>::default(req.0), TargetReactor::Link(self.gm_id));
         handle.close();
     }
 
-    fn maybe_close(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>) {
+    fn record<F: FnOnce() -> Event>(&self, event: F) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(event());
+        }
+    }
+
+    ///
+    /// Sends what the game answered, then who is to move next for games that take turns
+    /// The Controller is asked in the same order by replay::verify.
+    ///
+    fn output(&mut self, handle: &mut ReactorHandle<K, M>, msgs: Vec<HostMsg>) {
+        let turn = self.game.turn();
+        self.record(|| Event::Output {
+            msgs: msgs.clone(),
+            turn: turn.clone(),
+        });
+
+        self.spectate(handle, &msgs);
+        for msg in msgs {
+            handle.send_internal(msg, TargetReactor::Links);
+        }
+        if let Some(players) = turn {
+            handle.send_internal(Turn { players }, TargetReactor::Link(self.clients_id));
+        }
+
+        self.maybe_close(handle);
+    }
+
+    /// Sends every spectator its feed, the state is only asked when somebody watches it
+    fn spectate(&mut self, handle: &mut ReactorHandle<K, M>, msgs: &[HostMsg]) {
+        let state = if self.spectators.values().any(|(s, _)| s.feed == Feed::State) {
+            Some(self.game.state().to_string())
+        } else {
+            None
+        };
+
+        let id = *handle.id();
+        for (spectate, chan) in self.spectators.values() {
+            match (spectate.feed, &state) {
+                (Feed::State, Some(value)) => {
+                    let value = value.clone();
+                    chan.send(id, Data { value });
+                }
+                (Feed::Broadcasts, _) => {
+                    for msg in msgs {
+                        if let HostMsg::Data(data, None) = msg {
+                            chan.send(id, data.clone());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn maybe_close(&mut self, handle: &mut ReactorHandle<K, M>) {
         if let Some(mut value) = self.game.is_done() {
+            self.record(|| Event::Done(value.clone()));
             value.as_object_mut().map(|obj| {
                 obj.insert(
                     "players".to_string(),
@@ -104,42 +241,193 @@ impl Runner {
             handle.close();
         }
     }
-}
 
-impl ReactorState<any::TypeId, Message> for Runner {
-    const NAME: &'static str = "Game";
-    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
-        let client_params = LinkParams::new(())
-            .internal_handler(FunctionHandler::from(i_to_e::<(), HostMsg>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<(), Req<State>>()))
-            .external_handler(FunctionHandler::from(e_to_i::<(), PlayerMsg>(
-                TargetReactor::Reactor,
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<(), Start>(
-                TargetReactor::Reactor,
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<(), Vec<PlayerMsg>>(
-                TargetReactor::Reactor,
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<(), Res<State>>(
-                TargetReactor::Reactor,
-            )));
-        handle.open_link(self.clients_id, client_params, true);
+    #[init]
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, K, M>) {
+        handle.open_link(self.clients_id, GameProtocol::host(()), true);
 
         let gm_link_params = LinkParams::new(())
-            .internal_handler(FunctionHandler::from(i_to_e::<(), Res<(Value, State)>>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<(), Res<Kill>>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<(), (u64, Value)>()))
-            .external_handler(FunctionHandler::from(e_to_i::<(), Req<State>>(
-                TargetReactor::Link(self.clients_id),
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<(), Req<Kill>>(
-                TargetReactor::Reactor,
-            )));
+            .internal_handler(i_to_e::<(), Res<(Value, State)>, K, M>())
+            .internal_handler(i_to_e::<(), Res<Kill>, K, M>())
+            .internal_handler(i_to_e::<(), (u64, Value), K, M>())
+            .external_handler(e_to_i::<(), Req<State>, K, M>(TargetReactor::Link(self.clients_id)))
+            .external_handler(e_to_i::<(), Req<Kill>, K, M>(TargetReactor::Reactor))
+            .external_handler(e_to_i::<(), Watch, K, M>(TargetReactor::Reactor));
         handle.open_link(self.gm_id, gm_link_params, false);
 
         let logger_link_params =
-            LinkParams::new(()).internal_handler(FunctionHandler::from(i_to_e::<(), Value>()));
+            LinkParams::new(()).internal_handler(i_to_e::<(), Value, K, M>());
         handle.open_link(self.logger_id, logger_link_params, false);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::game::Controller;
+    use crate::modules::types::{Data, HostMsg};
+
+    use futures::channel::{mpsc, oneshot};
+    use futures::executor::{block_on, ThreadPool};
+    use futures::prelude::*;
+
+    struct OneStep(bool);
+    impl Controller for OneStep {
+        fn step(&mut self, _: Vec<PlayerMsg>) -> Vec<HostMsg> {
+            self.0 = true;
+            Vec::new()
+        }
+
+        fn is_done(&mut self) -> Option<Value> {
+            if self.0 {
+                Some(serde_json::json!({ "winner": 1 }))
+            } else {
+                None
+            }
+        }
+    }
+
+    struct Players(ReactorID);
+    impl ReactorState<String, JSONMessage> for Players {
+        const NAME: &'static str = "Players";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, String, JSONMessage>) {
+            handle.open_link(self.0, GameProtocol::players(()), true);
+
+            let players = vec![(1, String::from("one"))];
+            handle.send_internal(Start { players }, TargetReactor::Links);
+
+            let data = Some(Data {
+                value: String::from("move"),
+            });
+            handle.send_internal(PlayerMsg { id: 1, data }, TargetReactor::Links);
+        }
+    }
+
+    #[test]
+    fn runs_over_json() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::<String, JSONMessage>::new(pool);
+
+        let game_id = ReactorID::rand();
+        let players_id = ReactorID::rand();
+        let gm_id = ReactorID::rand();
+        let logger_id = ReactorID::rand();
+
+        let (done_tx, done_rx) = oneshot::channel();
+        let (gm_tx, gm_rx) = mpsc::unbounded();
+        let gm = async move {
+            let mut rx = receiver_handle(gm_rx).boxed();
+            while let Some(item) = rx.next().await {
+                if let Some((_, key, mut msg)) = item {
+                    if let Some(result) = <(u64, Value)>::from_msg(&key, &mut msg) {
+                        let _ = done_tx.send(result.clone());
+                        break;
+                    }
+                }
+            }
+        };
+        broker.spawn_reactorlike(gm_id, gm_tx, gm, "Game Manager");
+
+        let (logger_tx, logger_rx): (Sender<String, JSONMessage>, _) = mpsc::unbounded();
+        broker.spawn_reactorlike(logger_id, logger_tx, logger_rx.for_each(|_| async {}), "Logger");
+
+        let game = Box::new(OneStep(false));
+        broker.spawn(
+            Runner::params(players_id, gm_id, logger_id, game, 42),
+            Some(game_id),
+        );
+        broker.spawn(CoreParams::new(Players(game_id)), Some(players_id));
+
+        let expected = serde_json::json!({ "winner": 1, "players": [[1, "one"]] });
+        assert_eq!(block_on(done_rx), Ok((42, expected)));
+    }
+
+    /// Tells everybody about every move, one move long
+    struct Shout(u32);
+    impl Controller for Shout {
+        fn step(&mut self, _: Vec<PlayerMsg>) -> Vec<HostMsg> {
+            self.0 += 1;
+            let data = |value: &str| Data {
+                value: String::from(value),
+            };
+            vec![
+                HostMsg::Data(data("everybody"), None),
+                HostMsg::Data(data("only one"), Some(1)),
+            ]
+        }
+
+        fn state(&mut self) -> Value {
+            serde_json::json!(self.0)
+        }
+
+        fn is_done(&mut self) -> Option<Value> {
+            if self.0 > 0 {
+                Some(serde_json::json!({ "winner": 1 }))
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Reactor-like collecting the Data a spectator gets, until the game closes the link
+    fn spectator(
+        broker: &BrokerHandle<String, JSONMessage>,
+        id: ReactorID,
+    ) -> impl Future<Output = Vec<String>> {
+        let (tx, rx) = mpsc::unbounded();
+        let (seen_tx, seen_rx) = mpsc::unbounded();
+        let fut = async move {
+            let mut rx = receiver_handle(rx).boxed();
+            while let Some(Some((_, key, mut msg))) = rx.next().await {
+                if let Some(data) = Data::from_msg(&key, &mut msg) {
+                    let _ = seen_tx.unbounded_send(data.value.clone());
+                }
+            }
+        };
+        broker.spawn_reactorlike(id, tx, fut, "Spectator");
+        seen_rx.collect()
+    }
+
+    #[test]
+    fn spectators_watch_their_feed() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::<String, JSONMessage>::new(pool);
+
+        let game_id = ReactorID::rand();
+        let players_id = ReactorID::rand();
+        let gm_id = ReactorID::rand();
+        let logger_id = ReactorID::rand();
+        for id in [gm_id, logger_id] {
+            let (tx, rx): (Sender<String, JSONMessage>, _) = mpsc::unbounded();
+            broker.spawn_reactorlike(id, tx, rx.for_each(|_| async {}), "Sink");
+        }
+
+        let (broadcasts_id, state_id) = (ReactorID::rand(), ReactorID::rand());
+        let broadcasts = spectator(&broker, broadcasts_id);
+        let state = spectator(&broker, state_id);
+
+        let game = Box::new(Shout(0));
+        broker.spawn(
+            Runner::params(players_id, gm_id, logger_id, game, 42),
+            Some(game_id),
+        );
+
+        let runner = broker.get_sender(&game_id);
+        for (spectator, feed, snapshot) in
+            [(broadcasts_id, Feed::Broadcasts, false), (state_id, Feed::State, true)]
+        {
+            let spectate = Spectate {
+                game: 42,
+                feed,
+                snapshot,
+            };
+            runner.send(gm_id, Watch { spectator, spectate });
+        }
+        broker.spawn(CoreParams::new(Players(game_id)), Some(players_id));
+
+        assert_eq!(block_on(broadcasts), vec!["everybody"]);
+        // The snapshot, then the state after the start and after the move
+        assert_eq!(block_on(state), vec!["0", "0", "1"]);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::generic::*;
+use crate::modules::Translator;
+
+use serde::{Deserialize, Serialize};
+
+use std::any;
+
+type JSONTranslator = Translator<any::TypeId, String, Message, JSONMessage>;
+
+///
+/// The message types a Gateway translates
+/// Every type is translated both ways, keeping its Key<String> on the JSON side.
+/// Registry::game() is generated from the Transport type list in modules/mod.rs.
+///
+#[derive(Clone, Default)]
+pub struct Registry {
+    types: Vec<fn(&mut JSONTranslator)>,
+}
+
+fn add_type<T>(translator: &mut JSONTranslator)
+where
+    T: 'static + Send + Sync + Clone + Serialize + for<'de> Deserialize<'de> + Key<String>,
+{
+    translator.add_to(any::TypeId::of::<T>(), |k, m| T::from_msg(k, m).cloned());
+    translator.add_from(T::key(), |k, m| T::from_msg(k, m).cloned());
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<T>(mut self) -> Self
+    where
+        T: 'static + Send + Sync + Clone + Serialize + for<'de> Deserialize<'de> + Key<String>,
+    {
+        self.types.push(add_type::<T>);
+        self
+    }
+
+    fn translator(&self) -> JSONTranslator {
+        let mut translator = Translator::new();
+        for add in &self.types {
+            add(&mut translator);
+        }
+        translator
+    }
+}
+
+///
+/// Connects reactors on a TypeId/Message broker with reactors on a String/JSONMessage broker
+///
+/// Both reactors keep their ReactorID, each opens a link to the other as if it lived
+/// on the same broker. Closing that link closes the link on the other side.
+///
+pub struct Gateway {
+    registry: Registry,
+    typed: BrokerHandle<any::TypeId, Message>,
+    json: BrokerHandle<String, JSONMessage>,
+}
+
+impl Gateway {
+    pub fn new(
+        registry: Registry,
+        typed: BrokerHandle<any::TypeId, Message>,
+        json: BrokerHandle<String, JSONMessage>,
+    ) -> Self {
+        Self {
+            registry,
+            typed,
+            json,
+        }
+    }
+
+    /// Connects reactor typed_id on the typed broker with json_id on the JSON broker
+    pub fn connect(&self, typed_id: ReactorID, json_id: ReactorID) {
+        let mut translator = self.registry.translator();
+
+        let attach = translator.attach_to(json_id);
+        let (sender, fut) = attach(self.typed.get_sender(&typed_id));
+        self.typed
+            .spawn_reactorlike(json_id, sender, fut, "Gateway");
+
+        let attach = translator.attach_from(typed_id);
+        let (sender, fut) = attach(self.json.get_sender(&json_id));
+        self.json
+            .spawn_reactorlike(typed_id, sender, fut, "Gateway");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::types::Data;
+
+    use futures::channel::oneshot;
+    use futures::executor::{block_on, ThreadPool};
+
+    use std::sync::Mutex;
+
+    struct Pinger(ReactorID, Mutex<Option<oneshot::Sender<String>>>);
+    impl Pinger {
+        fn pong(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, data: &Data) {
+            if let Some(tx) = self.1.lock().unwrap().take() {
+                let _ = tx.send(data.value.clone());
+            }
+            handle.close();
+        }
+    }
+
+    impl ReactorState<any::TypeId, Message> for Pinger {
+        const NAME: &'static str = "Pinger";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+            let params = LinkParams::new(())
+                .internal_handler(i_to_e::<(), Data, _, _>())
+                .external_handler(e_to_i::<(), Data, _, _>(TargetReactor::Reactor));
+            handle.open_link(self.0, params, true);
+            handle.send_internal(
+                Data {
+                    value: String::from("ping"),
+                },
+                TargetReactor::Links,
+            );
+        }
+    }
+
+    struct Ponger(ReactorID);
+    impl Ponger {
+        fn ping(&mut self, handle: &mut ReactorHandle<String, JSONMessage>, data: &Data) {
+            let value = format!("{} pong", data.value);
+            handle.send_internal(Data { value }, TargetReactor::Links);
+        }
+    }
+
+    impl ReactorState<String, JSONMessage> for Ponger {
+        const NAME: &'static str = "Ponger";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, String, JSONMessage>) {
+            let params = LinkParams::new(())
+                .internal_handler(i_to_e::<(), Data, _, _>())
+                .external_handler(e_to_i::<(), Data, _, _>(TargetReactor::Reactor));
+            handle.open_link(self.0, params, true);
+        }
+    }
+
+    #[test]
+    fn bridges_both_ways() {
+        let pool = ThreadPool::new().unwrap();
+        let (typed, _typed_handle) = BrokerHandle::new(pool.clone());
+        let (json, _json_handle) = BrokerHandle::new(pool);
+
+        let pinger_id = ReactorID::rand();
+        let ponger_id = ReactorID::rand();
+        let ponger_closed = json.watch(&ponger_id);
+
+        Gateway::new(Registry::game(), typed.clone(), json.clone()).connect(pinger_id, ponger_id);
+
+        let (tx, rx) = oneshot::channel();
+        json.spawn(
+            CoreParams::new(Ponger(pinger_id)).handler(FunctionHandler::from(Ponger::ping)),
+            Some(ponger_id),
+        );
+        typed.spawn(
+            CoreParams::new(Pinger(ponger_id, Mutex::new(Some(tx))))
+                .handler(FunctionHandler::from(Pinger::pong)),
+            Some(pinger_id),
+        );
+
+        assert_eq!(block_on(rx), Ok(String::from("ping pong")));
+        // The pinger closing cascades through the gateway
+        assert_eq!(block_on(ponger_closed), Ok(CloseReason::Closed));
+    }
+}
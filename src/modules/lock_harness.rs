@@ -0,0 +1,204 @@
+//! A host and players around a lock under test, reporting what they see on one channel
+
+use super::types::{
+    Data, GameProtocol, HostMsg, PlayerId, PlayerMsg, Start, TimeLeft, TimedOut, Turn,
+};
+use crate::generic::*;
+
+use futures::channel::mpsc;
+use futures::executor::{block_on, ThreadPool};
+use futures::StreamExt;
+
+use std::any;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The host got a step with these moves
+    Step(Vec<(PlayerId, Option<String>)>),
+    /// The host was told player timed out, with their strikes and whether they forfeit
+    TimedOut(PlayerId, u32, bool),
+    /// The host was told the time left on the clock of player
+    TimeLeft(PlayerId, u64),
+    /// The players were told player is kicked
+    Kick(PlayerId),
+}
+
+/// What the host and the players do
+#[derive(Default)]
+pub struct Script {
+    /// Messages the host sends to the lock when it starts
+    pub host: Vec<HostMsg>,
+    /// Turns the host hands out, the first after Start and the next one after every step
+    pub turns: Vec<Vec<PlayerId>>,
+    /// The players send Start before their moves
+    pub start: bool,
+    /// Moves the players make when they start
+    pub moves: Vec<(PlayerId, &'static str)>,
+    /// Players that answer every message of the host right away
+    pub movers: Vec<PlayerId>,
+    /// Moves the players make once they see a player kicked
+    pub late: Vec<(PlayerId, &'static str)>,
+}
+
+type Events = mpsc::UnboundedSender<Event>;
+
+struct Host {
+    lock: ReactorID,
+    msgs: Vec<HostMsg>,
+    turns: Vec<Vec<PlayerId>>,
+    events: Events,
+}
+
+impl Host {
+    fn next_turn(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>) {
+        if !self.turns.is_empty() {
+            let players = self.turns.remove(0);
+            handle.send_internal(Turn { players }, TargetReactor::Links);
+        }
+    }
+
+    fn start(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, _: &Start) {
+        self.next_turn(handle);
+    }
+
+    fn step(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, msgs: Vec<PlayerMsg>) {
+        let moves = msgs
+            .into_iter()
+            .map(|msg| (msg.id, msg.data.map(|d| d.value)))
+            .collect();
+        let _ = self.events.unbounded_send(Event::Step(moves));
+        self.next_turn(handle);
+    }
+
+    fn timed_out(&mut self, _: &mut ReactorHandle<any::TypeId, Message>, t: &TimedOut) {
+        let _ = self
+            .events
+            .unbounded_send(Event::TimedOut(t.player, t.strikes, t.forfeit));
+    }
+
+    fn time_left(&mut self, _: &mut ReactorHandle<any::TypeId, Message>, t: &TimeLeft) {
+        let _ = self
+            .events
+            .unbounded_send(Event::TimeLeft(t.player, t.remaining_ms));
+    }
+}
+
+impl ReactorState<any::TypeId, Message> for Host {
+    const NAME: &'static str = "Host";
+
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+        handle.open_link(self.lock, GameProtocol::host(()), false);
+        for msg in self.msgs.drain(..) {
+            handle.send_internal(msg, TargetReactor::Links);
+        }
+    }
+}
+
+struct Players {
+    lock: ReactorID,
+    start: bool,
+    moves: Vec<(PlayerId, &'static str)>,
+    movers: Vec<PlayerId>,
+    late: Vec<(PlayerId, &'static str)>,
+    events: Events,
+}
+
+fn player_move(id: PlayerId, value: &str) -> PlayerMsg {
+    let data = Some(Data {
+        value: String::from(value),
+    });
+    PlayerMsg { id, data }
+}
+
+impl Players {
+    fn host_msg(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, m: &HostMsg) {
+        match m {
+            HostMsg::Kick(id) => {
+                let _ = self.events.unbounded_send(Event::Kick(*id));
+                for (id, value) in self.late.drain(..) {
+                    handle.send_internal(player_move(id, value), TargetReactor::Links);
+                }
+            }
+            HostMsg::Data(..) => {
+                for &id in &self.movers {
+                    handle.send_internal(player_move(id, "move"), TargetReactor::Links);
+                }
+            }
+        }
+    }
+}
+
+impl ReactorState<any::TypeId, Message> for Players {
+    const NAME: &'static str = "Players";
+
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+        handle.open_link(self.lock, GameProtocol::players(()), false);
+        if self.start {
+            handle.send_internal(Start { players: Vec::new() }, TargetReactor::Links);
+        }
+        for (id, value) in self.moves.drain(..) {
+            handle.send_internal(player_move(id, value), TargetReactor::Links);
+        }
+    }
+}
+
+/// Spawns the host, the lock built by lock(pool, host, players) and the players
+pub fn spawn<S, F>(script: Script, lock: F) -> mpsc::UnboundedReceiver<Event>
+where
+    S: 'static + Send + ReactorState<any::TypeId, Message> + Unpin,
+    F: FnOnce(ThreadPool, ReactorID, ReactorID) -> CoreParams<S, any::TypeId, Message>,
+{
+    let pool = ThreadPool::new().unwrap();
+    let (broker, handle) = BrokerHandle::new(pool.clone());
+    handle.forget();
+
+    let host_id = ReactorID::rand();
+    let lock_id = ReactorID::rand();
+    let players_id = ReactorID::rand();
+    let (events, rx) = mpsc::unbounded();
+
+    let host = Host {
+        lock: lock_id,
+        msgs: script.host,
+        turns: script.turns,
+        events: events.clone(),
+    };
+    broker.spawn(
+        CoreParams::new(host)
+            .handler(FunctionHandler::from(Host::start))
+            .handler(OwnedHandler::from(Host::step))
+            .handler(FunctionHandler::from(Host::timed_out))
+            .handler(FunctionHandler::from(Host::time_left)),
+        Some(host_id),
+    );
+
+    broker.spawn(lock(pool, host_id, players_id), Some(lock_id));
+
+    let players = Players {
+        lock: lock_id,
+        start: script.start,
+        moves: script.moves,
+        movers: script.movers,
+        late: script.late,
+        events,
+    };
+    broker.spawn(
+        CoreParams::new(players).handler(FunctionHandler::from(Players::host_msg)),
+        Some(players_id),
+    );
+
+    rx
+}
+
+/// Waits for the first event that f picks out, skipping the others
+pub fn wait<T, F>(rx: &mut mpsc::UnboundedReceiver<Event>, mut f: F) -> T
+where
+    F: FnMut(Event) -> Option<T>,
+{
+    loop {
+        let event = block_on(rx.next()).expect("the harness stopped");
+        if let Some(out) = f(event) {
+            return out;
+        }
+    }
+}
@@ -1,38 +1,50 @@
 use std::any;
+use std::marker::PhantomData;
 use std::pin::Pin;
 
 use futures::channel::mpsc;
-use futures::executor::ThreadPool;
 use futures::prelude::*;
 
 use crate::generic::*;
 
+use serde::{Deserialize, Serialize};
+
 use std::fmt::Debug;
 
-type BoxFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + 'a + Send>>;
+pub type BoxFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + 'a + Send>>;
 
-#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
 pub struct GameJoin(pub ReactorID);
 
 pub trait LogHandler<T> {
     fn handle<'a>(&'a mut self, log: T) -> BoxFuture<'a>;
 }
 
-pub struct Logger<T> {
+pub struct Logger<T, K = any::TypeId, M = Message> {
+    pd: PhantomData<fn() -> (K, M)>,
     tx: mpsc::UnboundedSender<T>,
     manager: ReactorID,
 }
 
-impl<T: 'static + Send + Clone> Logger<T> {
-    pub fn params<H: LogHandler<T> + Send + 'static>(
+impl<T, K, M> Logger<T, K, M>
+where
+    T: 'static + Send + Sync + Clone,
+    K: KeyType,
+    M: 'static + Send + Carrier<K, T> + Carrier<K, GameJoin>,
+{
+    pub fn params<H: LogHandler<T> + Send + 'static, R: Into<Runtime>>(
         manager: ReactorID,
         handler: H,
-        tp: ThreadPool,
-    ) -> CoreParams<Self, any::TypeId, Message> {
+        tp: R,
+    ) -> CoreParams<Self, K, M> {
         let (tx, rx) = mpsc::unbounded();
-        tp.spawn_ok(start_handler(handler, rx));
+        tp.into().spawn(start_handler(handler, rx));
 
-        let me = Self { tx, manager };
+        let me = Self {
+            pd: PhantomData,
+            tx,
+            manager,
+        };
 
         CoreParams::new(me)
             .handler(FunctionHandler::from(Self::handle_log))
@@ -41,35 +53,41 @@ impl<T: 'static + Send + Clone> Logger<T> {
 
     fn handle_game_join(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
+        handle: &mut ReactorHandle<K, M>,
         game: &GameJoin,
     ) {
-        let link = LinkParams::new(()).external_handler(FunctionHandler::from(e_to_i::<(), T>(
+        let link = LinkParams::new(()).external_handler(e_to_i::<(), T, K, M>(
             TargetReactor::Reactor,
-        )));
+        ));
         handle.open_link(game.0.clone(), link, false);
     }
 
-    fn handle_log(&mut self, _handle: &mut ReactorHandle<any::TypeId, Message>, log: &T) {
+    fn handle_log(&mut self, _handle: &mut ReactorHandle<K, M>, log: &T) {
         self.tx
             .unbounded_send(log.clone())
             .expect("Shit is failing here");
     }
 }
 
-impl<T> ReactorState<any::TypeId, Message> for Logger<T> {
+impl<T, K, M> ReactorState<K, M> for Logger<T, K, M>
+where
+    T: 'static + Send + Sync + Clone,
+    K: KeyType,
+    M: 'static + Send + Carrier<K, T> + Carrier<K, GameJoin>,
+{
     const NAME: &'static str = "Logger";
 
-    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, K, M>) {
         let manager_link =
-            LinkParams::new(()).external_handler(FunctionHandler::from(e_to_i::<(), GameJoin>(
+            LinkParams::new(()).external_handler(e_to_i::<(), GameJoin, K, M>(
                 TargetReactor::Reactor,
-            )));
+            ));
         handle.open_link(self.manager, manager_link, false);
     }
 }
 
-async fn start_handler<T, H: LogHandler<T> + Send + 'static>(
+/// Feeds everything from rx to handler, one at a time
+pub(crate) async fn start_handler<T, H: LogHandler<T> + Send + 'static>(
     mut handler: H,
     mut rx: mpsc::UnboundedReceiver<T>,
 ) {
@@ -84,7 +102,7 @@ async fn start_handler<T, H: LogHandler<T> + Send + 'static>(
     }
 }
 
-pub use default::DefaultLogHandler;
+pub use default::{BufferLogHandler, DefaultLogHandler};
 mod default {
     use super::BoxFuture;
     use super::LogHandler;
@@ -92,7 +110,9 @@ mod default {
     use async_std::fs::*;
     use async_std::path::Path;
     use async_std::prelude::*;
-    use serde_json::Value;
+    use serde::Serialize;
+
+    use std::sync::{Arc, Mutex};
 
     pub struct DefaultLogHandler {
         file: File,
@@ -117,8 +137,9 @@ mod default {
         }
     }
 
-    impl LogHandler<Value> for DefaultLogHandler {
-        fn handle<'a>(&'a mut self, vs: Value) -> BoxFuture<'a> {
+    /// Writes every log as a line of JSON
+    impl<T: Serialize + Send + 'static> LogHandler<T> for DefaultLogHandler {
+        fn handle<'a>(&'a mut self, vs: T) -> BoxFuture<'a> {
             Box::pin(async move {
                 let mut bytes = serde_json::to_vec(&vs).unwrap();
                 bytes.push(b'\n');
@@ -128,4 +149,42 @@ mod default {
             })
         }
     }
+    /// Keeps every log in memory, clones share the same buffer
+    pub struct BufferLogHandler<T> {
+        logs: Arc<Mutex<Vec<T>>>,
+    }
+
+    impl<T> BufferLogHandler<T> {
+        pub fn new() -> Self {
+            BufferLogHandler {
+                logs: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+
+        /// Everything logged so far, emptying the buffer
+        pub fn take(&self) -> Vec<T> {
+            std::mem::take(&mut *self.logs.lock().unwrap())
+        }
+    }
+
+    impl<T> Clone for BufferLogHandler<T> {
+        fn clone(&self) -> Self {
+            BufferLogHandler {
+                logs: self.logs.clone(),
+            }
+        }
+    }
+
+    impl<T> Default for BufferLogHandler<T> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<T: Send + 'static> LogHandler<T> for BufferLogHandler<T> {
+        fn handle<'a>(&'a mut self, log: T) -> BoxFuture<'a> {
+            self.logs.lock().unwrap().push(log);
+            Box::pin(async { Ok(()) })
+        }
+    }
 }
@@ -1,11 +1,17 @@
 mod translator;
 pub use translator::Translator;
 
+pub mod gateway;
+pub use gateway::Gateway;
+
 pub mod aggregator;
 pub use aggregator::Aggregator;
 
 mod steplock;
-pub use steplock::StepLock;
+pub use steplock::{StepLock, Strikes};
+
+mod turnlock;
+pub use turnlock::{OutOfTurn, TurnLock};
 
 // mod gamerunner;
 // pub use gamerunner::{GameBuilder, BoxedGameBuilder, GameController, GameRunner};
@@ -19,3 +25,88 @@ pub use net::{client_controller, ClientManager, EndpointBuilder, TcpEndpoint};
 pub mod game;
 pub mod types;
 pub mod logger;
+pub mod ratings;
+pub mod tournament;
+pub mod wiretap;
+
+#[cfg(test)]
+mod lock_harness;
+
+use crate::generic::{Carrier, ReactorID};
+use crate::util::request::{Connect, Kill, Req, Res, State};
+use aggregator::InitConnect;
+use logger::GameJoin;
+use net::client_controller::ClientClosed;
+use net::{
+    Accepted, BoxSpawnPlayer, PlayerUUIDs, RegisterEndpoint, RegisterGame, SpectatorLeft, Watch,
+};
+use serde_json::Value;
+use steplock::{ClockOut, ResetTimeOut, TimeOut};
+use turnlock::{TurnStarted, TurnTimeOut};
+use types::{Data, HostMsg, PlayerMsg, Start, TimeLeft, TimedOut, Turn};
+
+// Transport is a trait and a blanket impl over the same bounds, and the Gateway registry
+// translates the same types, this keeps all three in one list.
+// BoxSpawnPlayer names the message type itself and never leaves the process,
+// so it is added apart from the list.
+macro_rules! transport {
+    ($(#[$attr:meta])* $($ty:ty,)*) => {
+        $(#[$attr])*
+        pub trait Transport<K>:
+            'static + Send $(+ Carrier<K, $ty>)* + Carrier<K, BoxSpawnPlayer<K, Self>>
+        {
+        }
+
+        impl<K, M> Transport<K> for M where
+            M: 'static + Send $(+ Carrier<K, $ty>)* + Carrier<K, BoxSpawnPlayer<K, M>>
+        {
+        }
+
+        impl gateway::Registry {
+            /// Every serializable message of the game stack
+            pub fn game() -> Self {
+                Self::new()$(.register::<$ty>())*
+            }
+        }
+    };
+}
+
+transport! {
+    ///
+    /// A message type M that carries every message of the game stack, keyed with K
+    /// Message (keyed with TypeId) and JSONMessage (keyed with String) both do,
+    /// so the same modules run in-process or with serializable messages.
+    ///
+    PlayerMsg,
+    Vec<PlayerMsg>,
+    HostMsg,
+    Data,
+    Start,
+    Turn,
+    TimedOut,
+    TimeLeft,
+    Req<State>,
+    Res<State>,
+    Req<Connect>,
+    Res<Connect>,
+    Req<Kill>,
+    Res<Kill>,
+    Res<(Value, State)>,
+    (u64, Value),
+    Value,
+    InitConnect,
+    Accepted,
+    ClientClosed,
+    TimeOut,
+    ResetTimeOut,
+    ClockOut,
+    TurnStarted,
+    TurnTimeOut,
+    RegisterGame,
+    RegisterEndpoint,
+    PlayerUUIDs,
+    Watch,
+    SpectatorLeft,
+    GameJoin,
+    ReactorID,
+}
@@ -1,15 +1,21 @@
 use crate::generic::*;
-use crate::modules::aggregator::InitConnect;
+use crate::modules::aggregator::{ClientProtocol, InitConnect};
 use crate::modules::net::types::*;
 use crate::modules::types::*;
+use crate::modules::Transport;
 use crate::util::request::*;
 
+use serde::{Deserialize, Serialize};
+
 use std::any;
 use std::collections::VecDeque;
+use std::marker::PhantomData;
 
-struct ClientClosed;
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
+pub struct ClientClosed;
 
-pub struct ClientController {
+pub struct ClientController<K = any::TypeId, M = Message> {
+    pd: PhantomData<fn() -> (K, M)>,
     client_manager: ReactorID,
     host: ReactorID,
     client_id: PlayerId,
@@ -20,14 +26,15 @@ pub struct ClientController {
     key: u64,
 }
 
-impl ClientController {
+impl<K: KeyType, M: Transport<K>> ClientController<K, M> {
     pub fn params(
         client_manager: ReactorID,
         host: ReactorID,
         client_id: PlayerId,
         key: u64,
-    ) -> CoreParams<Self, any::TypeId, Message> {
+    ) -> CoreParams<Self, K, M> {
         CoreParams::new(Self {
+            pd: PhantomData,
             client_manager,
             host,
             client_id,
@@ -36,31 +43,31 @@ impl ClientController {
             buffer: VecDeque::new(),
             key,
         })
-        .handler(FunctionHandler::from(Self::handle_host_msg))
-        .handler(FunctionHandler::from(Self::handle_client_msg))
+        .handler(OwnedHandler::from(Self::handle_host_msg))
+        .handler(OwnedHandler::from(Self::handle_client_msg))
         .handler(FunctionHandler::from(Self::handle_conn))
         .handler(FunctionHandler::from(Self::handle_disc))
         .handler(FunctionHandler::from(Self::handle_conn_req))
     }
 
-    fn handle_host_msg(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, m: &HostMsg) {
+    fn handle_host_msg(&mut self, handle: &mut ReactorHandle<K, M>, m: HostMsg) {
         match m {
             HostMsg::Data(data, _) => {
                 if let Some(target) = self.client {
-                    handle.send_internal(data.clone(), TargetReactor::Link(target));
+                    handle.send_internal(data, TargetReactor::Link(target));
                 } else {
-                    self.buffer.push_back(data.clone());
+                    self.buffer.push_back(data);
                 }
             }
             HostMsg::Kick(_) => handle.close(),
         }
     }
 
-    fn handle_client_msg(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, m: &Data) {
+    fn handle_client_msg(&mut self, handle: &mut ReactorHandle<K, M>, m: Data) {
         info!(?m, "Got client msg");
         let msg = PlayerMsg {
             id: self.client_id,
-            data: Some(m.clone()),
+            data: Some(m),
         };
 
         handle.send_internal(msg, TargetReactor::Link(self.host));
@@ -68,7 +75,7 @@ impl ClientController {
 
     fn handle_conn_req(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
+        handle: &mut ReactorHandle<K, M>,
         req: &Req<Connect>,
     ) {
         if let Some(name) = &self.client_name {
@@ -91,18 +98,19 @@ impl ClientController {
         }
     }
 
-    fn handle_conn(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, accept: &Accepted) {
-        if self.client_name.is_none() {
-            handle.send_internal(InitConnect(self.client_id, accept.name.clone()), TargetReactor::Link(self.host));
-        }
+    /// The first Accepted registers the player with its game, later ones are reconnects
+    fn register(host: &mut ReactorID, handle: &mut LinkHandle<K, M>, accept: &Accepted) {
+        let init = InitConnect(accept.player, accept.name.clone());
+        handle.send_internal(init, TargetReactor::Link(*host));
+        handle.send_internal(accept.clone(), TargetReactor::Reactor);
+    }
 
+    fn handle_conn(&mut self, handle: &mut ReactorHandle<K, M>, accept: &Accepted) {
         info!("Opening link to client");
 
         let client_link_params = LinkParams::new(())
-            .external_handler(FunctionHandler::from(e_to_i::<(), Data>(
-                TargetReactor::Reactor,
-            )))
-            .internal_handler(FunctionHandler::from(i_to_e::<(), Data>()))
+            .external_handler(e_to_i::<(), Data, K, M>(TargetReactor::Reactor))
+            .internal_handler(i_to_e::<(), Data, K, M>())
             .closer(|_state, handle| {
                 handle.send_internal(ClientClosed, TargetReactor::Reactor);
             });
@@ -115,11 +123,11 @@ impl ClientController {
         self.flush_msgs(handle);
     }
 
-    fn handle_disc(&mut self, _handle: &mut ReactorHandle<any::TypeId, Message>, _: &ClientClosed) {
+    fn handle_disc(&mut self, _handle: &mut ReactorHandle<K, M>, _: &ClientClosed) {
         self.client = None;
     }
 
-    fn flush_msgs(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>) {
+    fn flush_msgs(&mut self, handle: &mut ReactorHandle<K, M>) {
         if let Some(target) = self.client {
             for data in self.buffer.drain(..) {
                 handle.send_internal(data, TargetReactor::Link(target));
@@ -128,26 +136,21 @@ impl ClientController {
     }
 }
 
-impl ReactorState<any::TypeId, Message> for ClientController {
+impl<K: KeyType, M: Transport<K>> ReactorState<K, M> for ClientController<K, M> {
     const NAME: &'static str = "Client Controller";
 
-    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
-        let host_link_params = LinkParams::new(())
-            .internal_handler(FunctionHandler::from(i_to_e::<(), PlayerMsg>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<(), Res<Connect>>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<(), InitConnect>()))
-            .external_handler(FunctionHandler::from(e_to_i::<(), Req<Connect>>(
-                TargetReactor::Reactor,
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<(), HostMsg>(
-                TargetReactor::Reactor,
-            )));
-        handle.open_link(self.host, host_link_params, true);
-
-        let cm_link_params =
-            LinkParams::new(()).external_handler(FunctionHandler::from(e_to_i::<(), Accepted>(
-                TargetReactor::Reactor,
-            )));
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, K, M>) {
+        handle.open_link(self.host, ClientProtocol::controller(()), true);
+
+        let register = goto(FunctionHandler::from(Self::register), "registered");
+        let cm_link_params = LinkParams::new(self.host)
+            .phase("registering", Phase::new().external_handler(register))
+            .phase(
+                "registered",
+                Phase::new().external_handler(e_to_i::<ReactorID, Accepted, K, M>(
+                    TargetReactor::Reactor,
+                )),
+            );
         handle.open_link(self.client_manager, cm_link_params, true);
     }
 }
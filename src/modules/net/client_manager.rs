@@ -1,6 +1,9 @@
 use crate::generic::*;
 use crate::modules::net::types::*;
 use crate::modules::types::*;
+use crate::modules::Transport;
+
+use serde::{Deserialize, Serialize};
 
 use std::sync::{Arc, Mutex};
 
@@ -8,30 +11,32 @@ use futures::future::Future;
 
 use std::any;
 use std::collections::HashMap;
+use std::marker::PhantomData;
 use std::pin::Pin;
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
 pub struct RegisterGame {
     pub game: u64,
     pub players: HashMap<u64, (PlayerId, ReactorID)>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
 pub struct PlayerUUIDs {
     game: u64,
     ids: Vec<u64>,
 }
 
-pub type BoxSpawnPlayer = Arc<Mutex<Option<SpawnPlayer>>>;
+/// Local, the builder cannot be serialized
+pub type BoxSpawnPlayer<K = any::TypeId, M = Message> = Local<Arc<Mutex<Option<SpawnPlayer<K, M>>>>>;
 
-pub struct SpawnPlayer {
+pub struct SpawnPlayer<K = any::TypeId, M = Message> {
     pub register: Register,
     pub builder: Box<
         dyn FnOnce(
                 ReactorID,
-                SenderHandle<any::TypeId, Message>,
+                SenderHandle<K, M>,
             ) -> (
-                Sender<any::TypeId, Message>,
+                Sender<K, M>,
                 Pin<Box<dyn Future<Output = ()> + Send>>,
                 &'static str,
             ) + Send
@@ -40,13 +45,13 @@ pub struct SpawnPlayer {
     >,
 }
 
-impl SpawnPlayer {
+impl<K, M> SpawnPlayer<K, M> {
     pub fn new<
         F: FnOnce(
                 ReactorID,
-                SenderHandle<any::TypeId, Message>,
+                SenderHandle<K, M>,
             ) -> (
-                Sender<any::TypeId, Message>,
+                Sender<K, M>,
                 Pin<Box<dyn Future<Output = ()> + Send>>,
                 &'static str,
             )
@@ -56,51 +61,74 @@ impl SpawnPlayer {
     >(
         register: Register,
         f: F,
-    ) -> BoxSpawnPlayer {
-        Arc::new(Mutex::new(Some(Self {
+    ) -> BoxSpawnPlayer<K, M> {
+        Self {
             register,
             builder: Box::new(f),
-        })))
+        }
+        .boxed()
+    }
+
+    pub fn boxed(self) -> BoxSpawnPlayer<K, M> {
+        Local(Arc::new(Mutex::new(Some(self))))
     }
 }
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
 pub struct RegisterEndpoint(pub ReactorID);
 
-pub struct ClientManager {
+pub struct ClientManager<K = any::TypeId, M = Message> {
+    pd: PhantomData<fn() -> (K, M)>,
     clients: HashMap<u64, (PlayerId, ReactorID)>,
     game_manager: ReactorID,
     endpoints: Vec<RegisterEndpoint>,
+    matchmaker: Option<ReactorID>,
 }
 
-impl ClientManager {
+#[handlers(name = "Client Manager", key = "K", message = "M", params = "into_params")]
+impl<K: KeyType, M: Transport<K>> ClientManager<K, M> {
     pub fn new(
         game_manager: ReactorID,
         endpoints: Vec<ReactorID>,
-    ) -> CoreParams<Self, any::TypeId, Message> {
-        let me = Self {
+    ) -> CoreParams<Self, K, M> {
+        Self::build(game_manager, endpoints, None)
+    }
+
+    /// Players with an unknown key go to the matchmaker, it sends them back with their key
+    pub fn with_matchmaker(
+        game_manager: ReactorID,
+        endpoints: Vec<ReactorID>,
+        matchmaker: ReactorID,
+    ) -> CoreParams<Self, K, M> {
+        Self::build(game_manager, endpoints, Some(matchmaker))
+    }
+
+    fn build(
+        game_manager: ReactorID,
+        endpoints: Vec<ReactorID>,
+        matchmaker: Option<ReactorID>,
+    ) -> CoreParams<Self, K, M> {
+        Self {
+            pd: PhantomData,
             game_manager,
             clients: HashMap::new(),
             endpoints: endpoints.iter().map(|x| RegisterEndpoint(*x)).collect(),
-        };
-
-        CoreParams::new(me)
-            .handler(FunctionHandler::from(Self::handle_spawn_game))
-            .handler(FunctionHandler::from(Self::handle_cc_close))
-            .handler(FunctionHandler::from(Self::handle_register_endpoint))
-            .handler(FunctionHandler::from(Self::handle_player_regiser))
+            matchmaker,
+        }
+        .into_params()
     }
 
+    #[handler]
     fn handle_spawn_game(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
+        handle: &mut ReactorHandle<K, M>,
         cs: &RegisterGame,
     ) {
         self.clients.extend(cs.players.clone());
 
         for (_, cc) in cs.players.values() {
             let cc_params = LinkParams::new(())
-                .internal_handler(FunctionHandler::from(i_to_e::<(), Accepted>()))
+                .internal_handler(i_to_e::<(), Accepted, K, M>())
                 .closer(|_, handle| {
                     handle.send_internal(*handle.target_id(), TargetReactor::Reactor);
                 });
@@ -108,7 +136,8 @@ impl ClientManager {
         }
     }
 
-    fn handle_cc_close(&mut self, _: &mut ReactorHandle<any::TypeId, Message>, id: &ReactorID) {
+    #[handler]
+    fn handle_cc_close(&mut self, _: &mut ReactorHandle<K, M>, id: &ReactorID) {
         let orig_len = self.clients.len();
 
         if orig_len == 0 {
@@ -127,67 +156,77 @@ impl ClientManager {
         }
     }
 
+    #[handler]
     fn handle_register_endpoint(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
+        handle: &mut ReactorHandle<K, M>,
         reg: &RegisterEndpoint,
     ) {
         let ep_link_params =
-            LinkParams::new(()).external_handler(FunctionHandler::from(
-                e_to_i::<(), BoxSpawnPlayer>(TargetReactor::Reactor),
+            LinkParams::new(()).external_handler(e_to_i::<(), BoxSpawnPlayer<K, M>, K, M>(
+                TargetReactor::Reactor,
             ));
         handle.open_link(reg.0, ep_link_params, false);
     }
 
+    #[handler]
     fn handle_player_regiser(
         &mut self,
-        handle: &mut ReactorHandle<any::TypeId, Message>,
-        reg: &BoxSpawnPlayer,
+        handle: &mut ReactorHandle<K, M>,
+        reg: &BoxSpawnPlayer<K, M>,
     ) {
         let mut reg = reg.lock().unwrap();
         let reg = std::mem::replace(&mut *reg, None);
 
-        if let Some(SpawnPlayer {  register: Register { id, name }, builder }) = reg {
-            if let Some((player, cc)) = self.clients.get(&id) {
+        if let Some(SpawnPlayer { register, builder }) = reg {
+            let Register { id, ref name, .. } = register;
+            if register.spectate.is_some() {
+                // The game manager knows the games to watch
+                let spawn = SpawnPlayer { register, builder }.boxed();
+                handle.send_internal(spawn, TargetReactor::Link(self.game_manager));
+            } else if let Some((player, cc)) = self.clients.get(&id) {
                 let id = ReactorID::rand();
                 let (chan, fut, handler_name) = builder(id, handle.get(cc));
                 handle.open_reactor_like(id, chan, fut, handler_name);
 
                 let accept = Accepted {
                     player: *player,
-                    name,
+                    name: name.clone(),
                     client_id: id,
                     contr_id: *cc,
                 };
                 handle.send_internal(accept.clone(), TargetReactor::Link(*cc));
+            } else if let Some(matchmaker) = self.matchmaker {
+                let spawn = SpawnPlayer { register, builder }.boxed();
+                handle.send_internal(spawn, TargetReactor::Link(matchmaker));
             }
         }
     }
-}
-
-impl ReactorState<any::TypeId, Message> for ClientManager {
-    const NAME: &'static str = "Client Manager";
 
-    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+    #[init]
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, K, M>) {
         for reg in &self.endpoints {
             let ep_link_params =
-                LinkParams::new(()).external_handler(FunctionHandler::from(e_to_i::<
-                    (),
-                    BoxSpawnPlayer,
-                >(
+                LinkParams::new(()).external_handler(e_to_i::<(), BoxSpawnPlayer<K, M>, K, M>(
                     TargetReactor::Reactor,
-                )));
+                ));
             handle.open_link(reg.0, ep_link_params, false);
         }
 
         let gm_link_params = LinkParams::new(())
-            .internal_handler(FunctionHandler::from(i_to_e::<(), PlayerUUIDs>()))
-            .external_handler(FunctionHandler::from(e_to_i::<(), RegisterGame>(
-                TargetReactor::Reactor,
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<(), RegisterEndpoint>(
-                TargetReactor::Reactor,
-            )));
+            .internal_handler(i_to_e::<(), PlayerUUIDs, K, M>())
+            .internal_handler(i_to_e::<(), BoxSpawnPlayer<K, M>, K, M>())
+            .external_handler(e_to_i::<(), RegisterGame, K, M>(TargetReactor::Reactor))
+            .external_handler(e_to_i::<(), RegisterEndpoint, K, M>(TargetReactor::Reactor));
         handle.open_link(self.game_manager, gm_link_params, false);
+
+        if let Some(matchmaker) = self.matchmaker {
+            let mm_link_params = LinkParams::new(())
+                .internal_handler(i_to_e::<(), BoxSpawnPlayer<K, M>, K, M>())
+                .external_handler(e_to_i::<(), BoxSpawnPlayer<K, M>, K, M>(
+                    TargetReactor::Reactor,
+                ));
+            handle.open_link(matchmaker, mm_link_params, false);
+        }
     }
 }
@@ -1,6 +1,6 @@
 use super::types::*;
 mod types;
-pub use types::Register;
+pub use types::{Accepted, Feed, Register, Spectate, SpectatorLeft, Watch};
 
 pub mod client_controller;
 
@@ -32,7 +32,9 @@ pub mod client_controller;
 ///                                            +--------------+------------>+------------------+
 ///
 mod client_manager;
-pub use client_manager::{ClientManager, PlayerUUIDs, RegisterGame, SpawnPlayer};
+pub use client_manager::{
+    BoxSpawnPlayer, ClientManager, PlayerUUIDs, RegisterEndpoint, RegisterGame, SpawnPlayer,
+};
 
 mod tcp_endpoint;
 pub use tcp_endpoint::TcpEndpoint;
@@ -45,13 +47,13 @@ use futures::future::Future;
 use std::any;
 use std::pin::Pin;
 
-pub trait EndpointBuilder {
+pub trait EndpointBuilder<K = any::TypeId, M = Message> {
     fn build(
         self,
         id: ReactorID,
-        cm_chan: SenderHandle<any::TypeId, Message>,
+        cm_chan: SenderHandle<K, M>,
     ) -> (
-        Sender<any::TypeId, Message>,
+        Sender<K, M>,
         Pin<Box<dyn Future<Output = Option<()>> + Send>>,
     );
 }
@@ -2,9 +2,9 @@ use crate::generic::*;
 use crate::modules::net::{EndpointBuilder, SpawnPlayer};
 use crate::modules::net::types::Register;
 use crate::modules::types::*;
+use crate::modules::Transport;
 
 use futures::channel::mpsc;
-use futures::executor::ThreadPool;
 use futures::stream::StreamExt;
 use futures::*;
 
@@ -12,22 +12,21 @@ use futures::io::*;
 
 use async_std::net;
 
-use std::any;
 use std::net::SocketAddr;
 use std::pin::Pin;
 
 pub struct Builder {
     addr: SocketAddr,
-    tp: ThreadPool,
+    tp: Runtime,
 }
 
-impl EndpointBuilder for Builder {
+impl<K: KeyType, M: Transport<K>> EndpointBuilder<K, M> for Builder {
     fn build(
         self,
         id: ReactorID,
-        cm_chan: SenderHandle<any::TypeId, Message>,
+        cm_chan: SenderHandle<K, M>,
     ) -> (
-        Sender<any::TypeId, Message>,
+        Sender<K, M>,
         Pin<Box<dyn Future<Output = Option<()>> + Send>>,
     ) {
         TcpEndpoint::build(id, self.addr, cm_chan, self.tp)
@@ -37,17 +36,23 @@ impl EndpointBuilder for Builder {
 pub struct TcpEndpoint;
 impl TcpEndpoint {
     /// Spawn reactor_like TcpEndpoint to handle clients connecting to this address
-    pub fn new(addr: SocketAddr, tp: ThreadPool) -> impl EndpointBuilder {
-        Builder { addr, tp }
+    pub fn new<K: KeyType, M: Transport<K>, R: Into<Runtime>>(
+        addr: SocketAddr,
+        tp: R,
+    ) -> impl EndpointBuilder<K, M> {
+        Builder {
+            addr,
+            tp: tp.into(),
+        }
     }
 
-    fn build(
+    fn build<K: KeyType, M: Transport<K>>(
         id: ReactorID,
         addr: SocketAddr,
-        cm_chan: SenderHandle<any::TypeId, Message>,
-        tp: ThreadPool,
+        cm_chan: SenderHandle<K, M>,
+        tp: Runtime,
     ) -> (
-        Sender<any::TypeId, Message>,
+        Sender<K, M>,
         Pin<Box<dyn Future<Output = Option<()>> + Send>>,
     ) {
         let (tx, rx) = mpsc::unbounded();
@@ -55,12 +60,12 @@ impl TcpEndpoint {
     }
 }
 
-async fn accepting(
+async fn accepting<K: KeyType, M: Transport<K>>(
     id: ReactorID,
     addr: SocketAddr,
-    rx: Receiver<any::TypeId, Message>,
-    cm_chan: SenderHandle<any::TypeId, Message>,
-    tp: ThreadPool,
+    rx: Receiver<K, M>,
+    cm_chan: SenderHandle<K, M>,
+    tp: Runtime,
 ) -> Option<()> {
     let mut rx = receiver_handle(rx).boxed().fuse();
 
@@ -72,7 +77,7 @@ async fn accepting(
             socket = listener.next() => {
                 info!("Got new socket");
 
-                tp.spawn_ok(handle_socket(id, socket?.ok()?, cm_chan.clone()).map(
+                tp.spawn(handle_socket(id, socket?.ok()?, cm_chan.clone()).map(
                     |_| ()
                 ));
             },
@@ -90,10 +95,10 @@ async fn accepting(
     Some(())
 }
 
-async fn handle_socket(
+async fn handle_socket<K: KeyType, M: Transport<K>>(
     id: ReactorID,
     stream: net::TcpStream,
-    cm_chan: SenderHandle<any::TypeId, Message>,
+    cm_chan: SenderHandle<K, M>,
 ) -> Option<()> {
     let (stream, player): (net::TcpStream, Register) = {
         let mut line = String::new();
@@ -121,8 +126,7 @@ async fn handle_socket(
     cm_chan.send(
         id,
         SpawnPlayer::new(player, move |s_id, cc_chan| {
-            let (tx, rx): (Sender<any::TypeId, Message>, Receiver<any::TypeId, Message>) =
-                mpsc::unbounded();
+            let (tx, rx): (Sender<K, M>, Receiver<K, M>) = mpsc::unbounded();
 
             (
                 tx,
@@ -140,11 +144,11 @@ async fn handle_socket(
     Some(())
 }
 
-async fn handle_spawn(
+async fn handle_spawn<K: KeyType, M: Transport<K>>(
     stream: net::TcpStream,
     s_id: ReactorID,
-    cc_chan: SenderHandle<any::TypeId, Message>,
-    rx: Receiver<any::TypeId, Message>,
+    cc_chan: SenderHandle<K, M>,
+    rx: Receiver<K, M>,
 ) -> Option<()> {
     let mut rx = receiver_handle(rx).boxed().fuse();
     let (rh, mut writer) = stream.split();
@@ -173,7 +177,9 @@ async fn handle_spawn(
                         break;
                     }
                 };
-                if cc_chan.send(s_id, Data { value }).is_none() {
+                // Every line starts a new trace, following it through all reactors
+                let span = trace_span!(parent: None, "Player message", player = %s_id);
+                if span.in_scope(|| cc_chan.send(s_id, Data { value })).is_none() {
                     error!("Something something client error");
                 };
             },
@@ -5,10 +5,50 @@ use crate::generic::ReactorID;
 
 #[derive(Serialize, Deserialize, Clone, Key, Debug)]
 pub struct Register {
+    /// The player key, spectators do not need one
+    #[serde(default)]
     pub id: u64,
     pub name: String,
+    /// The matchmaking queue to join, id is then the identity of the player
+    #[serde(default)]
+    pub queue: Option<String>,
+    /// Watch this game instead of playing
+    #[serde(default)]
+    pub spectate: Option<Spectate>,
 }
 
+/// What a spectator gets to see
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Feed {
+    /// Host messages that go to every player
+    #[default]
+    Broadcasts,
+    /// Controller::state after every step
+    State,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Spectate {
+    pub game: u64,
+    #[serde(default)]
+    pub feed: Feed,
+    /// Also send the current state when joining
+    #[serde(default)]
+    pub snapshot: bool,
+}
+
+/// A spectator joins the game, it can only receive Data
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
+pub struct Watch {
+    pub spectator: ReactorID,
+    pub spectate: Spectate,
+}
+
+/// The link to a spectator closed, the game stops sending it its feed
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
+pub struct SpectatorLeft(pub ReactorID);
+
 #[derive(Serialize, Deserialize, Clone, Key, Debug)]
 pub struct Accepted {
     pub player: PlayerId,
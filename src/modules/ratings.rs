@@ -0,0 +1,413 @@
+use crate::modules::logger::{BoxFuture, LogHandler};
+use crate::modules::types::PlayerId;
+
+use async_std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::io;
+
+/// A persistent player, like the id players register with at the Matchmaker
+pub type Identity = u64;
+
+/// Converts between the Glicko and the Glicko-2 scale
+const GLICKO_SCALE: f64 = 173.7178;
+
+/// Glicko-2 rating, on the Glicko scale
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Glicko {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Glicko {
+    fn default() -> Self {
+        Glicko {
+            rating: 1500.0,
+            deviation: 350.0,
+            volatility: 0.06,
+        }
+    }
+}
+
+impl Glicko {
+    fn mu(&self) -> f64 {
+        (self.rating - 1500.0) / GLICKO_SCALE
+    }
+
+    fn phi(&self) -> f64 {
+        self.deviation / GLICKO_SCALE
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Rating {
+    pub elo: f64,
+    pub glicko: Glicko,
+    pub games: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            elo: 1500.0,
+            glicko: Glicko::default(),
+            games: 0,
+        }
+    }
+}
+
+///
+/// The result of one game, groups of players from best to worst
+/// Players in the same group tied, every player is scored against all others.
+///
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Outcome(pub Vec<Vec<PlayerId>>);
+
+impl Outcome {
+    pub fn win(winner: PlayerId, loser: PlayerId) -> Self {
+        Outcome(vec![vec![winner], vec![loser]])
+    }
+
+    pub fn draw(players: Vec<PlayerId>) -> Self {
+        Outcome(vec![players])
+    }
+
+    /// Players from first to last place, without ties
+    pub fn ranking(players: Vec<PlayerId>) -> Self {
+        Outcome(players.into_iter().map(|id| vec![id]).collect())
+    }
+
+    /// The same outcome for the identities of the players, None when one of them has none
+    pub fn identities(&self, identities: &HashMap<PlayerId, Identity>) -> Option<Self> {
+        self.0
+            .iter()
+            .map(|group| group.iter().map(|id| identities.get(id).copied()).collect())
+            .collect::<Option<_>>()
+            .map(Outcome)
+    }
+
+    /// Every player with its score against each of the others, 1 for a win and 0.5 for a draw
+    fn scores(&self) -> Vec<(PlayerId, Vec<(PlayerId, f64)>)> {
+        let placed: Vec<(usize, PlayerId)> = self
+            .0
+            .iter()
+            .enumerate()
+            .flat_map(|(place, group)| group.iter().map(move |&id| (place, id)))
+            .collect();
+
+        placed
+            .iter()
+            .map(|&(place, id)| {
+                let scores = placed
+                    .iter()
+                    .filter(|&&(_, other)| other != id)
+                    .map(|&(other_place, other)| {
+                        let score = match place.cmp(&other_place) {
+                            std::cmp::Ordering::Less => 1.0,
+                            std::cmp::Ordering::Equal => 0.5,
+                            std::cmp::Ordering::Greater => 0.0,
+                        };
+                        (other, score)
+                    })
+                    .collect();
+                (id, scores)
+            })
+            .collect()
+    }
+}
+
+///
+/// Elo and Glicko-2 ratings of every player that finished a game
+/// Every game is its own Glicko-2 rating period. In games with more players the Elo
+/// K factor is split over the opponents.
+/// Ratings belong to identities, a PlayerId only names a player within one game.
+///
+#[derive(Clone, Debug)]
+pub struct Ratings {
+    players: HashMap<Identity, Rating>,
+    k_factor: f64,
+    tau: f64,
+}
+
+impl Ratings {
+    pub fn new() -> Self {
+        Ratings {
+            players: HashMap::new(),
+            k_factor: 32.0,
+            tau: 0.5,
+        }
+    }
+
+    pub fn with_k_factor(mut self, k_factor: f64) -> Self {
+        self.k_factor = k_factor;
+        self
+    }
+
+    /// Glicko-2 system constant, lower values keep the volatility from changing much
+    pub fn with_tau(mut self, tau: f64) -> Self {
+        self.tau = tau;
+        self
+    }
+
+    /// Reads ratings saved to path, a missing file is no ratings yet
+    pub async fn load<P: AsRef<Path>>(mut self, path: P) -> io::Result<Self> {
+        match async_std::fs::read(path).await {
+            Ok(bytes) => self.players = serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(self)
+    }
+
+    /// Everything load reads back
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(&self.players).unwrap_or(Value::Null)
+    }
+
+    pub fn get(&self, player: Identity) -> Option<Rating> {
+        self.players.get(&player).copied()
+    }
+
+    /// Every player, the best Glicko-2 rating first
+    pub fn leaderboard(&self) -> Vec<(Identity, Rating)> {
+        let mut board: Vec<_> = self.players.iter().map(|(&id, &r)| (id, r)).collect();
+        board.sort_by(|a, b| {
+            b.1.glicko
+                .rating
+                .partial_cmp(&a.1.glicko.rating)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        board
+    }
+
+    /// Updates everybody in the game, against the ratings they had before it
+    /// The outcome names identities, see Outcome::identities.
+    pub fn record(&mut self, outcome: &Outcome) {
+        let before = self.players.clone();
+        let rating = |id: &PlayerId| before.get(id).copied().unwrap_or_default();
+
+        for (id, scores) in outcome.scores() {
+            if scores.is_empty() {
+                continue;
+            }
+
+            let old = rating(&id);
+            let k = self.k_factor / scores.len() as f64;
+            let elo = old.elo
+                + scores
+                    .iter()
+                    .map(|(other, score)| k * (score - expected_elo(old.elo, rating(other).elo)))
+                    .sum::<f64>();
+
+            let opponents: Vec<_> = scores
+                .iter()
+                .map(|(other, score)| (rating(other).glicko, *score))
+                .collect();
+
+            self.players.insert(
+                id,
+                Rating {
+                    elo,
+                    glicko: glicko2(old.glicko, &opponents, self.tau),
+                    games: old.games + 1,
+                },
+            );
+        }
+    }
+}
+
+impl Default for Ratings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn expected_elo(rating: f64, other: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((other - rating) / 400.0))
+}
+
+/// One Glicko-2 rating period, as in Glickman's "Example of the Glicko-2 system"
+fn glicko2(player: Glicko, opponents: &[(Glicko, f64)], tau: f64) -> Glicko {
+    let g = |phi: f64| 1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt();
+    let e = |mu: f64, other: &Glicko| 1.0 / (1.0 + (-g(other.phi()) * (mu - other.mu())).exp());
+
+    let (mu, phi, sigma) = (player.mu(), player.phi(), player.volatility);
+
+    let v = 1.0
+        / opponents
+            .iter()
+            .map(|(o, _)| g(o.phi()).powi(2) * e(mu, o) * (1.0 - e(mu, o)))
+            .sum::<f64>();
+    let improvement: f64 = opponents
+        .iter()
+        .map(|(o, score)| g(o.phi()) * (score - e(mu, o)))
+        .sum();
+    let delta = v * improvement;
+
+    // New volatility, with the Illinois algorithm
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2))
+            - (x - a) / (tau * tau)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * tau) < 0.0 {
+            k += 1.0;
+        }
+        a - k * tau
+    };
+
+    let (mut f_a, mut f_b) = (f(big_a), f(big_b));
+    while (big_b - big_a).abs() > 0.000_001 {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+    let volatility = (big_a / 2.0).exp();
+
+    let phi_star = (phi * phi + volatility * volatility).sqrt();
+    let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+    let new_mu = mu + new_phi * new_phi * improvement;
+
+    Glicko {
+        rating: GLICKO_SCALE * new_mu + 1500.0,
+        deviation: GLICKO_SCALE * new_phi,
+        volatility,
+    }
+}
+
+///
+/// Replaces the file at path with every snapshot it gets, see Ratings::to_json
+/// Snapshots go to path.tmp first, so a crash while saving keeps the previous one.
+///
+pub(crate) struct RatingsFile(pub PathBuf);
+
+impl LogHandler<Value> for RatingsFile {
+    fn handle<'a>(&'a mut self, ratings: Value) -> BoxFuture<'a> {
+        Box::pin(async move {
+            let bytes = serde_json::to_vec_pretty(&ratings).map_err(|e| e.to_string())?;
+
+            let mut tmp = self.0.as_os_str().to_os_string();
+            tmp.push(".tmp");
+            let tmp = PathBuf::from(tmp);
+
+            async_std::fs::write(&tmp, bytes)
+                .await
+                .map_err(|e| format!("Cannot save ratings: {:?}", e))?;
+            async_std::fs::rename(&tmp, &self.0)
+                .await
+                .map_err(|e| format!("Cannot save ratings: {:?}", e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::block_on;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 0.01
+    }
+
+    #[test]
+    fn glickman_example() {
+        let player = Glicko {
+            rating: 1500.0,
+            deviation: 200.0,
+            volatility: 0.06,
+        };
+        let opponent = |rating, deviation| Glicko {
+            rating,
+            deviation,
+            volatility: 0.06,
+        };
+        let games = [
+            (opponent(1400.0, 30.0), 1.0),
+            (opponent(1550.0, 100.0), 0.0),
+            (opponent(1700.0, 300.0), 0.0),
+        ];
+
+        let rated = glicko2(player, &games, 0.5);
+        assert!(close(rated.rating, 1464.06), "{:?}", rated);
+        assert!(close(rated.deviation, 151.52), "{:?}", rated);
+        assert!((rated.volatility - 0.05999).abs() < 0.00001, "{:?}", rated);
+    }
+
+    #[test]
+    fn rankings_score_every_pair() {
+        let mut ratings = Ratings::new();
+        ratings.record(&Outcome::win(1, 2));
+        assert_eq!(ratings.get(1).map(|r| r.elo), Some(1516.0));
+        assert_eq!(ratings.get(2).map(|r| r.elo), Some(1484.0));
+
+        // The middle player beat one and lost to one, against equal ratings that is even
+        let mut ratings = Ratings::new();
+        ratings.record(&Outcome::ranking(vec![3, 4, 5]));
+        let board: Vec<_> = ratings.leaderboard().iter().map(|(id, _)| *id).collect();
+        assert_eq!(board, vec![3, 4, 5]);
+        assert!(close(ratings.get(4).unwrap().elo, 1500.0));
+        assert!(ratings.get(4).unwrap().glicko.deviation < 350.0);
+    }
+
+    #[test]
+    fn outcomes_need_every_identity() {
+        let identities: HashMap<_, _> = vec![(1, 10), (2, 20)].into_iter().collect();
+
+        let outcome = Outcome(vec![vec![2], vec![1]]).identities(&identities);
+        assert_eq!(outcome, Some(Outcome::win(20, 10)));
+        assert_eq!(Outcome::win(1, 3).identities(&identities), None);
+    }
+
+    #[test]
+    fn saved_ratings_load_back() {
+        let dir = std::env::temp_dir().join(format!("mozaic-ratings-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = PathBuf::from(dir.join("ratings.json"));
+
+        let mut ratings = Ratings::new();
+        ratings.record(&Outcome::win(1, 2));
+        ratings.record(&Outcome::draw(vec![2, 3]));
+
+        block_on(async {
+            RatingsFile(path.clone()).handle(ratings.to_json()).await.unwrap();
+            // Saving again replaces the snapshot
+            RatingsFile(path.clone()).handle(ratings.to_json()).await.unwrap();
+
+            let loaded = Ratings::new().load(&path).await.unwrap();
+            assert_eq!(loaded.leaderboard(), ratings.leaderboard());
+
+            let missing = PathBuf::from(dir.join("missing.json"));
+            let missing = Ratings::new().load(&missing).await.unwrap();
+            assert!(missing.leaderboard().is_empty());
+        });
+
+        // Nothing is left behind next to it
+        let files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, vec![std::ffi::OsString::from("ratings.json")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,43 +1,108 @@
-use super::types::{Data, HostMsg, PlayerId, PlayerMsg, Start};
+use super::types::{Data, GameProtocol, HostMsg, PlayerId, PlayerMsg, Start, TimeLeft, TimedOut};
+use super::Transport;
 use crate::generic::*;
+use crate::util::request::*;
 
 use futures::channel::mpsc;
-use futures::executor::ThreadPool;
+use futures::future::{self, AbortHandle};
 use futures::{FutureExt, StreamExt};
 
 use async_std::task::sleep;
 
+use serde::{Deserialize, Serialize};
+
 use std::collections::HashMap;
-use std::time::Duration;
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
 use std::{any, mem};
 
-#[derive(Clone, Debug)]
-struct TimeOut;
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
+pub struct TimeOut;
+
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
+pub struct ResetTimeOut;
 
+/// The clock of a player ran out, unless it was stopped since it started
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
+pub struct ClockOut {
+    player: PlayerId,
+    started: u64,
+}
+
+/// Time bank of a player, see StepLock::with_clock
 #[derive(Clone, Debug)]
-struct ResetTimeOut;
+struct Clock {
+    remaining: Duration,
+    running: Option<Instant>,
+    /// How often the clock started, telling stale ClockOuts apart
+    started: u64,
+    /// Sends the ClockOut when the clock runs out, aborted when it stops first
+    timer: Option<AbortHandle>,
+}
 
-#[derive(Clone)]
-pub struct StepLock {
+/// When a player that keeps timing out forfeits, see StepLock::with_strikes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strikes {
+    /// This many timeouts in a row
+    Consecutive(u32),
+    /// This many timeouts in the whole game
+    Total(u32),
+}
+
+pub struct StepLock<K = any::TypeId, M = Message> {
+    pd: PhantomData<fn() -> (K, M)>,
     step: HashMap<PlayerId, Option<Data>>,
     players: Vec<PlayerId>,
     host: ReactorID,
     player_id: ReactorID,
     timeout_ms: Option<Duration>,
     init_timeout_ms: Option<Duration>,
-    tp: ThreadPool,
+    strikes: Option<Strikes>,
+    /// Consecutive and total timeouts per player
+    timeouts: HashMap<PlayerId, (u32, u32)>,
+    increment: Duration,
+    clocks: HashMap<PlayerId, Clock>,
+    timer_id: ReactorID,
+    tp: Runtime,
 }
 
-impl StepLock {
-    pub fn new(players: Vec<PlayerId>, tp: ThreadPool) -> Self {
+impl<K, M> Clone for StepLock<K, M> {
+    fn clone(&self) -> Self {
         Self {
+            pd: PhantomData,
+            step: self.step.clone(),
+            players: self.players.clone(),
+            host: self.host,
+            player_id: self.player_id,
+            timeout_ms: self.timeout_ms,
+            init_timeout_ms: self.init_timeout_ms,
+            strikes: self.strikes,
+            timeouts: self.timeouts.clone(),
+            increment: self.increment,
+            clocks: self.clocks.clone(),
+            timer_id: self.timer_id,
+            tp: self.tp.clone(),
+        }
+    }
+}
+
+#[handlers(key = "K", message = "M", params = "into_params")]
+impl<K: KeyType, M: Transport<K>> StepLock<K, M> {
+    pub fn new<R: Into<Runtime>>(players: Vec<PlayerId>, tp: R) -> Self {
+        Self {
+            pd: PhantomData,
             host: 0.into(),
             player_id: 0.into(),
             step: players.iter().map(|&id| (id, None)).collect(),
             players,
             timeout_ms: None,
             init_timeout_ms: None,
-            tp,
+            strikes: None,
+            timeouts: HashMap::new(),
+            increment: Duration::from_secs(0),
+            clocks: HashMap::new(),
+            timer_id: 0.into(),
+            tp: tp.into(),
         }
     }
 
@@ -51,94 +116,117 @@ impl StepLock {
         self
     }
 
+    /// Kicks players that time out too often, the host is told they forfeit
+    pub fn with_strikes(mut self, strikes: Strikes) -> Self {
+        self.strikes = Some(strikes);
+        self
+    }
+
+    ///
+    /// Gives every player a time bank, like a chess clock
+    /// A clock runs from a HostMsg to the player until its move, the increment is added after
+    /// every move. Players that run out of time forfeit, the host gets a TimeLeft for every move.
+    ///
+    pub fn with_clock(mut self, bank: Duration, increment: Duration) -> Self {
+        self.increment = increment;
+        self.clocks = self
+            .players
+            .iter()
+            .map(|&id| {
+                let clock = Clock {
+                    remaining: bank,
+                    running: None,
+                    started: 0,
+                    timer: None,
+                };
+                (id, clock)
+            })
+            .collect();
+        self
+    }
+
     pub fn params(
         mut self,
         host: ReactorID,
         player_id: ReactorID,
-    ) -> CoreParams<Self, any::TypeId, Message> {
+    ) -> CoreParams<Self, K, M> {
         self.host = host;
         self.player_id = player_id;
-        CoreParams::new(self)
-            .handler(FunctionHandler::from(Self::player_msg))
-            .handler(FunctionHandler::from(Self::timeout))
-            .handler(FunctionHandler::from(Self::host_msg))
+        self.into_params()
     }
 
     /// Insert the player message in the buffered message
-    fn player_msg(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, e: &PlayerMsg) {
+    #[handler]
+    fn player_msg(&mut self, handle: &mut ReactorHandle<K, M>, e: PlayerMsg) {
+        let data = match e.data {
+            Some(data) => data,
+            None => {
+                info!(player = e.id, "Ignoring player msg without data");
+                return;
+            }
+        };
+
+        if !self.step.contains_key(&e.id) {
+            info!(player = e.id, "Ignoring player msg of a player that forfeited");
+            return;
+        }
+
         info!("Got player data");
-        self.step.insert(
-            e.id,
-            Some(
-                e.data
-                    .as_ref()
-                    .expect("Player msg from player without DATA?")
-                    .clone(),
-            ),
-        );
+        if !self.stop_clock(handle, e.id, true) {
+            self.run_out(handle, e.id);
+            return;
+        }
+
+        self.step.insert(e.id, Some(data));
         if self.step.values().all(Option::is_some) {
             self.flush_msgs(handle);
         }
     }
 
-    fn host_msg(&mut self, _handle: &mut ReactorHandle<any::TypeId, Message>, m: &HostMsg) {
-        if let HostMsg::Kick(id) = m {
-            self.step.remove(&id);
+    #[handler]
+    fn host_msg(&mut self, handle: &mut ReactorHandle<K, M>, m: &HostMsg) {
+        match m {
+            HostMsg::Kick(id) => {
+                self.step.remove(id);
+            }
+            HostMsg::Data(_, Some(id)) => self.start_clock(handle, *id),
+            HostMsg::Data(_, None) => {
+                let players: Vec<_> = self.step.keys().copied().collect();
+                for id in players {
+                    self.start_clock(handle, id);
+                }
+            }
         }
     }
 
-    fn timeout(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>, _e: &TimeOut) {
-        self.flush_msgs(handle);
-    }
-
-    /// Flush all messages to the host
-    fn flush_msgs(&mut self, handle: &mut ReactorHandle<any::TypeId, Message>) {
-        handle.send_internal(ResetTimeOut, TargetReactor::Links);
-        let mut player_msgs = Vec::new();
-        for (&id, msg) in self.step.iter_mut() {
-            let msg = PlayerMsg {
-                id,
-                data: mem::replace(msg, None),
-            };
-            player_msgs.push(msg);
+    #[handler]
+    fn clock_out(&mut self, handle: &mut ReactorHandle<K, M>, e: &ClockOut) {
+        let running = self
+            .clocks
+            .get(&e.player)
+            .filter(|clock| clock.running.is_some() && clock.started == e.started);
+        if running.is_some() && !self.stop_clock(handle, e.player, false) {
+            self.run_out(handle, e.player);
         }
-        handle.send_internal(player_msgs, TargetReactor::Link(self.host));
     }
-}
 
-use crate::util::request::*;
-
-impl ReactorState<any::TypeId, Message> for StepLock {
-    const NAME: &'static str = "StepLock";
+    #[handler]
+    fn timeout(&mut self, handle: &mut ReactorHandle<K, M>, _e: &TimeOut) {
+        self.flush_msgs(handle);
+    }
 
-    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, any::TypeId, Message>) {
+    #[init]
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, K, M>) {
         // Open link to host
-        let host_link_params = LinkParams::new(())
-            .internal_handler(FunctionHandler::from(i_to_e::<(), PlayerMsg>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<(), Vec<PlayerMsg>>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<(), Res<State>>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<(), Start>()))
-            .external_handler(FunctionHandler::from(e_to_i::<(), HostMsg>(
-                TargetReactor::All,
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<(), Req<State>>(
-                TargetReactor::Link(self.player_id),
-            )));
+        let host_link_params = GameProtocol::players(())
+            .route::<HostMsg>(TargetReactor::All)
+            .route::<Req<State>>(TargetReactor::Link(self.player_id));
         handle.open_link(self.host, host_link_params, true);
 
         // Open link to client
-        let client_link_params = LinkParams::new(())
-            .internal_handler(FunctionHandler::from(i_to_e::<(), HostMsg>()))
-            .internal_handler(FunctionHandler::from(i_to_e::<(), Req<State>>()))
-            .external_handler(FunctionHandler::from(e_to_i::<(), PlayerMsg>(
-                TargetReactor::Reactor,
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<(), Start>(
-                TargetReactor::Link(self.host),
-            )))
-            .external_handler(FunctionHandler::from(e_to_i::<(), Res<State>>(
-                TargetReactor::Link(self.host),
-            )));
+        let client_link_params = GameProtocol::host(())
+            .route::<Start>(TargetReactor::Link(self.host))
+            .route::<Res<State>>(TargetReactor::Link(self.host));
         handle.open_link(self.player_id, client_link_params, true);
 
         // Start timing out
@@ -147,11 +235,11 @@ impl ReactorState<any::TypeId, Message> for StepLock {
         let self_send_f = handle.chan();
 
         let timeout_params = LinkParams::new(())
-            .internal_handler(FunctionHandler::from(i_to_e::<(), ResetTimeOut>()))
-            .external_handler(FunctionHandler::from(e_to_i::<(), TimeOut>(
-                TargetReactor::Reactor,
-            )));
+            .internal_handler(i_to_e::<(), ResetTimeOut, K, M>())
+            .external_handler(e_to_i::<(), TimeOut, K, M>(TargetReactor::Reactor))
+            .external_handler(e_to_i::<(), ClockOut, K, M>(TargetReactor::Reactor));
         handle.open_link(timeout_id, timeout_params, true);
+        self.timer_id = timeout_id;
 
         let timeout_ms = self.timeout_ms.clone();
         let init_timeout = self.init_timeout_ms.clone();
@@ -203,4 +291,281 @@ impl ReactorState<any::TypeId, Message> for StepLock {
 
         handle.open_reactor_like(timeout_id, tx, fut, "Time-out Generator");
     }
+
+    /// Starts the clock of a player that still has to move this step
+    fn start_clock(&mut self, handle: &mut ReactorHandle<K, M>, player: PlayerId) {
+        if !matches!(self.step.get(&player), Some(None)) {
+            return;
+        }
+
+        let clock = match self.clocks.get_mut(&player) {
+            Some(clock) if clock.running.is_none() => clock,
+            _ => return,
+        };
+        clock.running = Some(Instant::now());
+        clock.started += 1;
+
+        let out = ClockOut {
+            player,
+            started: clock.started,
+        };
+        let remaining = clock.remaining;
+        let timer_id = self.timer_id;
+        let self_send_f = handle.chan();
+        let (timer, abort) = future::abortable(async move {
+            sleep(remaining).await;
+            self_send_f.send(timer_id, out);
+        });
+        clock.timer = Some(abort);
+        self.tp.spawn(timer.map(|_| ()));
+    }
+
+    ///
+    /// Stops the clock of a player, reporting the time it has left to the host
+    /// Returns false when the player ran out of time, true when its clock was not running.
+    ///
+    fn stop_clock(
+        &mut self,
+        handle: &mut ReactorHandle<K, M>,
+        player: PlayerId,
+        moved: bool,
+    ) -> bool {
+        let clock = match self.clocks.get_mut(&player) {
+            Some(clock) => clock,
+            None => return true,
+        };
+        if let Some(timer) = clock.timer.take() {
+            timer.abort();
+        }
+        let elapsed = match clock.running.take() {
+            Some(start) => start.elapsed(),
+            None => return true,
+        };
+
+        let in_time = elapsed < clock.remaining;
+        clock.remaining = clock.remaining.saturating_sub(elapsed);
+        if in_time && moved {
+            clock.remaining += self.increment;
+        }
+
+        let time_left = TimeLeft {
+            player,
+            remaining_ms: clock.remaining.as_millis() as u64,
+        };
+        handle.send_internal(time_left, TargetReactor::Link(self.host));
+        in_time
+    }
+
+    /// A player without time left forfeits, the step goes on without them
+    fn run_out(&mut self, handle: &mut ReactorHandle<K, M>, player: PlayerId) {
+        self.forfeit(handle, player);
+
+        if !self.step.is_empty() && self.step.values().all(Option::is_some) {
+            self.flush_msgs(handle);
+        }
+    }
+
+    fn forfeit(&mut self, handle: &mut ReactorHandle<K, M>, player: PlayerId) {
+        info!(player, "Player ran out of time");
+        self.step.remove(&player);
+        handle.send_internal(HostMsg::Kick(player), TargetReactor::Link(self.player_id));
+
+        let timed_out = TimedOut {
+            player,
+            strikes: self.timeouts.get(&player).map_or(0, |t| t.1),
+            forfeit: true,
+        };
+        handle.send_internal(timed_out, TargetReactor::Link(self.host));
+    }
+
+    /// Flush all messages to the host
+    fn flush_msgs(&mut self, handle: &mut ReactorHandle<K, M>) {
+        handle.send_internal(ResetTimeOut, TargetReactor::Links);
+
+        // Players that did not move stop their clock, they may be out of time by now
+        let waiting: Vec<_> = self
+            .step
+            .iter()
+            .filter(|(_, data)| data.is_none())
+            .map(|(&id, _)| id)
+            .collect();
+        let out: Vec<_> = waiting
+            .into_iter()
+            .filter(|&id| !self.stop_clock(handle, id, false))
+            .collect();
+        for id in out {
+            self.forfeit(handle, id);
+        }
+        let mut player_msgs = Vec::new();
+        for (&id, msg) in self.step.iter_mut() {
+            let msg = PlayerMsg {
+                id,
+                data: mem::replace(msg, None),
+            };
+            player_msgs.push(msg);
+        }
+
+        let mut timed_out = Vec::new();
+        for msg in &player_msgs {
+            let timeouts = self.timeouts.entry(msg.id).or_default();
+            if msg.data.is_some() {
+                timeouts.0 = 0;
+            } else {
+                *timeouts = (timeouts.0 + 1, timeouts.1 + 1);
+                timed_out.push(self.strike(msg.id));
+            }
+        }
+        handle.send_internal(player_msgs, TargetReactor::Link(self.host));
+
+        timed_out.sort_by_key(|t| t.player);
+        for t in timed_out {
+            if t.forfeit {
+                info!(player = t.player, strikes = t.strikes, "Player forfeits");
+                self.step.remove(&t.player);
+                let kick = HostMsg::Kick(t.player);
+                handle.send_internal(kick, TargetReactor::Link(self.player_id));
+            }
+            handle.send_internal(t, TargetReactor::Link(self.host));
+        }
+    }
+
+    /// Strikes for a player that just timed out, without a policy they never forfeit
+    fn strike(&self, player: PlayerId) -> TimedOut {
+        let (consecutive, total) = self.timeouts[&player];
+        let (strikes, forfeit) = match self.strikes {
+            Some(Strikes::Consecutive(max)) => (consecutive, consecutive >= max),
+            Some(Strikes::Total(max)) => (total, total >= max),
+            None => (total, false),
+        };
+
+        TimedOut {
+            player,
+            strikes,
+            forfeit,
+        }
+    }
+}
+
+/// Clocks that still run when the lock closes have nothing left to time out
+impl<K, M> Drop for StepLock<K, M> {
+    fn drop(&mut self) {
+        for timer in self.clocks.values_mut().filter_map(|clock| clock.timer.take()) {
+            timer.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::lock_harness::{self, Event, Script};
+
+    #[test]
+    fn repeat_offenders_forfeit() {
+        // Only player 1 moves, once
+        let script = Script {
+            moves: vec![(1, "move")],
+            ..Script::default()
+        };
+        let mut rx = lock_harness::spawn(script, |pool, host, players| {
+            StepLock::new(vec![1, 2], pool)
+                .with_init_timeout(Duration::from_millis(50))
+                .with_timeout(Duration::from_millis(50))
+                .with_strikes(Strikes::Consecutive(2))
+                .params(host, players)
+        });
+
+        let mut timeouts = Vec::new();
+        while !matches!(timeouts.last(), Some(&(_, _, true))) {
+            let timeout = lock_harness::wait(&mut rx, |e| match e {
+                Event::TimedOut(player, strikes, forfeit) => Some((player, strikes, forfeit)),
+                _ => None,
+            });
+            timeouts.push(timeout);
+        }
+        let kicked = lock_harness::wait(&mut rx, |e| match e {
+            Event::Kick(player) => Some(player),
+            _ => None,
+        });
+
+        // Player 2 never moves and is out after two steps, player 1 only missed one
+        assert_eq!(timeouts, vec![(2, 1, false), (1, 1, false), (2, 2, true)]);
+        assert_eq!(kicked, 2);
+    }
+
+    #[test]
+    fn forfeited_players_stay_out() {
+        // Player 2 never moves in time, it moves once it is kicked and player 1 moves after it
+        let script = Script {
+            moves: vec![(1, "move")],
+            late: vec![(2, "late"), (1, "next")],
+            ..Script::default()
+        };
+        let mut rx = lock_harness::spawn(script, |pool, host, players| {
+            StepLock::new(vec![1, 2], pool)
+                .with_init_timeout(Duration::from_millis(50))
+                .with_timeout(Duration::from_millis(50))
+                .with_strikes(Strikes::Consecutive(1))
+                .params(host, players)
+        });
+
+        let kicked = lock_harness::wait(&mut rx, |e| match e {
+            Event::Kick(player) => Some(player),
+            _ => None,
+        });
+        let step = lock_harness::wait(&mut rx, |e| match e {
+            Event::Step(moves) if moves.contains(&(1, Some(String::from("next")))) => Some(moves),
+            _ => None,
+        });
+
+        // The move of player 2 came too late, the host never gets it
+        assert_eq!(kicked, 2);
+        assert_eq!(step, vec![(1, Some(String::from("next")))]);
+    }
+
+    #[test]
+    fn clocks_run_out() {
+        // Everybody has to move, only player 1 does so right away
+        // Player 2 still moves after it ran out, player 1 moves again after that
+        let script = Script {
+            host: vec![HostMsg::new(String::from("go"), None)],
+            movers: vec![1],
+            late: vec![(2, "late"), (1, "next")],
+            ..Script::default()
+        };
+        let mut rx = lock_harness::spawn(script, |pool, host, players| {
+            StepLock::new(vec![1, 2], pool)
+                .with_clock(Duration::from_millis(100), Duration::from_secs(1))
+                .params(host, players)
+        });
+
+        let mut time_left = Vec::new();
+        let mut forfeits = Vec::new();
+        let moved = lock_harness::wait(&mut rx, |e| match e {
+            Event::TimeLeft(player, remaining) => {
+                time_left.push((player, remaining));
+                None
+            }
+            Event::TimedOut(player, _, true) => {
+                forfeits.push(player);
+                None
+            }
+            Event::Step(moves) => Some(moves.into_iter().map(|(id, _)| id).collect::<Vec<_>>()),
+            _ => None,
+        });
+
+        // Player 1 moved in time and got the increment, player 2 ran out and forfeits
+        assert_eq!(time_left.len(), 2);
+        assert!(time_left[0].0 == 1 && time_left[0].1 > 1000);
+        assert_eq!(time_left[1], (2, 0));
+        assert_eq!(forfeits, vec![2]);
+        assert_eq!(moved, vec![1]);
+
+        // The late move of player 2 never reaches the host
+        let moved = lock_harness::wait(&mut rx, |e| match e {
+            Event::Step(moves) => Some(moves),
+            _ => None,
+        });
+        assert_eq!(moved, vec![(1, Some(String::from("next")))]);
+    }
 }
@@ -0,0 +1,572 @@
+use crate::generic::{KeyType, Message};
+use crate::modules::game::{BoxedBuilder, Manager};
+use crate::modules::types::PlayerId;
+use crate::modules::Transport;
+
+use futures::stream::{self, StreamExt};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::any;
+use std::collections::{HashMap, HashSet};
+
+/// How the players of a tournament are paired, every game has two players
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Every player plays every other player once
+    RoundRobin,
+    /// Players with the same score meet, without rematches when possible
+    /// A bye is worth as many points as a win.
+    Swiss { rounds: usize },
+    /// Out after the first loss
+    SingleElimination,
+    /// Out after the second loss, a grand final lost by the winners side is replayed
+    DoubleElimination,
+}
+
+///
+/// What a tournament plays, see Tournament::new
+/// Players are in seed order, a draw in an elimination format counts as a loss
+/// for the lower seed. Games that were not played count for nobody, in an elimination
+/// format neither player goes through.
+///
+pub trait Competition<K = any::TypeId, M = Message> {
+    /// The game these players play
+    fn game(&mut self, players: &[PlayerId]) -> BoxedBuilder<K, M>;
+    /// The winner of a finished game, None for a draw
+    fn winner(&self, players: &[PlayerId], result: &Value) -> Option<PlayerId>;
+}
+
+/// How a game of a round went
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// Not done yet
+    Pending,
+    Played,
+    /// Manager did not start the game
+    Unplayed,
+    /// The game started but closed without a result, e.g. it was killed
+    Errored,
+}
+
+/// One game of a round
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Pairing {
+    pub players: Vec<PlayerId>,
+    pub status: Status,
+    /// The id Manager gave this game, None when it did not start
+    pub game: Option<u64>,
+    pub result: Option<Value>,
+    pub winner: Option<PlayerId>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct Round {
+    pub games: Vec<Pairing>,
+    pub byes: Vec<PlayerId>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Standing {
+    pub player: PlayerId,
+    pub points: f64,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub byes: u32,
+}
+
+///
+/// Runs a competition through a game Manager, one round at a time
+/// A round only starts when the previous one is done, at most max_games run at once.
+///
+pub struct Tournament<C> {
+    format: Format,
+    players: Vec<PlayerId>,
+    competition: C,
+    max_games: usize,
+    rounds: Vec<Round>,
+}
+
+impl<C> Tournament<C> {
+    /// Players are given in seed order, the best first
+    pub fn new(format: Format, players: Vec<PlayerId>, competition: C) -> Self {
+        Self {
+            format,
+            players,
+            competition,
+            max_games: usize::MAX,
+            rounds: Vec::new(),
+        }
+    }
+
+    pub fn max_games(mut self, max_games: usize) -> Self {
+        self.max_games = max_games.max(1);
+        self
+    }
+
+    /// Plays every round, returning the final standings
+    pub async fn run<K, M>(&mut self, manager: &Manager<K, M>) -> Vec<Standing>
+    where
+        C: Competition<K, M>,
+        K: KeyType,
+        M: Transport<K>,
+    {
+        while let Some(pairings) = self.next_round() {
+            let builders: Vec<_> = pairings
+                .iter()
+                .map(|players| self.competition.game(players))
+                .collect();
+
+            let games = builders.into_iter().enumerate().map(|(i, builder)| async move {
+                let game = manager.start_game(builder).await;
+                let result = match game {
+                    Some(game) => manager.wait_game(game).await,
+                    None => None,
+                };
+                (i, game, result)
+            });
+            let mut played = stream::iter(games).buffer_unordered(self.max_games);
+
+            while let Some((i, game, result)) = played.next().await {
+                info!(round = self.rounds.len(), game = i, "Tournament game done");
+                let competition = &self.competition;
+                if let Some(pairing) = self.rounds.last_mut().and_then(|r| r.games.get_mut(i)) {
+                    pairing.status = match (game, &result) {
+                        (None, _) => Status::Unplayed,
+                        (Some(_), None) => Status::Errored,
+                        (Some(_), Some(_)) => Status::Played,
+                    };
+                    if pairing.status != Status::Played {
+                        let (players, status) = (&pairing.players, pairing.status);
+                        warn!(?players, ?status, "Tournament game was not played");
+                    }
+                    pairing.winner = result
+                        .as_ref()
+                        .and_then(|result| competition.winner(&pairing.players, result));
+                    pairing.game = game;
+                    pairing.result = result;
+                }
+            }
+        }
+
+        self.standings()
+    }
+
+    /// Best first, on points, then losses, then seed, games that were not played do not count
+    pub fn standings(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = self
+            .players
+            .iter()
+            .map(|&player| Standing {
+                player,
+                points: 0.0,
+                wins: 0,
+                draws: 0,
+                losses: 0,
+                byes: 0,
+            })
+            .collect();
+        let index: HashMap<PlayerId, usize> =
+            self.players.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+
+        for round in &self.rounds {
+            for pairing in round.games.iter().filter(|p| p.status == Status::Played) {
+                let winner = self.advancing(pairing);
+                for player in &pairing.players {
+                    let standing = &mut standings[index[player]];
+                    match winner {
+                        Some(winner) if winner == *player => {
+                            standing.wins += 1;
+                            standing.points += 1.0;
+                        }
+                        Some(_) => standing.losses += 1,
+                        None => {
+                            standing.draws += 1;
+                            standing.points += 0.5;
+                        }
+                    }
+                }
+            }
+
+            for player in &round.byes {
+                let standing = &mut standings[index[player]];
+                standing.byes += 1;
+                if let Format::Swiss { .. } = self.format {
+                    standing.points += 1.0;
+                }
+            }
+        }
+
+        standings.sort_by(|a, b| {
+            b.points
+                .partial_cmp(&a.points)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.losses.cmp(&b.losses))
+                .then(index[&a.player].cmp(&index[&b.player]))
+        });
+        standings
+    }
+
+    /// All rounds so far with their games and results, for exporting the bracket
+    pub fn bracket(&self) -> Value {
+        serde_json::json!({
+            "format": self.format,
+            "players": self.players,
+            "rounds": self.rounds,
+            "standings": self.standings(),
+        })
+    }
+
+    /// The winner of a game, in elimination formats the higher seed wins a draw
+    /// Nobody wins a game that was not played.
+    fn advancing(&self, pairing: &Pairing) -> Option<PlayerId> {
+        if pairing.status != Status::Played {
+            return None;
+        }
+
+        match self.format {
+            Format::SingleElimination | Format::DoubleElimination => {
+                pairing.winner.or_else(|| pairing.players.first().copied())
+            }
+            _ => pairing.winner,
+        }
+    }
+
+    /// Pairs the next round, None when the tournament is over
+    fn next_round(&mut self) -> Option<Vec<Vec<PlayerId>>> {
+        let round = match self.format {
+            Format::RoundRobin => self.round_robin(),
+            Format::Swiss { rounds } if self.rounds.len() < rounds => Some(self.swiss()),
+            Format::Swiss { .. } => None,
+            Format::SingleElimination => self.elimination(1),
+            Format::DoubleElimination => self.elimination(2),
+        }?;
+
+        let pairings = round.games.iter().map(|p| p.players.clone()).collect();
+        self.rounds.push(round);
+        Some(pairings)
+    }
+
+    /// Circle method, the first player stays put while the others rotate
+    fn round_robin(&self) -> Option<Round> {
+        let mut seats: Vec<Option<PlayerId>> = self.players.iter().copied().map(Some).collect();
+        if seats.len() % 2 == 1 {
+            seats.push(None);
+        }
+
+        let r = self.rounds.len();
+        if seats.len() < 2 || r >= seats.len() - 1 {
+            return None;
+        }
+        seats[1..].rotate_right(r);
+
+        let mut round = Round::default();
+        let n = seats.len();
+        for i in 0..n / 2 {
+            match (seats[i], seats[n - 1 - i]) {
+                (Some(a), Some(b)) => round.games.push(Pairing::new(vec![a, b])),
+                (Some(a), None) | (None, Some(a)) => round.byes.push(a),
+                (None, None) => {}
+            }
+        }
+        Some(round)
+    }
+
+    /// Pairs players on the standings, avoiding rematches where it can
+    fn swiss(&self) -> Round {
+        let mut ranked: Vec<PlayerId> = self.standings().iter().map(|s| s.player).collect();
+        let mut round = Round::default();
+
+        if ranked.len() % 2 == 1 {
+            let had_bye: HashSet<PlayerId> =
+                self.rounds.iter().flat_map(|r| r.byes.iter().copied()).collect();
+            let bye = ranked
+                .iter()
+                .rposition(|id| !had_bye.contains(id))
+                .unwrap_or(ranked.len() - 1);
+            round.byes.push(ranked.remove(bye));
+        }
+
+        let played: HashSet<(PlayerId, PlayerId)> = self
+            .rounds
+            .iter()
+            .flat_map(|r| r.games.iter())
+            .filter(|p| p.players.len() == 2)
+            .flat_map(|p| {
+                let (a, b) = (p.players[0], p.players[1]);
+                vec![(a, b), (b, a)]
+            })
+            .collect();
+
+        match pair_up(&ranked, &played) {
+            Some(pairs) => {
+                for (a, b) in pairs {
+                    round.games.push(Pairing::new(vec![a, b]));
+                }
+            }
+            None => {
+                // Rematches can not be avoided, keep them to the lower ranks
+                while !ranked.is_empty() {
+                    let a = ranked.remove(0);
+                    let opponent = ranked
+                        .iter()
+                        .position(|&b| !played.contains(&(a, b)))
+                        .unwrap_or(0);
+                    let b = ranked.remove(opponent);
+                    round.games.push(Pairing::new(vec![a, b]));
+                }
+            }
+        }
+        round
+    }
+
+    ///
+    /// Players meet others with as many losses, the best seed against the worst
+    /// The rounds are reseeded every time, lone players wait with a bye.
+    /// When nobody has an opponent with as many losses, the last ones play each other.
+    ///
+    fn elimination(&self, lives: u32) -> Option<Round> {
+        let mut losses: HashMap<PlayerId, u32> = self.players.iter().map(|&id| (id, 0)).collect();
+        for pairing in self.rounds.iter().flat_map(|r| r.games.iter()) {
+            let winner = self.advancing(pairing);
+            for player in &pairing.players {
+                if Some(*player) != winner {
+                    *losses.entry(*player).or_default() += 1;
+                }
+            }
+        }
+
+        let alive: Vec<PlayerId> = self
+            .players
+            .iter()
+            .copied()
+            .filter(|id| losses[id] < lives)
+            .collect();
+        if alive.len() < 2 {
+            return None;
+        }
+
+        let mut groups: Vec<Vec<PlayerId>> = (0..lives)
+            .map(|l| alive.iter().copied().filter(|id| losses[id] == l).collect())
+            .filter(|group: &Vec<PlayerId>| !group.is_empty())
+            .collect();
+        if groups.iter().all(|group| group.len() == 1) {
+            groups = vec![alive];
+        }
+
+        let mut round = Round::default();
+        for mut group in groups {
+            if group.len() % 2 == 1 {
+                round.byes.push(group.remove(0));
+            }
+            let n = group.len();
+            for i in 0..n / 2 {
+                round
+                    .games
+                    .push(Pairing::new(vec![group[i], group[n - 1 - i]]));
+            }
+        }
+        Some(round)
+    }
+}
+
+///
+/// Pairs ranked players from the top, every player with the best ranked one they did
+/// not play yet. Backtracks when that leaves players that all met, None when every
+/// pairing has a rematch.
+///
+fn pair_up(
+    ranked: &[PlayerId],
+    played: &HashSet<(PlayerId, PlayerId)>,
+) -> Option<Vec<(PlayerId, PlayerId)>> {
+    let (&a, rest) = match ranked.split_first() {
+        Some(split) => split,
+        None => return Some(Vec::new()),
+    };
+
+    for (i, &b) in rest.iter().enumerate() {
+        if played.contains(&(a, b)) {
+            continue;
+        }
+        let mut others = rest.to_vec();
+        others.remove(i);
+        if let Some(mut pairs) = pair_up(&others, played) {
+            pairs.insert(0, (a, b));
+            return Some(pairs);
+        }
+    }
+    None
+}
+
+impl Pairing {
+    fn new(players: Vec<PlayerId>) -> Self {
+        Self {
+            players,
+            status: Status::Pending,
+            game: None,
+            result: None,
+            winner: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generic::*;
+    use crate::modules::game::harness;
+
+    use futures::executor::{block_on, ThreadPool};
+
+    use std::collections::HashMap;
+
+    /// Plays every round without a Manager, the best seed always wins
+    fn play_out<C>(tournament: &mut Tournament<C>) {
+        while tournament.next_round().is_some() {
+            let round = tournament.rounds.last_mut().unwrap();
+            for pairing in &mut round.games {
+                pairing.status = Status::Played;
+                pairing.winner = pairing.players.iter().copied().min();
+            }
+        }
+    }
+
+    #[test]
+    fn round_robin_meets_everybody_once() {
+        let mut tournament = Tournament::new(Format::RoundRobin, vec![1, 2, 3, 4, 5], ());
+        play_out(&mut tournament);
+
+        let mut met: Vec<_> = tournament
+            .rounds
+            .iter()
+            .flat_map(|r| r.games.iter())
+            .map(|p| (p.players[0].min(p.players[1]), p.players[0].max(p.players[1])))
+            .collect();
+        met.sort_unstable();
+        met.dedup();
+
+        assert_eq!(tournament.rounds.len(), 5);
+        assert_eq!(met.len(), 10);
+        assert!(tournament.rounds.iter().all(|r| r.byes.len() == 1));
+
+        let points: Vec<_> = tournament.standings().iter().map(|s| s.points).collect();
+        assert_eq!(points, vec![4.0, 3.0, 2.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn double_elimination_needs_two_losses() {
+        let mut tournament = Tournament::new(Format::DoubleElimination, vec![1, 2, 3, 4], ());
+        play_out(&mut tournament);
+
+        let standings = tournament.standings();
+        let order: Vec<_> = standings.iter().map(|s| s.player).collect();
+        assert_eq!(order, vec![1, 2, 3, 4]);
+        assert_eq!(standings[0].losses, 0);
+        assert!(standings[1..].iter().all(|s| s.losses == 2));
+
+        let bracket = tournament.bracket();
+        assert_eq!(bracket["rounds"].as_array().map(Vec::len), Some(tournament.rounds.len()));
+    }
+
+    #[test]
+    fn swiss_avoids_rematches() {
+        let mut tournament = Tournament::new(Format::Swiss { rounds: 3 }, vec![1, 2, 3, 4, 5], ());
+        play_out(&mut tournament);
+
+        let mut met: Vec<_> = tournament
+            .rounds
+            .iter()
+            .flat_map(|r| r.games.iter())
+            .map(|p| (p.players[0].min(p.players[1]), p.players[0].max(p.players[1])))
+            .collect();
+        let games = met.len();
+        met.sort_unstable();
+        met.dedup();
+
+        let mut byes: Vec<_> = tournament.rounds.iter().flat_map(|r| r.byes.clone()).collect();
+        byes.sort_unstable();
+        byes.dedup();
+
+        assert_eq!(tournament.rounds.len(), 3);
+        assert_eq!((games, met.len()), (6, 6));
+        assert_eq!(byes.len(), 3);
+        assert_eq!(tournament.standings()[0].player, 1);
+    }
+
+    #[test]
+    fn single_elimination_gives_byes_to_the_best_seeds() {
+        let mut tournament = Tournament::new(Format::SingleElimination, vec![1, 2, 3, 4, 5], ());
+        play_out(&mut tournament);
+
+        let rounds: Vec<_> = tournament
+            .rounds
+            .iter()
+            .map(|r| (r.games.iter().map(|p| p.players.clone()).collect(), r.byes.clone()))
+            .collect();
+        let expected: Vec<(Vec<Vec<PlayerId>>, Vec<PlayerId>)> = vec![
+            (vec![vec![2, 5], vec![3, 4]], vec![1]),
+            (vec![vec![2, 3]], vec![1]),
+            (vec![vec![1, 2]], vec![]),
+        ];
+        assert_eq!(rounds, expected);
+
+        let losses: HashMap<_, _> =
+            tournament.standings().iter().map(|s| (s.player, s.losses)).collect();
+        assert_eq!(losses[&1], 0);
+        assert!((2..=5).all(|player| losses[&player] == 1));
+    }
+
+    /// The lowest id wins, games with player 3 crash
+    struct LowestWins;
+    impl Competition for LowestWins {
+        fn game(&mut self, players: &[PlayerId]) -> BoxedBuilder {
+            if players.contains(&3) {
+                harness::crash()
+            } else {
+                harness::lowest_wins(players.to_vec())
+            }
+        }
+
+        fn winner(&self, _: &[PlayerId], result: &Value) -> Option<PlayerId> {
+            result["winner"].as_u64()
+        }
+    }
+
+    #[test]
+    fn runs_through_a_manager() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::new(pool);
+
+        let (cm_id, logger_id) = harness::sinks(&broker);
+        let manager = Manager::new(broker, ReactorID::rand(), cm_id, logger_id);
+
+        let mut tournament =
+            Tournament::new(Format::RoundRobin, vec![1, 2, 3], LowestWins).max_games(1);
+        let standings = block_on(tournament.run(&manager));
+
+        let statuses: Vec<_> = tournament
+            .rounds
+            .iter()
+            .flat_map(|r| r.games.iter())
+            .map(|p| (p.players.clone(), p.status))
+            .collect();
+        assert_eq!(statuses.len(), 3);
+        for (players, status) in statuses {
+            let expected = if players.contains(&3) {
+                Status::Errored
+            } else {
+                Status::Played
+            };
+            assert_eq!(status, expected, "game of {:?}", players);
+        }
+
+        // The crashed games count for nobody, so player 3 has no losses
+        let records: Vec<_> = standings
+            .iter()
+            .map(|s| (s.player, s.wins, s.draws, s.losses))
+            .collect();
+        assert_eq!(records, vec![(1, 1, 0, 0), (3, 0, 0, 0), (2, 0, 0, 1)]);
+    }
+}
@@ -1,23 +1,24 @@
 use futures::channel::mpsc;
-use futures::executor::ThreadPool;
+use futures::future::BoxFuture;
 use futures::stream::StreamExt;
 use futures::{future, FutureExt};
 
 use std::collections::HashMap;
+use std::fmt::Debug;
 use std::hash::Hash;
 
 use crate::generic::*;
 
 use std::marker::PhantomData;
 struct Helper<T, K1, M1, K2, M2> {
-    pd: PhantomData<(T, K2, M2)>,
-    f: Box<dyn Fn(&K1, &M1) -> Option<T> + Send + Sync + 'static>,
+    pd: PhantomData<fn() -> (T, K2, M2)>,
+    f: Box<dyn Fn(&K1, &mut M1) -> Option<T> + Send + Sync + 'static>,
 }
 
 impl<T, K1, M1, K2, M2> Helper<T, K1, M1, K2, M2> {
     fn new<F>(f: F) -> Self
     where
-        F: Fn(&K1, &M1) -> Option<T> + 'static + Send + Sync,
+        F: Fn(&K1, &mut M1) -> Option<T> + 'static + Send + Sync,
     {
         Self {
             f: Box::new(f),
@@ -31,7 +32,7 @@ trait HelperHandler<K1, M1, K2, M2> {
         &self,
         origin: ReactorID,
         k: &K1,
-        m: &M1,
+        m: &mut M1,
         handler: &SenderHandle<K2, M2>,
     ) -> Option<()>;
 }
@@ -40,15 +41,15 @@ impl<T, K1, M1, K2, M2> HelperHandler<K1, M1, K2, M2> for Helper<T, K1, M1, K2,
 where
     K1: Hash + Eq + Send + Sync + 'static + Unpin,
     K2: Hash + Eq + Send + Sync + 'static + Unpin,
-    M1: Send + Sync + 'static,
-    M2: Send + Sync + 'static,
+    M1: Send + 'static,
+    M2: Send + 'static,
     T: IntoMessage<K2, M2>,
 {
     fn handle(
         &self,
         origin: ReactorID,
         k: &K1,
-        m: &M1,
+        m: &mut M1,
         handler: &SenderHandle<K2, M2>,
     ) -> Option<()> {
         if let Some(t) = (self.f)(k, m) {
@@ -66,6 +67,11 @@ enum InnerMsg<K, M> {
 
 type SendF<K, M> = Box<dyn Fn(&SenderHandle<K, M>) -> ()>;
 
+/// Given the channel to the real reactor, returns the channel and future of the reactor-like
+/// that stands in for it
+pub type Attach<K, M> =
+    Box<dyn FnOnce(SenderHandle<K, M>) -> (Sender<K, M>, BoxFuture<'static, ()>) + Send>;
+
 pub struct Translator<K1, K2, M1, M2> {
     to: Option<(
         mpsc::UnboundedReceiver<InnerMsg<K1, M1>>,
@@ -83,10 +89,10 @@ pub struct Translator<K1, K2, M1, M2> {
 use std::marker::Unpin;
 impl<K1, K2, M1, M2> Translator<K1, K2, M1, M2>
 where
-    K1: Hash + Eq + Send + Sync + 'static + Unpin,
-    K2: Hash + Eq + Send + Sync + 'static + Unpin,
-    M1: Send + Sync + 'static,
-    M2: Send + Sync + 'static,
+    K1: Hash + Eq + Debug + Send + Sync + 'static + Unpin,
+    K2: Hash + Eq + Debug + Send + Sync + 'static + Unpin,
+    M1: Send + 'static,
+    M2: Send + 'static,
 {
     pub fn new() -> Self {
         let (s1, r1) = mpsc::unbounded();
@@ -102,7 +108,7 @@ where
 
     pub fn add_to<F, T: IntoMessage<K2, M2> + Send + Sync + 'static>(&mut self, key: K1, f: F)
     where
-        F: Fn(&K1, &M1) -> Option<T> + Send + Sync + 'static,
+        F: Fn(&K1, &mut M1) -> Option<T> + Send + Sync + 'static,
     {
         self.to
             .as_mut()
@@ -111,84 +117,76 @@ where
 
     pub fn add_from<F, T: IntoMessage<K1, M1> + Send + Sync + 'static>(&mut self, key: K2, f: F)
     where
-        F: Fn(&K2, &M2) -> Option<T> + Send + Sync + 'static,
+        F: Fn(&K2, &mut M2) -> Option<T> + Send + Sync + 'static,
     {
         self.from
             .as_mut()
             .map(|(_, map)| map.insert(key, Box::new(Helper::new(f))));
     }
 
+    /// The reactor-like passes messages and closes on to the other side, until either side closes
     fn attach(
         origin: ReactorID,
-        pool: ThreadPool,
         rec: mpsc::UnboundedReceiver<InnerMsg<K2, M2>>,
         s2: mpsc::UnboundedSender<InnerMsg<K1, M1>>,
         map: HashMap<K2, Box<dyn HelperHandler<K2, M2, K1, M1> + Send + Sync>>,
-    ) -> Box<dyn FnOnce(SenderHandle<K1, M1>) -> Sender<K1, M1> + Send> {
+    ) -> Attach<K1, M1> {
         Box::new(move |sender| {
             let (tx, rx) = mpsc::unbounded();
 
-            let rx = receiver_handle(rx);
-
-            // translate msg from rx to something and send it to s2
-            pool.spawn_ok(
-                rx.map(move |item| {
-                    if let Some((_, k, m)) = item {
-                        InnerMsg::Msg(k, m)
-                    } else {
-                        InnerMsg::Close()
+            // pass msg from rx through to s2, to be translated on the other side
+            let incoming = async move {
+                let mut rx = receiver_handle(rx).boxed();
+                while let Some(item) = rx.next().await {
+                    let (item, closed) = match item {
+                        Some((_, k, m)) => (InnerMsg::Msg(k, m), false),
+                        None => (InnerMsg::Close(), true),
+                    };
+
+                    if s2.unbounded_send(item).is_err() || closed {
+                        break;
                     }
-                })
-                .for_each(move |item| {
-                    s2.unbounded_send(item).expect("Send failed");
-                    future::ready(())
-                })
-                .boxed(),
-            );
-
-            // pass msg through from rec to sender
-            pool.spawn_ok(
-                rec.for_each(move |item| {
-                    if match item {
-                        InnerMsg::Close() => sender.close(origin),
-                        InnerMsg::Msg(k, m) => {
+                }
+            };
+
+            // translate msg from rec and send it to sender
+            let outgoing = async move {
+                let mut rec = rec;
+                while let Some(item) = rec.next().await {
+                    match item {
+                        InnerMsg::Close() => {
+                            sender.close(origin);
+                            break;
+                        }
+                        InnerMsg::Msg(k, mut m) => {
                             if let Some(helper) = map.get(&k) {
-                                helper.handle(origin, &k, &m, &sender)
+                                if helper.handle(origin, &k, &mut m, &sender).is_none() {
+                                    trace!("Couldn't send message");
+                                }
                             } else {
-                                Some(())
+                                warn!("No translation registered for key {:?}, dropping it", k);
                             }
                         }
                     }
-                    .is_none()
-                    {
-                        trace!("Couldn't send message");
-                    }
-                    future::ready(())
-                })
-                .boxed(),
-            );
+                }
+            };
 
-            tx
+            let fut = future::select(incoming.boxed(), outgoing.boxed()).map(|_| ());
+            (tx, fut.boxed())
         })
     }
 
-    pub fn attach_to(
-        &mut self,
-        origin: ReactorID,
-        pool: ThreadPool,
-    ) -> Box<dyn FnOnce(SenderHandle<K1, M1>) -> Sender<K1, M1> + Send> {
+    /// Attach the reactor-like on the first broker, standing in for origin on the second
+    pub fn attach_to(&mut self, origin: ReactorID) -> Attach<K1, M1> {
         let (rec, map) = std::mem::replace(&mut self.from, None).unwrap();
 
-        Translator::<K1, K2, M1, M2>::attach(origin, pool, rec, self.s1.clone(), map)
+        Translator::<K1, K2, M1, M2>::attach(origin, rec, self.s1.clone(), map)
     }
 
-    pub fn attach_from(
-        &mut self,
-        origin: ReactorID,
-        pool: ThreadPool,
-    ) -> Box<dyn FnOnce(SenderHandle<K2, M2>) -> Sender<K2, M2> + Send> {
+    /// Attach the reactor-like on the second broker, standing in for origin on the first
+    pub fn attach_from(&mut self, origin: ReactorID) -> Attach<K2, M2> {
         let (rec, map) = std::mem::replace(&mut self.to, None).unwrap();
 
-        Translator::<K2, K1, M2, M1>::attach(origin, pool, rec, self.s2.clone(), map)
+        Translator::<K2, K1, M2, M1>::attach(origin, rec, self.s2.clone(), map)
     }
 }
@@ -0,0 +1,282 @@
+use super::types::{Data, GameProtocol, HostMsg, PlayerId, PlayerMsg, Start, Turn};
+use super::Transport;
+use crate::generic::*;
+use crate::util::request::*;
+
+use futures::channel::mpsc;
+use futures::future::{self, FutureExt};
+use futures::StreamExt;
+
+use async_std::task::sleep;
+
+use serde::{Deserialize, Serialize};
+
+use std::any;
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Starts the timer for this turn
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
+pub struct TurnStarted(pub u64);
+
+/// This turn took too long
+#[derive(Serialize, Deserialize, Clone, Key, Debug)]
+pub struct TurnTimeOut(pub u64);
+
+/// What happens with a move from a player that is not to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutOfTurn {
+    Drop,
+    /// Used as soon as it is that player's turn
+    Queue,
+}
+
+///
+/// Lock for games that take turns, sitting where a StepLock would
+/// Only players the Controller says are to move get through, see Controller::turn.
+/// The turn is flushed to the host when all of them moved or the timeout hit,
+/// players that did not move get a PlayerMsg without data.
+///
+pub struct TurnLock<K = any::TypeId, M = Message> {
+    pd: PhantomData<fn() -> (K, M)>,
+    turn: u64,
+    moves: HashMap<PlayerId, Option<Data>>,
+    queued: HashMap<PlayerId, VecDeque<Data>>,
+    out_of_turn: OutOfTurn,
+    host: ReactorID,
+    player_id: ReactorID,
+    timer_id: ReactorID,
+    timeout: Option<Duration>,
+}
+
+impl<K, M> Clone for TurnLock<K, M> {
+    fn clone(&self) -> Self {
+        Self {
+            pd: PhantomData,
+            turn: self.turn,
+            moves: self.moves.clone(),
+            queued: self.queued.clone(),
+            out_of_turn: self.out_of_turn,
+            host: self.host,
+            player_id: self.player_id,
+            timer_id: self.timer_id,
+            timeout: self.timeout,
+        }
+    }
+}
+
+#[handlers(key = "K", message = "M", params = "into_params")]
+impl<K: KeyType, M: Transport<K>> TurnLock<K, M> {
+    pub fn new() -> Self {
+        Self {
+            pd: PhantomData,
+            turn: 0,
+            moves: HashMap::new(),
+            queued: HashMap::new(),
+            out_of_turn: OutOfTurn::Drop,
+            host: 0.into(),
+            player_id: 0.into(),
+            timer_id: 0.into(),
+            timeout: None,
+        }
+    }
+
+    /// Time a player gets for its turn
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn out_of_turn(mut self, out_of_turn: OutOfTurn) -> Self {
+        self.out_of_turn = out_of_turn;
+        self
+    }
+
+    pub fn params(mut self, host: ReactorID, player_id: ReactorID) -> CoreParams<Self, K, M> {
+        self.host = host;
+        self.player_id = player_id;
+        self.into_params()
+    }
+
+    #[handler]
+    fn player_msg(&mut self, handle: &mut ReactorHandle<K, M>, e: PlayerMsg) {
+        let data = match e.data {
+            Some(data) => data,
+            None => return,
+        };
+
+        match self.moves.get_mut(&e.id) {
+            Some(slot @ None) => {
+                *slot = Some(data);
+                self.maybe_flush(handle);
+            }
+            _ if self.out_of_turn == OutOfTurn::Queue => {
+                trace!(player = e.id, "Queueing move out of turn");
+                self.queued.entry(e.id).or_default().push_back(data);
+            }
+            _ => info!(player = e.id, "Dropping move out of turn"),
+        }
+    }
+
+    /// The next turn starts, with queued moves if there are any
+    #[handler]
+    fn turn(&mut self, handle: &mut ReactorHandle<K, M>, turn: &Turn) {
+        self.turn += 1;
+
+        let queued = &mut self.queued;
+        self.moves = turn
+            .players
+            .iter()
+            .map(|id| (*id, queued.get_mut(id).and_then(VecDeque::pop_front)))
+            .collect();
+
+        if self.timeout.is_some() {
+            handle.send_internal(TurnStarted(self.turn), TargetReactor::Link(self.timer_id));
+        }
+
+        self.maybe_flush(handle);
+    }
+
+    #[handler]
+    fn host_msg(&mut self, handle: &mut ReactorHandle<K, M>, m: &HostMsg) {
+        if let HostMsg::Kick(id) = m {
+            self.queued.remove(id);
+            if self.moves.remove(id).is_some() {
+                self.maybe_flush(handle);
+            }
+        }
+    }
+
+    /// Only the players of this turn time out, stale timeouts are ignored
+    #[handler]
+    fn timeout(&mut self, handle: &mut ReactorHandle<K, M>, e: &TurnTimeOut) {
+        if e.0 == self.turn && !self.moves.is_empty() {
+            info!(turn = self.turn, "Turn timed out");
+            self.flush(handle);
+        }
+    }
+
+    #[init]
+    fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, K, M>) {
+        // Open link to host
+        let host_link_params = GameProtocol::players(())
+            .route::<HostMsg>(TargetReactor::All)
+            .route::<Req<State>>(TargetReactor::Link(self.player_id));
+        handle.open_link(self.host, host_link_params, true);
+
+        // Open link to client
+        let client_link_params = GameProtocol::host(())
+            .route::<Start>(TargetReactor::Link(self.host))
+            .route::<Res<State>>(TargetReactor::Link(self.host));
+        handle.open_link(self.player_id, client_link_params, true);
+
+        if let Some(timeout) = self.timeout {
+            self.start_timer(handle, timeout);
+        }
+    }
+
+    /// Reactor-like that times out every turn it is told about
+    fn start_timer<'a>(&mut self, handle: &mut ReactorHandle<'a, K, M>, timeout: Duration) {
+        self.timer_id = ReactorID::rand();
+        let timer_id = self.timer_id;
+        let (tx, rx) = mpsc::unbounded();
+        let self_send_f = handle.chan();
+
+        let timer_params = LinkParams::new(())
+            .internal_handler(i_to_e::<(), TurnStarted, K, M>())
+            .external_handler(e_to_i::<(), TurnTimeOut, K, M>(TargetReactor::Reactor));
+        handle.open_link(timer_id, timer_params, true);
+
+        let fut = async move {
+            let mut rx = receiver_handle(rx).boxed().fuse();
+            let mut turn = None;
+
+            loop {
+                let mut timer = match turn {
+                    Some(_) => sleep(timeout).boxed().fuse(),
+                    None => future::pending().boxed().fuse(),
+                };
+
+                select! {
+                    v = rx.next() => match v? {
+                        None => break,
+                        Some((_, key, mut msg)) => {
+                            turn = TurnStarted::from_msg(&key, &mut msg).map(|t| t.0);
+                        }
+                    },
+                    _ = timer => {
+                        self_send_f.send(timer_id, TurnTimeOut(turn.take()?))?;
+                    }
+                }
+            }
+
+            Some(())
+        }
+        .map(|_| ());
+
+        handle.open_reactor_like(timer_id, tx, fut, "Turn Timer");
+    }
+
+    fn maybe_flush(&mut self, handle: &mut ReactorHandle<K, M>) {
+        if !self.moves.is_empty() && self.moves.values().all(Option::is_some) {
+            self.flush(handle);
+        }
+    }
+
+    /// Sends this turn to the host, nobody is to move until the next Turn
+    fn flush(&mut self, handle: &mut ReactorHandle<K, M>) {
+        let player_msgs: Vec<PlayerMsg> = self
+            .moves
+            .drain()
+            .map(|(id, data)| PlayerMsg { id, data })
+            .collect();
+        handle.send_internal(player_msgs, TargetReactor::Link(self.host));
+    }
+}
+
+impl<K: KeyType, M: Transport<K>> Default for TurnLock<K, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::lock_harness::{self, Event, Script};
+
+    #[test]
+    fn only_the_active_player_moves() {
+        let script = Script {
+            turns: vec![vec![1], vec![1], vec![2]],
+            start: true,
+            moves: vec![(2, "early"), (1, "first")],
+            ..Script::default()
+        };
+        let mut rx = lock_harness::spawn(script, |_, host, players| {
+            TurnLock::new()
+                .out_of_turn(OutOfTurn::Queue)
+                .with_timeout(Duration::from_millis(50))
+                .params(host, players)
+        });
+
+        let mut steps = Vec::new();
+        while steps.len() < 3 {
+            let step = lock_harness::wait(&mut rx, |e| match e {
+                Event::Step(moves) => Some(moves),
+                _ => None,
+            });
+            steps.push(step);
+        }
+
+        // Player 2 moved early, that move waits until it is their turn
+        // Player 1 has nothing left for the second turn and times out
+        let expected = vec![
+            vec![(1, Some(String::from("first")))],
+            vec![(1, None)],
+            vec![(2, Some(String::from("early")))],
+        ];
+        assert_eq!(steps, expected);
+    }
+}
@@ -1,25 +1,10 @@
-use serde::{Deserialize, Serialize};
+use crate::util::request::{Req, Res, State};
 
 pub type PlayerId = u64;
 pub type DataType = String;
 
-#[derive(Serialize, Deserialize, Clone, Key, Debug)]
-pub struct PlayerMsg {
-    pub id: PlayerId,
-    pub data: Option<Data>,
-}
-
-// #[derive(Serialize, Deserialize, Clone, Key, Debug)]
-// pub struct HostData {
-//     pub value: String,
-//     pub target: Option<PlayerId>,
-// }
-
-#[derive(Serialize, Deserialize, Clone, Key, Debug)]
-pub enum HostMsg {
-    Data(Data, Option<PlayerId>),
-    Kick(PlayerId),
-}
+// PlayerMsg, HostMsg, Data, Close, Start, Turn, TimedOut and TimeLeft, from schema/mozaic/messages.toml
+include!(concat!(env!("OUT_DIR"), "/messages.rs"));
 
 impl HostMsg {
     pub fn new(value: DataType, player: Option<PlayerId>) -> Self {
@@ -31,14 +16,8 @@ impl HostMsg {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Key, Debug)]
-pub struct Data {
-    pub value: DataType,
-}
-
-#[derive(Serialize, Deserialize, Clone, Key, Debug)]
-pub struct Close {}
-#[derive(Serialize, Deserialize, Clone, Key, Debug)]
-pub struct Start {
-    pub players: Vec<(PlayerId, String)>
-}
+/// The link between the players (Aggregator or a lock) and the host (a lock or Runner)
+#[derive(LinkProtocol)]
+#[side(players: PlayerMsg, Vec<PlayerMsg>, Res<State>, Start, TimedOut, TimeLeft)]
+#[side(host: HostMsg, Req<State>, Turn)]
+pub struct GameProtocol;
@@ -0,0 +1,132 @@
+use crate::generic::*;
+use crate::modules::logger::{start_handler, LogHandler};
+
+use futures::channel::mpsc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One message seen on a tapped link
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Tapped {
+    pub source: ReactorID,
+    pub target: ReactorID,
+    pub direction: Direction,
+    pub type_name: String,
+    /// None for messages that are not serialized, like Message
+    pub body: Option<Value>,
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+}
+
+///
+/// Mirrors every message on the link from source to target to handler
+/// The link keeps running as before, handler runs on the runtime of the broker.
+/// Tapping a link again replaces the previous tap.
+///
+pub fn tap<K, M, H>(broker: &BrokerHandle<K, M>, source: ReactorID, target: ReactorID, handler: H)
+where
+    M: Inspect,
+    H: LogHandler<Tapped> + Send + 'static,
+{
+    let (tx, rx) = mpsc::unbounded();
+    broker.runtime().spawn(start_handler(handler, rx));
+
+    let tap = move |_: &K, msg: &M, direction| {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        let tapped = Tapped {
+            source,
+            target,
+            direction,
+            type_name: msg.type_name().to_string(),
+            body: msg.body(),
+            timestamp,
+        };
+
+        if tx.unbounded_send(tapped).is_err() {
+            trace!("Wiretap handler is gone");
+        }
+    };
+
+    broker.tap(source, target, Some(Box::new(tap)));
+}
+
+/// Removes the tap from the link from source to target
+pub fn untap<K, M>(broker: &BrokerHandle<K, M>, source: ReactorID, target: ReactorID) {
+    broker.tap(source, target, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::logger::BoxFuture;
+    use crate::modules::types::Data;
+
+    use futures::executor::{block_on, ThreadPool};
+    use futures::stream::StreamExt;
+
+    struct Sink(mpsc::UnboundedSender<Tapped>);
+    impl LogHandler<Tapped> for Sink {
+        fn handle<'a>(&'a mut self, log: Tapped) -> BoxFuture<'a> {
+            let _ = self.0.unbounded_send(log);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    struct Sender(ReactorID);
+    impl ReactorState<String, JSONMessage> for Sender {
+        const NAME: &'static str = "Sender";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, String, JSONMessage>) {
+            let params = LinkParams::new(()).internal_handler(i_to_e::<(), Data, _, _>());
+            handle.open_link(self.0, params, false);
+            handle.send_internal(
+                Data {
+                    value: String::from("move"),
+                },
+                TargetReactor::Links,
+            );
+        }
+    }
+
+    struct Receiver(ReactorID);
+    impl ReactorState<String, JSONMessage> for Receiver {
+        const NAME: &'static str = "Receiver";
+
+        fn init<'a>(&mut self, handle: &mut ReactorHandle<'a, String, JSONMessage>) {
+            handle.open_link(self.0, LinkParams::new(()), false);
+        }
+    }
+
+    #[test]
+    fn taps_both_sides() {
+        let pool = ThreadPool::new().unwrap();
+        let (broker, _handle) = BrokerHandle::<String, JSONMessage>::new(pool);
+
+        let sender_id = ReactorID::rand();
+        let receiver_id = ReactorID::rand();
+
+        // Taps are queued before the reactors spawn, so they see the first message
+        let (tx, rx) = mpsc::unbounded();
+        tap(&broker, sender_id, receiver_id, Sink(tx.clone()));
+        tap(&broker, receiver_id, sender_id, Sink(tx));
+
+        broker.spawn(CoreParams::new(Receiver(sender_id)), Some(receiver_id));
+        broker.spawn(CoreParams::new(Sender(receiver_id)), Some(sender_id));
+
+        let mut tapped = block_on(rx.take(2).collect::<Vec<_>>());
+        tapped.sort_by_key(|t| t.source == receiver_id);
+
+        assert_eq!(tapped[0].direction, Direction::Internal);
+        assert_eq!(tapped[1].direction, Direction::External);
+        for t in &tapped {
+            assert_eq!(t.type_name, <Data as Key<String>>::key());
+            assert_eq!(t.body, Some(serde_json::json!({ "value": "move" })));
+        }
+    }
+}
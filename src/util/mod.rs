@@ -1,8 +1,11 @@
 pub mod request {
+    use crate::generic::Key;
+
     use rand;
+    use serde::{Deserialize, Serialize};
     use std::hash::Hash;
 
-    #[derive(Clone, Debug, Copy, Hash, Eq, PartialEq, PartialOrd)]
+    #[derive(Serialize, Deserialize, Clone, Debug, Copy, Hash, Eq, PartialEq, PartialOrd)]
     pub struct UUID(u64);
     impl UUID {
         pub fn new() -> Self {
@@ -15,7 +18,7 @@ pub mod request {
         }
     }
 
-    #[derive(Clone, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct Req<T>(pub UUID, pub T);
     impl<T> Req<T> {
         pub fn new(t: T) -> Self {
@@ -31,7 +34,17 @@ pub mod request {
         }
     }
 
-    #[derive(Clone, Debug)]
+    impl<T: Key<String>> Key<String> for Req<T> {
+        fn key() -> String {
+            format!("req<{}>", T::key())
+        }
+
+        fn version() -> u32 {
+            T::version()
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct Res<T>(pub UUID, pub T);
     impl<T> Res<T> {
         pub fn new<U: Into<UUID>>(uuid: U, t: T) -> Self {
@@ -44,9 +57,19 @@ pub mod request {
         }
     }
 
-    #[derive(Default, Clone, Debug)]
+    impl<T: Key<String>> Key<String> for Res<T> {
+        fn key() -> String {
+            format!("res<{}>", T::key())
+        }
+
+        fn version() -> u32 {
+            T::version()
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Default, Clone, Key, Debug)]
     pub struct Kill;
-    #[derive(Clone, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Key, Debug)]
     pub enum State {
         Request,
         Response(Vec<Connect>),
@@ -68,7 +91,7 @@ pub mod request {
 
     use crate::modules::types::PlayerId;
 
-    #[derive(Clone, Debug)]
+    #[derive(Serialize, Deserialize, Clone, Key, Debug)]
     pub enum Connect {
         Connected(PlayerId, String),
         Reconnecting(PlayerId, String),
@@ -0,0 +1,6 @@
+#[test]
+fn handlers_macro() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/handlers/pass.rs");
+    t.compile_fail("tests/ui/handlers/fail-*.rs");
+}
@@ -0,0 +1,6 @@
+#[test]
+fn link_protocol_derive() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/protocol/pass.rs");
+    t.compile_fail("tests/ui/protocol/fail-*.rs");
+}
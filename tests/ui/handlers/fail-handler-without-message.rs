@@ -0,0 +1,17 @@
+// The failed expansion drops the impl, so nothing uses these imports
+#![allow(unused_imports)]
+
+use mozaic::generic::{Message, ReactorHandle};
+use mozaic_derive::handlers;
+
+use std::any::TypeId;
+
+struct Counter;
+
+#[handlers(key = "TypeId", message = "Message", params = "into_params")]
+impl Counter {
+    #[handler]
+    fn handle(&mut self, _: &mut ReactorHandle<TypeId, Message>) {}
+}
+
+fn main() {}
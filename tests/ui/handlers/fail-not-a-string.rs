@@ -0,0 +1,8 @@
+use mozaic_derive::handlers;
+
+struct Counter;
+
+#[handlers(name = 5, key = "TypeId", message = "Message", params = "into_params")]
+impl Counter {}
+
+fn main() {}
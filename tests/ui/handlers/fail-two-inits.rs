@@ -0,0 +1,20 @@
+// The failed expansion drops the impl, so nothing uses these imports
+#![allow(unused_imports)]
+
+use mozaic::generic::{Message, ReactorHandle};
+use mozaic_derive::handlers;
+
+use std::any::TypeId;
+
+struct Counter;
+
+#[handlers(key = "TypeId", message = "Message", params = "into_params")]
+impl Counter {
+    #[init]
+    fn init<'a>(&mut self, _: &mut ReactorHandle<'a, TypeId, Message>) {}
+
+    #[init]
+    fn init_again<'a>(&mut self, _: &mut ReactorHandle<'a, TypeId, Message>) {}
+}
+
+fn main() {}
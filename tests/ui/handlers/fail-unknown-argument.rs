@@ -0,0 +1,8 @@
+use mozaic_derive::handlers;
+
+struct Counter;
+
+#[handlers(key = "TypeId", message = "Message", params = "into_params", kind = "reactor")]
+impl Counter {}
+
+fn main() {}
@@ -0,0 +1,31 @@
+use mozaic::generic::*;
+use mozaic_derive::handlers;
+
+use std::any::TypeId;
+
+struct Counter {
+    count: u32,
+}
+
+#[handlers(name = "Counter", key = "TypeId", message = "Message", params = "into_params")]
+impl Counter {
+    #[init]
+    fn init<'a>(&mut self, _handle: &mut ReactorHandle<'a, TypeId, Message>) {
+        self.count = 0;
+    }
+
+    #[handler]
+    fn handle_borrowed(&mut self, _handle: &mut ReactorHandle<TypeId, Message>, by: &u32) {
+        self.count += by;
+    }
+
+    #[handler]
+    fn handle_owned(&mut self, _handle: &mut ReactorHandle<TypeId, Message>, name: String) {
+        self.count += name.len() as u32;
+    }
+}
+
+fn main() {
+    let _params: CoreParams<Counter, TypeId, Message> = Counter { count: 0 }.into_params();
+    assert_eq!(<Counter as ReactorState<TypeId, Message>>::NAME, "Counter");
+}
@@ -0,0 +1,9 @@
+use mozaic_derive::LinkProtocol;
+
+struct Ping;
+
+#[derive(LinkProtocol)]
+#[side(client: Ping)]
+struct PingProtocol;
+
+fn main() {}
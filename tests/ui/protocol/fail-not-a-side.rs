@@ -0,0 +1,11 @@
+use mozaic_derive::LinkProtocol;
+
+struct Ping;
+struct Pong;
+
+#[derive(LinkProtocol)]
+#[side(client Ping)]
+#[side(server: Pong)]
+struct PingProtocol;
+
+fn main() {}
@@ -0,0 +1,20 @@
+use mozaic::generic::{Message, ProtocolParams, SideA, TargetReactor};
+use mozaic_derive::LinkProtocol;
+
+use std::any::TypeId;
+
+#[derive(Clone)]
+struct Ping;
+#[derive(Clone)]
+struct Pong;
+
+#[derive(LinkProtocol)]
+#[side(client: Ping)]
+#[side(server: Pong)]
+struct PingProtocol;
+
+fn main() {
+    // The client sends Ping, only the server receives it
+    let _: ProtocolParams<SideA<PingProtocol>, (), TypeId, Message> =
+        PingProtocol::client(()).route::<Ping>(TargetReactor::Links);
+}
@@ -0,0 +1,12 @@
+use mozaic_derive::LinkProtocol;
+
+struct Ping;
+struct Pong;
+
+#[derive(LinkProtocol)]
+#[side(client: Ping)]
+#[side(server: Pong)]
+#[side(spectator: Ping)]
+struct PingProtocol;
+
+fn main() {}